@@ -1,3 +1,10 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_path_to_error;
+extern crate toml;
+
 pub mod utils;
 
 #[cfg(test)]
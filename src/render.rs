@@ -0,0 +1,241 @@
+extern crate ray_tracing_in_one_weekend;
+
+use std::env;
+use std::path::Path;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use ray_tracing_in_one_weekend::utils::hitable::{Hitable, HitableList};
+use ray_tracing_in_one_weekend::utils::bvh;
+use ray_tracing_in_one_weekend::utils::gltf;
+use ray_tracing_in_one_weekend::utils::material::{Lambertian, Material};
+use ray_tracing_in_one_weekend::utils::vec3::Vec3;
+use ray_tracing_in_one_weekend::utils::render::{available_threads, render, render_parallel, Background, Integrator,
+                                                RenderSettings, Shading};
+use ray_tracing_in_one_weekend::utils::film::ImageFormat;
+use ray_tracing_in_one_weekend::utils::scene::{self, CAMERAS, SCENES};
+use ray_tracing_in_one_weekend::utils::scene_file::SceneFile;
+use ray_tracing_in_one_weekend::utils::tonemap::{DisplayTransform, Encoding, ToneMap};
+
+#[derive(Default)]
+struct Options {
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    max_depth: Option<i32>,
+    min_depth: Option<i32>,
+    seed: Option<u64>,
+    scene: Option<String>,
+    scene_file: Option<String>,
+    camera: Option<String>,
+    output: Option<String>,
+    format: Option<ImageFormat>,
+    background: Option<Background>,
+    tonemap: Option<ToneMap>,
+    exposure: Option<f32>,
+    threads: Option<usize>,
+    integrator: Option<Integrator>,
+    stats: bool,
+}
+
+fn usage() -> String {
+    format!("usage: render [options]\n\n\
+             options:\n    \
+             --width N         image width in pixels (default 200)\n    \
+             --height N        image height in pixels (default 100)\n    \
+             --samples N       samples per pixel (default 100)\n    \
+             --max-depth N     maximum number of bounces (default 50)\n    \
+             --min-depth N     bounces before Russian roulette may end a path (default off)\n    \
+             --seed N          random seed; the same seed gives the same image (default 0)\n    \
+             --scene NAME      one of: {} (default random)\n    \
+             --camera NAME     one of: {}, or the index of a glTF camera (default depends on scene)\n    \
+             --background BG   sky, black or r,g,b (default depends on scene)\n    \
+             --scene-file PATH load the scene, camera and settings from a .toml or .json file, or a\n                      \
+             .gltf or .glb scene seen through its first camera\n    \
+             --output PATH     write the image to PATH instead of stdout\n    \
+             --format FMT      p3, p6, png, png16, pfm, hdr or exr[-half|-float][-zip|-none]\n                      \
+             (default: from the output extension, p3 on stdout)\n    \
+             --tonemap NAME    clamp, reinhard, extended-reinhard[:WHITE], aces or agx (default clamp)\n    \
+             --exposure STOPS  scale the image by 2^STOPS before tone mapping (default 0)\n    \
+             --threads N       number of render threads (default: available cores)\n    \
+             --integrator NAME brute, nee (sample lights at every non-specular hit) or mis (default brute)\n    \
+             --stats           print the number of paths and their average length to stderr\n    \
+             --help            show this message",
+            SCENES.join(", "),
+            CAMERAS.join(", "))
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value.parse()
+         .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => opts.width = Some(parse_value(&arg, args.next())?),
+            "--height" => opts.height = Some(parse_value(&arg, args.next())?),
+            "--samples" => opts.samples = Some(parse_value(&arg, args.next())?),
+            "--max-depth" => opts.max_depth = Some(parse_value(&arg, args.next())?),
+            "--min-depth" => opts.min_depth = Some(parse_value(&arg, args.next())?),
+            "--seed" => opts.seed = Some(parse_value(&arg, args.next())?),
+            "--scene" => opts.scene = Some(parse_value(&arg, args.next())?),
+            "--scene-file" => opts.scene_file = Some(parse_value(&arg, args.next())?),
+            "--camera" => opts.camera = Some(parse_value(&arg, args.next())?),
+            "--output" => opts.output = Some(parse_value(&arg, args.next())?),
+            "--format" => opts.format = Some(parse_value(&arg, args.next())?),
+            "--background" => opts.background = Some(parse_value(&arg, args.next())?),
+            "--tonemap" => opts.tonemap = Some(parse_value(&arg, args.next())?),
+            "--exposure" => opts.exposure = Some(parse_value(&arg, args.next())?),
+            "--threads" => opts.threads = Some(parse_value(&arg, args.next())?),
+            "--integrator" => opts.integrator = Some(parse_value(&arg, args.next())?),
+            "--stats" => opts.stats = true,
+            "--help" | "-h" => {
+                println!("{}", usage());
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if opts.scene.is_some() && opts.scene_file.is_some() {
+        return Err("--scene and --scene-file cannot be used together".to_string());
+    }
+    if opts.width == Some(0) || opts.height == Some(0) || opts.samples == Some(0) || opts.threads == Some(0) {
+        return Err("width, height, samples and threads must be positive".to_string());
+    }
+    Ok(opts)
+}
+
+/// Command line values take precedence over the defaults or the scene file.
+fn apply_overrides(settings: &mut RenderSettings, opts: &Options) {
+    settings.width = opts.width.unwrap_or(settings.width);
+    settings.height = opts.height.unwrap_or(settings.height);
+    settings.samples = opts.samples.unwrap_or(settings.samples);
+    // Russian roulette stays off, whatever the maximum depth, unless a minimum depth is given
+    let roulette = settings.min_depth < settings.max_depth;
+    settings.max_depth = opts.max_depth.unwrap_or(settings.max_depth);
+    settings.min_depth = match opts.min_depth {
+        Some(depth) => depth,
+        None if roulette => settings.min_depth,
+        None => settings.max_depth,
+    };
+    settings.seed = opts.seed.unwrap_or(settings.seed);
+    settings.integrator = opts.integrator.unwrap_or(settings.integrator);
+    if let Some(ref background) = opts.background {
+        settings.background = background.clone();
+    }
+}
+
+fn is_gltf(path: &str) -> bool {
+    let ext = Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    ext.as_deref() == Some("gltf") || ext.as_deref() == Some("glb")
+}
+
+fn run(opts: Options) -> Result<(), String> {
+    let (world, lights, delta_lights, cam, settings): (Box<dyn Hitable>, _, _, _, _) = match opts.scene_file {
+        Some(ref path) if is_gltf(path) => {
+            let grey: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+            let scene = gltf::load(path, grey).map_err(|e| format!("{}: {}", path, e))?;
+            let mut settings = RenderSettings::default();
+            apply_overrides(&mut settings, &opts);
+            let aspect = settings.width as f32 / settings.height as f32;
+            let cam = match opts.camera.as_ref().map(|name| (name, name.parse::<usize>())) {
+                Some((_, Ok(i))) => {
+                    scene.cameras
+                         .get(i)
+                         .ok_or_else(|| format!("{}: no camera {}, the file has {}", path, i, scene.cameras.len()))?
+                         .build(aspect)
+                }
+                Some((name, Err(_))) => {
+                    scene::camera_preset(name, aspect).ok_or_else(|| format!("unknown camera: {}", name))?
+                }
+                None => {
+                    scene.cameras
+                         .first()
+                         .ok_or_else(|| format!("{}: the file has no cameras, pick one with --camera", path))?
+                         .build(aspect)
+                }
+            };
+            (Box::new(scene.mesh), HitableList::new(vec![]), vec![], cam, settings)
+        }
+        Some(ref path) => {
+            let file = SceneFile::load(path).map_err(|e| e.to_string())?;
+            let mut settings = file.settings();
+            apply_overrides(&mut settings, &opts);
+            let aspect = settings.width as f32 / settings.height as f32;
+            let cam = match opts.camera {
+                Some(ref name) => scene::camera_preset(name, aspect).ok_or_else(|| format!("unknown camera: {}", name))?,
+                None => file.camera.build(aspect),
+            };
+            let (world, lights) = file.world_and_lights().map_err(|e| format!("{}: {}", path, e))?;
+            let delta_lights = file.delta_lights().map_err(|e| format!("{}: {}", path, e))?;
+            (bvh::build(world, cam.time0, cam.time1), lights, delta_lights, cam, settings)
+        }
+        None => {
+            let name = opts.scene.clone().unwrap_or_else(|| "random".to_string());
+            let mut settings = RenderSettings::default();
+            apply_overrides(&mut settings, &opts);
+            let scene = scene::by_name(&name, settings.seed).ok_or_else(|| format!("unknown scene: {}", name))?;
+            settings.shading = scene.shading;
+            if opts.background.is_none() {
+                settings.background = scene.background.clone();
+            }
+            let aspect = settings.width as f32 / settings.height as f32;
+            let camera_name = opts.camera.clone().unwrap_or_else(|| scene.camera.to_string());
+            let cam = scene::camera_preset(&camera_name, aspect).ok_or_else(|| format!("unknown camera: {}", camera_name))?;
+            (scene.world, scene.lights, vec![], cam, settings)
+        }
+    };
+
+    let (film, stats) = match opts.threads.unwrap_or_else(available_threads) {
+        1 => render(world.as_ref(), &lights, &delta_lights, &cam, &settings),
+        n => render_parallel(world.as_ref(), &lights, &delta_lights, &cam, &settings, n),
+    };
+    if opts.stats {
+        eprintln!("{} paths, {:.2} bounces on average", stats.paths, stats.average_path_length());
+    }
+
+    // normals are written as they are, everything else is radiance
+    let transform = DisplayTransform {
+        exposure: opts.exposure.unwrap_or(0.),
+        tone_map: opts.tonemap.unwrap_or(ToneMap::Clamp),
+        encoding: if settings.shading == Shading::Normal {
+            Encoding::Linear
+        } else {
+            Encoding::Srgb
+        },
+    };
+    match opts.output {
+        Some(ref path) => {
+            let format = opts.format
+                             .or_else(|| ImageFormat::from_path(path))
+                             .ok_or_else(|| format!("{}: unknown image extension, use --format", path))?;
+            film.write(path, format, &transform).map_err(|e| format!("{}: {}", path, e))
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            film.write_to(&mut out, opts.format.unwrap_or(ImageFormat::P3), &transform)
+                .and_then(|_| out.flush())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn main() {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("render: {}\n\n{}", e, usage());
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(opts) {
+        eprintln!("render: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,54 @@
+use utils::vec3::Vec3;
+use utils::ray::Ray;
+
+#[allow(dead_code)]
+#[derive(Clone, Default, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+#[allow(dead_code)]
+impl Aabb {
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Self { min: a, max: b }
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for a in 0..3 {
+            let inv_d = 1. / r.direction()[a];
+            let mut t0 = (self.min[a] - r.origin()[a]) * inv_d;
+            let mut t1 = (self.max[a] - r.origin()[a]) * inv_d;
+            if inv_d < 0. {
+                ::std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min.clone() + self.max.clone()) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max.clone() - self.min.clone();
+        2. * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+}
+
+pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+    let small = Vec3::new(box0.min.x().min(box1.min.x()),
+                          box0.min.y().min(box1.min.y()),
+                          box0.min.z().min(box1.min.z()));
+    let big = Vec3::new(box0.max.x().max(box1.max.x()),
+                        box0.max.y().max(box1.max.y()),
+                        box0.max.z().max(box1.max.z()));
+    Aabb::new(small, big)
+}
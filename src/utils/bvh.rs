@@ -0,0 +1,203 @@
+use std::cmp::Ordering;
+use utils::aabb::{Aabb, surrounding_box};
+use utils::hitable::{Hitable, HitableList, HitRecord};
+use utils::ray::Ray;
+use utils::random::Sampler;
+
+#[allow(dead_code)]
+pub struct BvhNode {
+    left: Box<dyn Hitable>,
+    right: Option<Box<dyn Hitable>>,
+    bbox: Aabb,
+}
+
+#[allow(dead_code)]
+impl BvhNode {
+    pub fn new(list: HitableList, t0: f32, t1: f32) -> Self {
+        assert!(!list.list.is_empty(), "BvhNode::new called with an empty list");
+        let items = list.list
+                        .into_iter()
+                        .map(|h| {
+                                 let mut bbox = Aabb::default();
+                                 if !h.bounding_box(t0, t1, &mut bbox) {
+                                     panic!("no bounding box in BvhNode::new");
+                                 }
+                                 (bbox, h)
+                             })
+                        .collect();
+        Self::build(items)
+    }
+
+    fn build(mut items: Vec<(Aabb, Box<dyn Hitable>)>) -> Self {
+        let n = items.len();
+        if n == 1 {
+            let (bbox, h) = items.pop().unwrap();
+            return Self {
+                left: h,
+                right: None,
+                bbox,
+            };
+        }
+        if n == 2 {
+            let (rbox, r) = items.pop().unwrap();
+            let (lbox, l) = items.pop().unwrap();
+            return Self {
+                left: l,
+                right: Some(r),
+                bbox: surrounding_box(&lbox, &rbox),
+            };
+        }
+
+        // surface area heuristic: try every split position along every axis and keep the cheapest
+        let mut best_axis = 0;
+        let mut best_split = n / 2;
+        let mut best_cost = f32::MAX;
+        for axis in 0..3 {
+            sort_by_axis(&mut items, axis);
+            let mut right_area = vec![0.; n];
+            let mut acc = items[n - 1].0.clone();
+            for i in (1..n).rev() {
+                acc = surrounding_box(&acc, &items[i].0);
+                right_area[i] = acc.surface_area();
+            }
+            let mut acc = items[0].0.clone();
+            for i in 1..n {
+                let cost = i as f32 * acc.surface_area() + (n - i) as f32 * right_area[i];
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_axis = axis;
+                    best_split = i;
+                }
+                acc = surrounding_box(&acc, &items[i].0);
+            }
+        }
+
+        sort_by_axis(&mut items, best_axis);
+        let rest = items.split_off(best_split);
+        let left = Self::build(items);
+        let right = Self::build(rest);
+        Self {
+            bbox: surrounding_box(&left.bbox, &right.bbox),
+            left: Box::new(left),
+            right: Some(Box::new(right)),
+        }
+    }
+}
+
+/// Puts every bounded object of `list` into a BVH and tests unbounded ones, such as infinite
+/// planes, one by one next to it. Also accepts an empty list.
+pub fn build(list: HitableList, t0: f32, t1: f32) -> Box<dyn Hitable> {
+    let (bounded, unbounded): (Vec<_>, Vec<_>) = list.list
+                                                     .into_iter()
+                                                     .partition(|h| h.bounding_box(t0, t1, &mut Aabb::default()));
+    if bounded.is_empty() {
+        return Box::new(HitableList::new(unbounded));
+    }
+    let bvh = Box::new(BvhNode::new(HitableList::new(bounded), t0, t1));
+    if unbounded.is_empty() {
+        return bvh;
+    }
+    let mut list = unbounded;
+    list.push(bvh);
+    Box::new(HitableList::new(list))
+}
+
+fn sort_by_axis(items: &mut [(Aabb, Box<dyn Hitable>)], axis: usize) {
+    items.sort_by(|a, b| {
+                      a.0.centroid()[axis]
+                          .partial_cmp(&b.0.centroid()[axis])
+                          .unwrap_or(Ordering::Equal)
+                  });
+}
+
+impl Hitable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return false;
+        }
+        let hit_left = self.left.hit(r, t_min, t_max, rec, sampler);
+        let hit_right = match self.right {
+            Some(ref right) => right.hit(r, t_min, if hit_left { rec.t } else { t_max }, rec, sampler),
+            None => false,
+        };
+        hit_left || hit_right
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return 1.;
+        }
+        let left = self.left.transmittance(r, t_min, t_max, sampler);
+        match self.right {
+            Some(ref right) if left > 0. => left * right.transmittance(r, t_min, t_max, sampler),
+            _ => left,
+        }
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox.clone();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build, BvhNode};
+    use utils::plane::Plane;
+    use utils::hitable::{Hitable, HitableList, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::sphere::Sphere;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    fn sphere_field(centers: &[Vec3]) -> HitableList {
+        let list = centers.iter()
+                          .map(|c| Box::new(Sphere::new(c.clone(), 0.2, dummy())) as Box<dyn Hitable>)
+                          .collect();
+        HitableList::new(list)
+    }
+
+    #[test]
+    fn bvh_matches_linear_list() {
+        let mut sampler = Sampler::new(0);
+        let mut centers = vec![];
+        for a in -5..5 {
+            for b in -5..5 {
+                centers.push(Vec3::new(a as f32 + 0.9 * sampler.drand48(), 0.2, b as f32 + 0.9 * sampler.drand48()));
+            }
+        }
+        let list = sphere_field(&centers);
+        let bvh = BvhNode::new(sphere_field(&centers), 0., 1.);
+
+        let origin = Vec3::new(13., 2., 3.);
+        for _ in 0..2000 {
+            let target = Vec3::new(10. * sampler.drand48() - 5., 0.4 * sampler.drand48(), 10. * sampler.drand48() - 5.);
+            let r = Ray::new(&origin, &(target - origin.clone()));
+            let mut rec_list = HitRecord::new(dummy());
+            let mut rec_bvh = HitRecord::new(dummy());
+            let hit_list = list.hit(&r, 0.001, f32::MAX, &mut rec_list, &mut sampler);
+            let hit_bvh = bvh.hit(&r, 0.001, f32::MAX, &mut rec_bvh, &mut sampler);
+            assert_eq!(hit_list, hit_bvh);
+            if hit_list {
+                assert_eq!(rec_list.t, rec_bvh.t);
+            }
+        }
+    }
+
+    #[test]
+    fn build_keeps_unbounded_objects_outside_the_tree() {
+        let mut sampler = Sampler::new(0);
+        let mut list = sphere_field(&[Vec3::new(0., 0., -3.), Vec3::new(2., 0., -3.)]);
+        list.list.push(Box::new(Plane::new(Vec3::new(0., -1., 0.), Vec3::new(0., 1., 0.), dummy())));
+        let world = build(list, 0., 1.);
+        let mut rec = HitRecord::new(dummy());
+        let down = Ray::new(&Vec3::new(50., 0., 50.), &Vec3::new(0., -1., 0.));
+        assert!(world.hit(&down, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert_eq!(rec.t, 1.);
+        let ahead = Ray::new(&Vec3::new(0., 0., 0.), &Vec3::new(0., 0., -1.));
+        assert!(world.hit(&ahead, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 2.8).abs() < 1e-5);
+        assert!(!build(HitableList::new(vec![]), 0., 1.).hit(&ahead, 0.001, f32::MAX, &mut rec, &mut sampler));
+    }
+}
@@ -1,8 +1,10 @@
 use std::f32::consts::PI;
-use utils::vec3::{Vec3, unit_vector, cross, dot};
+use utils::vec3::{Vec3, unit_vector, cross};
 use utils::ray::Ray;
-use utils::random::drand48;
+use utils::random::Sampler;
+use utils::sampling::concentric_disk;
 
+#[allow(dead_code)]
 #[derive(Default)]
 pub struct Camera {
     pub origin: Vec3,
@@ -13,6 +15,9 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f32,
+    /// Shutter interval; rays get a uniformly sampled time in `[time0, time1)`.
+    pub time0: f32,
+    pub time1: f32,
 }
 
 #[allow(dead_code)]
@@ -49,7 +54,7 @@ impl Camera {
         let half_width = aspect * half_height;
         let origin = lookfrom.clone();
         Self {
-            origin: origin,
+            origin,
             lower_left_corner: lookfrom.clone() - u.clone() * half_width - v.clone() * half_height - w,
             horizontal: u * 2. * half_width,
             vertical: v * 2. * half_height,
@@ -79,36 +84,65 @@ impl Camera {
                                w.clone() * focus_dist,
             horizontal: u.clone() * 2. * focus_dist * half_width,
             vertical: v.clone() * 2. * focus_dist * half_height,
-            w: w,
-            u: u,
-            v: v,
-            lens_radius: lens_radius,
+            w,
+            u,
+            v,
+            lens_radius,
             ..Self::default()
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
+    /// Keeps the shutter open from `time0` to `time1`; objects that move in between are blurred.
+    pub fn with_shutter(mut self, time0: f32, time1: f32) -> Self {
+        self.time0 = time0;
+        self.time1 = time1;
+        self
+    }
+
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut Sampler) -> Ray {
         // let ray_vec = self.lower_left_corner.clone() + self.horizontal.clone() * u + self.vertical.clone() * v -
         //               self.origin.clone();
         // Ray::new(&self.origin, &ray_vec)
 
-        let rd = random_in_unit_disk() * self.lens_radius;
+        let rd = random_in_unit_disk(sampler) * self.lens_radius;
         let offset: Vec3 = self.u.clone() * rd.x() + self.v.clone() * rd.y();
         let ray_vec = self.lower_left_corner.clone() + self.horizontal.clone() * u + self.vertical.clone() * v -
                       self.origin.clone() - offset.clone();
         let origin = self.origin.clone() + offset.clone();
-        Ray::new(&origin, &ray_vec)
+        // an instantaneous shutter draws no extra random numbers, so still renders are unchanged
+        let time = if self.time1 > self.time0 {
+            self.time0 + sampler.drand48() * (self.time1 - self.time0)
+        } else {
+            self.time0
+        };
+        Ray::with_time(&origin, &ray_vec, time)
     }
 }
 
+/// Uniform point of the unit disk in the xy plane, from the concentric mapping so that
+/// neighbouring random numbers stay neighbours on the lens.
 #[allow(dead_code)]
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut p: Vec3;
-    loop {
-        p = Vec3::new(drand48(), drand48(), 0.) * 2.0 - Vec3::new(1., 1., 0.);
-        if !(dot(&p, &p) >= 1.) {
-            break;
-        }
+pub fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3 {
+    let (x, y) = concentric_disk(sampler.drand48(), sampler.drand48());
+    Vec3::new(x, y, 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Camera;
+    use utils::random::Sampler;
+    use utils::vec3::Vec3;
+
+    #[test]
+    fn rays_sample_the_shutter_interval() {
+        let mut sampler = Sampler::new(7);
+        let vup = Vec3::new(0., 1., 0.);
+        let still = Camera::with_lookat(&Vec3::new(0., 0., 0.), &Vec3::new(0., 0., -1.), &vup, 90., 2.);
+        assert_eq!(still.get_ray(0.5, 0.5, &mut sampler).time(), 0.);
+
+        let cam = still.with_shutter(1., 2.);
+        let times: Vec<f32> = (0..100).map(|_| cam.get_ray(0.5, 0.5, &mut sampler).time()).collect();
+        assert!(times.iter().all(|&t| (1. ..2.).contains(&t)));
+        assert!(times.iter().any(|&t| t < 1.5) && times.iter().any(|&t| t >= 1.5));
     }
-    p
 }
@@ -0,0 +1,233 @@
+extern crate flate2;
+
+use std::io::{self, Write};
+use self::flate2::Compression as ZlibLevel;
+use self::flate2::write::ZlibEncoder;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelType {
+    Half,
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    /// zlib over blocks of 16 scanlines.
+    Zip,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            Compression::None => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even and saturating to infinity.
+pub fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let man = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // infinity or NaN, keeping NaNs quiet
+        return sign | 0x7c00 | if man != 0 { 0x200 } else { 0 };
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        // subnormal half: shift the mantissa including its implicit leading one
+        let man = man | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = man >> shift;
+        let rem = man & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+    let half = ((e as u32) << 10) | (man >> 13);
+    let rem = man & 0x1fff;
+    let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // a carry out of the mantissa correctly bumps the exponent, up to infinity
+    sign | (half + round as u32) as u16
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+    let mut v = vec![];
+    for n in [0, 0, width as i32 - 1, height as i32 - 1].iter() {
+        v.extend_from_slice(&n.to_le_bytes());
+    }
+    v
+}
+
+/// Applies the byte interleaving and delta predictor OpenEXR expects before zlib, then falls back
+/// to the raw bytes when compression does not pay off.
+fn zip_block(raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut tmp: Vec<u8> = Vec::with_capacity(raw.len());
+    tmp.extend(raw.iter().step_by(2));
+    tmp.extend(raw.iter().skip(1).step_by(2));
+    let mut p = tmp[0];
+    for t in tmp.iter_mut().skip(1) {
+        let d = t.wrapping_sub(p).wrapping_add(128);
+        p = *t;
+        *t = d;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), ZlibLevel::default());
+    encoder.write_all(&tmp)?;
+    let compressed = encoder.finish()?;
+    if compressed.len() < raw.len() {
+        Ok(compressed)
+    } else {
+        Ok(raw.to_vec())
+    }
+}
+
+/// Writes a single-part scanline OpenEXR image with `R`, `G` and `B` channels. `rgb` holds linear
+/// values, top row first.
+pub fn write_exr(out: &mut dyn Write,
+                 width: usize,
+                 height: usize,
+                 rgb: &[[f32; 3]],
+                 pixel_type: PixelType,
+                 compression: Compression)
+                 -> io::Result<()> {
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    let mut channels = vec![];
+    for name in ["B", "G", "R"].iter() {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        let kind: i32 = match pixel_type {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        };
+        channels.extend_from_slice(&kind.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[compression.id()]);
+    attribute(&mut header, "dataWindow", "box2i", &box2i(width, height));
+    attribute(&mut header, "displayWindow", "box2i", &box2i(width, height));
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    let lines = compression.lines_per_block();
+    let mut blocks = vec![];
+    for (b, block) in rgb.chunks(width * lines).enumerate() {
+        let mut raw = vec![];
+        for row in block.chunks(width) {
+            // channels are stored one after another in alphabetical order
+            for c in [2, 1, 0].iter() {
+                for px in row.iter() {
+                    match pixel_type {
+                        PixelType::Half => raw.extend_from_slice(&f32_to_f16(px[*c]).to_le_bytes()),
+                        PixelType::Float => raw.extend_from_slice(&px[*c].to_le_bytes()),
+                    }
+                }
+            }
+        }
+        let data = match compression {
+            Compression::None => raw,
+            Compression::Zip => zip_block(&raw)?,
+        };
+        let mut chunk = Vec::with_capacity(data.len() + 8);
+        chunk.extend_from_slice(&((b * lines) as i32).to_le_bytes());
+        chunk.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        chunk.extend_from_slice(&data);
+        blocks.push(chunk);
+    }
+
+    let mut offset = (header.len() + blocks.len() * 8) as u64;
+    for block in blocks.iter() {
+        header.extend_from_slice(&offset.to_le_bytes());
+        offset += block.len() as u64;
+    }
+    out.write_all(&header)?;
+    for block in blocks.iter() {
+        out.write_all(block)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate exr;
+
+    use std::io::Cursor;
+    use self::exr::prelude::{read, ReadChannels, ReadLayers};
+    use super::{f32_to_f16, write_exr, Compression, PixelType};
+
+    #[test]
+    fn converts_to_half() {
+        assert_eq!(f32_to_f16(0.), 0);
+        assert_eq!(f32_to_f16(1.), 0x3c00);
+        assert_eq!(f32_to_f16(-2.), 0xc000);
+        assert_eq!(f32_to_f16(65504.), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16(0.333_333_34), 0x3555);
+    }
+
+    fn image(width: usize, height: usize) -> Vec<[f32; 3]> {
+        (0..width * height).map(|i| [i as f32 * 0.25, 1. + (i % 7) as f32, 40. - i as f32 * 0.125]).collect()
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let (width, height) = (13, 37);
+        let rgb = image(width, height);
+        for &pixel_type in [PixelType::Half, PixelType::Float].iter() {
+            for &compression in [Compression::None, Compression::Zip].iter() {
+                let mut data = vec![];
+                write_exr(&mut data, width, height, &rgb, pixel_type, compression).unwrap();
+                let image = read().no_deep_data()
+                                  .largest_resolution_level()
+                                  .rgb_channels(|res, _| vec![[0f32; 3]; res.width() * res.height()],
+                                                move |pixels: &mut Vec<[f32; 3]>, pos, (r, g, b): (f32, f32, f32)| {
+                                                    pixels[pos.y() * width + pos.x()] = [r, g, b];
+                                                })
+                                  .first_valid_layer()
+                                  .all_attributes()
+                                  .from_buffered(Cursor::new(data))
+                                  .unwrap();
+                let pixels = &image.layer_data.channel_data.pixels;
+                for (a, b) in rgb.iter().zip(pixels.iter()) {
+                    for c in 0..3 {
+                        assert!((a[c] - b[c]).abs() <= a[c].abs() * 1e-3, "{:?} != {:?}", a, b);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+extern crate png;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use utils::vec3::Vec3;
+use utils::{exr, rgbe};
+use utils::tonemap::DisplayTransform;
+
+/// Running sum of the linear radiance samples that landed in one pixel.
+#[derive(Clone, Default, Debug)]
+pub struct Pixel {
+    pub sum: Vec3,
+    pub weight: f32,
+}
+
+impl Pixel {
+    pub fn add_sample(&mut self, col: Vec3) {
+        self.sum = self.sum.clone() + col;
+        self.weight += 1.;
+    }
+
+    pub fn value(&self) -> Vec3 {
+        if self.weight > 0. {
+            self.sum.clone() / self.weight
+        } else {
+            Vec3::new(0., 0., 0.)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// ASCII PPM, one pixel per line.
+    P3,
+    /// Binary PPM.
+    P6,
+    Png8,
+    Png16,
+    /// Portable float map: linear 32-bit float RGB.
+    Pfm,
+    /// Radiance RGBE, run-length encoded.
+    Hdr,
+    /// Single-part scanline OpenEXR.
+    Exr(exr::PixelType, exr::Compression),
+}
+
+impl ImageFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "ppm" => Some(ImageFormat::P6),
+            "png" => Some(ImageFormat::Png8),
+            "pfm" => Some(ImageFormat::Pfm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr(exr::PixelType::Half, exr::Compression::Zip)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    /// Besides the plain names, `exr` takes optional `-half`/`-float` and `-zip`/`-none` suffixes,
+    /// e.g. `exr-float-none`. It defaults to half floats with ZIP compression.
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s == "exr" || s.starts_with("exr-") {
+            let mut pixel_type = exr::PixelType::Half;
+            let mut compression = exr::Compression::Zip;
+            for token in s.split('-').skip(1) {
+                match token {
+                    "half" => pixel_type = exr::PixelType::Half,
+                    "float" => pixel_type = exr::PixelType::Float,
+                    "zip" => compression = exr::Compression::Zip,
+                    "none" => compression = exr::Compression::None,
+                    _ => return Err(format!("unknown image format: {}", s)),
+                }
+            }
+            return Ok(ImageFormat::Exr(pixel_type, compression));
+        }
+        match s {
+            "p3" => Ok(ImageFormat::P3),
+            "p6" => Ok(ImageFormat::P6),
+            "png" | "png8" => Ok(ImageFormat::Png8),
+            "png16" => Ok(ImageFormat::Png16),
+            "pfm" => Ok(ImageFormat::Pfm),
+            "hdr" => Ok(ImageFormat::Hdr),
+            _ => Err(format!("unknown image format: {}", s)),
+        }
+    }
+}
+
+/// Image-sized accumulation buffer. `(x, y)` addresses pixels from the top-left corner.
+#[derive(Clone, Debug)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &Pixel {
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut Pixel {
+        &mut self.pixels[y * self.width + x]
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, col: Vec3) {
+        self.pixel_mut(x, y).add_sample(col);
+    }
+
+    /// Averaged linear colors, top row first.
+    pub fn values(&self) -> Vec<Vec3> {
+        self.pixels.iter().map(Pixel::value).collect()
+    }
+
+    /// Display-referred color in `[0, 1]`.
+    fn display(&self, x: usize, y: usize, transform: &DisplayTransform) -> [f32; 3] {
+        let col = self.pixel(x, y).value();
+        transform.apply([col.x(), col.y(), col.z()])
+    }
+
+    fn to_u8(&self, transform: &DisplayTransform) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.width * self.height * 3);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for c in self.display(x, y, transform).iter() {
+                    data.push((255. * c).round() as u8);
+                }
+            }
+        }
+        data
+    }
+
+    pub fn write_p3(&self, out: &mut dyn Write, transform: &DisplayTransform) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for rgb in self.to_u8(transform).chunks(3) {
+            writeln!(out, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+        }
+        Ok(())
+    }
+
+    pub fn write_p6(&self, out: &mut dyn Write, transform: &DisplayTransform) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.to_u8(transform))
+    }
+
+    pub fn write_png(&self, out: &mut dyn Write, transform: &DisplayTransform, sixteen_bit: bool) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        let data = if sixteen_bit {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            let mut data = Vec::with_capacity(self.width * self.height * 6);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    for c in self.display(x, y, transform).iter() {
+                        let v = (65535. * c).round() as u16;
+                        data.push((v >> 8) as u8);
+                        data.push(v as u8);
+                    }
+                }
+            }
+            data
+        } else {
+            encoder.set_depth(png::BitDepth::Eight);
+            self.to_u8(transform)
+        };
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&data).map_err(png_error)?;
+        writer.finish().map_err(png_error)
+    }
+
+    /// Writes the raw linear averages. PFM stores rows bottom to top and a negative scale marks
+    /// little-endian floats.
+    pub fn write_pfm(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let mut data = Vec::with_capacity(self.width * self.height * 12);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let col = self.pixel(x, y).value();
+                for c in [col.x(), col.y(), col.z()].iter() {
+                    data.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+        }
+        out.write_all(&data)
+    }
+
+    fn linear_rgb(&self) -> Vec<[f32; 3]> {
+        self.pixels.iter().map(|p| {
+                                   let col = p.value();
+                                   [col.x(), col.y(), col.z()]
+                               })
+                   .collect()
+    }
+
+    pub fn write_hdr(&self, out: &mut dyn Write) -> io::Result<()> {
+        rgbe::write_hdr(out, self.width, self.height, &self.linear_rgb())
+    }
+
+    pub fn write_exr(&self,
+                     out: &mut dyn Write,
+                     pixel_type: exr::PixelType,
+                     compression: exr::Compression)
+                     -> io::Result<()> {
+        exr::write_exr(out, self.width, self.height, &self.linear_rgb(), pixel_type, compression)
+    }
+
+    /// `transform` only affects the display-referred formats; PFM, HDR and EXR keep linear values.
+    pub fn write_to(&self, out: &mut dyn Write, format: ImageFormat, transform: &DisplayTransform) -> io::Result<()> {
+        match format {
+            ImageFormat::P3 => self.write_p3(out, transform),
+            ImageFormat::P6 => self.write_p6(out, transform),
+            ImageFormat::Png8 => self.write_png(out, transform, false),
+            ImageFormat::Png16 => self.write_png(out, transform, true),
+            ImageFormat::Pfm => self.write_pfm(out),
+            ImageFormat::Hdr => self.write_hdr(out),
+            ImageFormat::Exr(pixel_type, compression) => self.write_exr(out, pixel_type, compression),
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self,
+                                 path: P,
+                                 format: ImageFormat,
+                                 transform: &DisplayTransform)
+                                 -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out, format, transform)?;
+        out.flush()
+    }
+}
+
+fn png_error(e: png::EncodingError) -> io::Error {
+    match e {
+        png::EncodingError::IoError(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Film, ImageFormat};
+    use utils::exr::{Compression, PixelType};
+    use utils::tonemap::{DisplayTransform, Encoding, ToneMap};
+    use utils::vec3::Vec3;
+
+    fn film() -> Film {
+        let mut film = Film::new(3, 2);
+        film.add_sample(0, 0, Vec3::new(1., 0.5, 0.));
+        film.add_sample(0, 0, Vec3::new(0., 0.5, 0.));
+        film.add_sample(2, 1, Vec3::new(4., 0.25, 0.));
+        film
+    }
+
+    #[test]
+    fn averages_samples() {
+        let film = film();
+        assert_eq!(film.pixel(0, 0).value().e, (0.5, 0.5, 0.));
+        assert_eq!(film.pixel(1, 0).value().e, (0., 0., 0.));
+    }
+
+    #[test]
+    fn writes_binary_ppm_and_pfm() {
+        let film = film();
+        let linear = DisplayTransform {
+            encoding: Encoding::Linear,
+            ..DisplayTransform::default()
+        };
+        let mut p6 = vec![];
+        film.write_to(&mut p6, ImageFormat::P6, &linear).unwrap();
+        assert!(p6.starts_with(b"P6\n3 2\n255\n"));
+        assert_eq!(p6.len(), 11 + 3 * 2 * 3);
+        assert_eq!(&p6[11..14], &[128, 128, 0]);
+        assert_eq!(&p6[p6.len() - 3..], &[255, 64, 0]);
+
+        let mut pfm = vec![];
+        film.write_to(&mut pfm, ImageFormat::Pfm, &linear).unwrap();
+        let header = b"PF\n3 2\n-1.0\n";
+        assert!(pfm.starts_with(header));
+        // bottom row comes first, so its last pixel keeps the unclamped value
+        let red = &pfm[header.len() + 24..header.len() + 28];
+        assert_eq!(f32::from_le_bytes([red[0], red[1], red[2], red[3]]), 4.);
+    }
+
+    #[test]
+    fn writes_png() {
+        let mut png = vec![];
+        film().write_to(&mut png, ImageFormat::Png16, &DisplayTransform::default()).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn applies_display_transform_before_quantizing() {
+        let transform = DisplayTransform {
+            exposure: -1.,
+            tone_map: ToneMap::Reinhard,
+            encoding: Encoding::Srgb,
+        };
+        let mut p3 = vec![];
+        film().write_to(&mut p3, ImageFormat::P3, &transform).unwrap();
+        let text = String::from_utf8(p3).unwrap();
+        // 4.0 at -1 stop is 2.0, which Reinhard maps to 2/3 before the sRGB curve
+        assert_eq!(text.lines().last(), Some("213 94 0"));
+        assert!(text.lines().skip(3).all(|l| l.split(' ').all(|v| v.parse::<u8>().is_ok())));
+    }
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert_eq!(ImageFormat::from_path("out.PNG"), Some(ImageFormat::Png8));
+        assert_eq!(ImageFormat::from_path("out.pfm"), Some(ImageFormat::Pfm));
+        assert_eq!(ImageFormat::from_path("out.ppm"), Some(ImageFormat::P6));
+        assert_eq!(ImageFormat::from_path("out.hdr"), Some(ImageFormat::Hdr));
+        assert_eq!(ImageFormat::from_path("out.exr"),
+                   Some(ImageFormat::Exr(PixelType::Half, Compression::Zip)));
+        assert_eq!(ImageFormat::from_path("out"), None);
+    }
+
+    #[test]
+    fn parses_exr_options() {
+        assert_eq!("exr".parse(), Ok(ImageFormat::Exr(PixelType::Half, Compression::Zip)));
+        assert_eq!("exr-float-none".parse(), Ok(ImageFormat::Exr(PixelType::Float, Compression::None)));
+        assert_eq!("exr-none".parse(), Ok(ImageFormat::Exr(PixelType::Half, Compression::None)));
+        assert!("exr-lossy".parse::<ImageFormat>().is_err());
+    }
+}
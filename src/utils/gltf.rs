@@ -0,0 +1,811 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use serde_json;
+use utils::vec3::{unit_vector, Vec3};
+use utils::camera::Camera;
+use utils::transform::{Matrix, Transform, IDENTITY};
+use utils::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use utils::mesh::{Face, MeshData, TriangleMesh};
+use utils::texture::{ConstantTexture, ImageTexture, Texture};
+
+/// The subset of the glTF 2.0 JSON the renderer reads; everything else is ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<SceneDef>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+    #[serde(default)]
+    accessors: Vec<Accessor>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    buffers: Vec<Buffer>,
+    #[serde(default)]
+    materials: Vec<MaterialDef>,
+    #[serde(default)]
+    textures: Vec<TextureDef>,
+    #[serde(default)]
+    images: Vec<ImageDef>,
+    #[serde(default)]
+    cameras: Vec<CameraDef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SceneDef {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Node {
+    #[serde(default)]
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    /// Column-major.
+    matrix: Option<[f32; 16]>,
+    translation: Option<[f32; 3]>,
+    /// Unit quaternion `[x, y, z, w]`.
+    rotation: Option<[f32; 4]>,
+    scale: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeshDef {
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Primitive {
+    attributes: BTreeMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    #[serde(default = "default_mode")]
+    mode: u32,
+}
+
+fn default_mode() -> u32 {
+    4
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Buffer {
+    uri: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaterialDef {
+    #[serde(default)]
+    pbr_metallic_roughness: Pbr,
+    #[serde(default)]
+    emissive_factor: [f32; 3],
+    #[serde(default)]
+    extensions: MaterialExtensions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pbr {
+    #[serde(default = "default_base_color")]
+    base_color_factor: [f32; 4],
+    base_color_texture: Option<TextureRef>,
+    #[serde(default = "one")]
+    metallic_factor: f32,
+    #[serde(default = "one")]
+    roughness_factor: f32,
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Self {
+            base_color_factor: default_base_color(),
+            base_color_texture: None,
+            metallic_factor: 1.,
+            roughness_factor: 1.,
+        }
+    }
+}
+
+fn default_base_color() -> [f32; 4] {
+    [1., 1., 1., 1.]
+}
+
+fn one() -> f32 {
+    1.
+}
+
+#[derive(Debug, Deserialize)]
+struct TextureRef {
+    index: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<Ior>,
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    emissive_strength: Option<EmissiveStrength>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transmission {
+    #[serde(default)]
+    transmission_factor: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ior {
+    #[serde(default = "default_ior")]
+    ior: f32,
+}
+
+fn default_ior() -> f32 {
+    1.5
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmissiveStrength {
+    #[serde(default = "one")]
+    emissive_strength: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TextureDef {
+    source: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageDef {
+    uri: Option<String>,
+    buffer_view: Option<usize>,
+    mime_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraDef {
+    #[serde(rename = "type")]
+    kind: String,
+    perspective: Option<Perspective>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Perspective {
+    aspect_ratio: Option<f32>,
+    /// Vertical field of view in radians.
+    yfov: f32,
+}
+
+/// A perspective camera placed by its node: it looks down the node's -z axis with +y up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfCamera {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees.
+    pub vfov: f32,
+    pub aspect: Option<f32>,
+}
+
+impl GltfCamera {
+    /// The equivalent `Camera::with_lookat`, using the file's aspect ratio if it has one.
+    pub fn build(&self, default_aspect: f32) -> Camera {
+        Camera::with_lookat(&self.lookfrom,
+                            &self.lookat,
+                            &self.vup,
+                            self.vfov,
+                            self.aspect.unwrap_or(default_aspect))
+    }
+}
+
+/// Every mesh of a glTF scene, baked into world space as one triangle mesh, and its cameras in
+/// the order the node hierarchy is walked.
+pub struct GltfScene {
+    pub mesh: TriangleMesh,
+    pub cameras: Vec<GltfCamera>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The node's local transform: its `matrix`, or translation * rotation * scale.
+fn local_matrix(node: &Node) -> Matrix {
+    if let Some(m) = node.matrix {
+        let mut out = IDENTITY;
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = m[j * 4 + i];
+            }
+        }
+        return out;
+    }
+    let [tx, ty, tz] = node.translation.unwrap_or([0., 0., 0.]);
+    let [x, y, z, w] = node.rotation.unwrap_or([0., 0., 0., 1.]);
+    let [sx, sy, sz] = node.scale.unwrap_or([1., 1., 1.]);
+    let r = [[1. - 2. * (y * y + z * z), 2. * (x * y - z * w), 2. * (x * z + y * w)],
+             [2. * (x * y + z * w), 1. - 2. * (x * x + z * z), 2. * (y * z - x * w)],
+             [2. * (x * z - y * w), 2. * (y * z + x * w), 1. - 2. * (x * x + y * y)]];
+    [[r[0][0] * sx, r[0][1] * sy, r[0][2] * sz, tx],
+     [r[1][0] * sx, r[1][1] * sy, r[1][2] * sz, ty],
+     [r[2][0] * sx, r[2][1] * sy, r[2][2] * sz, tz],
+     [0., 0., 0., 1.]]
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+                       .and_then(|h| std::str::from_utf8(h).ok())
+                       .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Reads a `data:` URI or a file relative to the glTF file.
+fn read_uri(uri: &str, base_dir: &Path) -> io::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let data = uri.split_once(";base64,")
+                      .and_then(|(_, data)| decode_base64(data))
+                      .ok_or_else(|| invalid("only base64 data URIs are supported".to_string()))?;
+        return Ok(data);
+    }
+    let path = base_dir.join(percent_decode(uri));
+    fs::read(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// Everything needed while walking the document, with its buffers loaded.
+struct Loader<'a> {
+    doc: &'a Document,
+    buffers: Vec<Vec<u8>>,
+    base_dir: &'a Path,
+}
+
+impl<'a> Loader<'a> {
+    fn view(&self, index: usize) -> io::Result<(&[u8], Option<usize>)> {
+        let view = self.doc
+                       .buffer_views
+                       .get(index)
+                       .ok_or_else(|| invalid(format!("missing buffer view {}", index)))?;
+        let buffer = self.buffers
+                         .get(view.buffer)
+                         .ok_or_else(|| invalid(format!("buffer view {}: missing buffer {}", index, view.buffer)))?;
+        let end = view.byte_offset
+                      .checked_add(view.byte_length)
+                      .ok_or_else(|| invalid(format!("buffer view {}: byte range overflows", index)))?;
+        if end > buffer.len() {
+            return Err(invalid(format!("buffer view {} ends at byte {} of a {} byte buffer", index, end, buffer.len())));
+        }
+        Ok((&buffer[view.byte_offset..end], view.byte_stride))
+    }
+
+    /// Reads `count` elements of `components` values each, converting normalized integers to
+    /// `[0, 1]` or `[-1, 1]`. An accessor without a buffer view reads as zeros, up to `zeros`
+    /// elements; its count is not backed by any data, so it must not decide how much to allocate.
+    fn read(&self, index: usize, components: &[&str], zeros: usize) -> io::Result<(Vec<f64>, usize)> {
+        let context = |msg: String| invalid(format!("accessor {}: {}", index, msg));
+        let acc = self.doc.accessors.get(index).ok_or_else(|| invalid(format!("missing accessor {}", index)))?;
+        if acc.sparse.is_some() {
+            return Err(context("sparse accessors are not supported".to_string()));
+        }
+        if !components.contains(&acc.kind.as_str()) {
+            return Err(context(format!("unexpected type {}", acc.kind)));
+        }
+        let n = match acc.kind.as_str() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            k => return Err(context(format!("unsupported type {}", k))),
+        };
+        let size = match acc.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            t => return Err(context(format!("unknown component type {}", t))),
+        };
+        let view = match acc.buffer_view {
+            Some(v) => v,
+            // no buffer view means all zeros
+            None if acc.count <= zeros => return Ok((vec![0.; acc.count * n], n)),
+            None => return Err(context(format!("{} elements without a buffer view", acc.count))),
+        };
+        let (bytes, stride) = self.view(view)?;
+        let stride = stride.unwrap_or(n * size);
+        // byte just past the last element, or `None` if that overflows
+        let end = match acc.count {
+            0 => Some(0),
+            count => stride.checked_mul(count - 1)
+                           .and_then(|last| last.checked_add(acc.byte_offset))
+                           .and_then(|last| last.checked_add(n * size)),
+        };
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(context(format!("{} elements do not fit in buffer view {}", acc.count, view)));
+        }
+        let mut out = Vec::with_capacity(acc.count * n);
+        for i in 0..acc.count {
+            for k in 0..n {
+                let at = acc.byte_offset + i * stride + k * size;
+                let b = &bytes[at..at + size];
+                let (value, max) = match acc.component_type {
+                    5120 => (b[0] as i8 as f64, 127.),
+                    5121 => (b[0] as f64, 255.),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.),
+                    5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.),
+                };
+                out.push(if acc.normalized { (value / max).max(-1.) } else { value });
+            }
+        }
+        Ok((out, n))
+    }
+
+    fn texture(&self, index: usize) -> io::Result<ImageTexture> {
+        let context = |e: io::Error| io::Error::new(e.kind(), format!("texture {}: {}", index, e));
+        let image = self.doc
+                        .textures
+                        .get(index)
+                        .and_then(|t| t.source)
+                        .and_then(|s| self.doc.images.get(s))
+                        .ok_or_else(|| context(invalid("missing image".to_string())))?;
+        let bytes = match (&image.uri, image.buffer_view) {
+            (Some(uri), _) => read_uri(uri, self.base_dir).map_err(context)?,
+            (None, Some(view)) => self.view(view).map_err(context)?.0.to_vec(),
+            (None, None) => return Err(context(invalid("image has neither a uri nor a buffer view".to_string()))),
+        };
+        if bytes.starts_with(b"\x89PNG") {
+            ImageTexture::from_png(&bytes[..]).map_err(context)
+        } else {
+            let kind = image.mime_type.clone().unwrap_or_else(|| "this image type".to_string());
+            Err(context(invalid(format!("only PNG images are supported, not {}", kind))))
+        }
+    }
+
+    /// Picks the closest of our materials: emissive ones become `DiffuseLight`, transmissive ones
+    /// `Dielectric`, metallic ones `Metal` with the roughness as fuzz, and the rest `Lambertian`.
+    fn material(&self, def: &MaterialDef) -> io::Result<Arc<dyn Material>> {
+        let strength = def.extensions.emissive_strength.as_ref().map_or(1., |s| s.emissive_strength);
+        let [er, eg, eb] = def.emissive_factor;
+        if er.max(eg).max(eb) > 0. {
+            return Ok(Arc::new(DiffuseLight::new(Vec3::new(er, eg, eb) * strength)));
+        }
+        if def.extensions.transmission.as_ref().is_some_and(|t| t.transmission_factor > 0.) {
+            let ior = def.extensions.ior.as_ref().map_or(1.5, |i| i.ior);
+            return Ok(Arc::new(Dielectric::new(ior)));
+        }
+        let pbr = &def.pbr_metallic_roughness;
+        let [r, g, b, _] = pbr.base_color_factor;
+        let factor = Vec3::new(r, g, b);
+        let albedo: Arc<dyn Texture> = match pbr.base_color_texture {
+            Some(ref t) => Arc::new(self.texture(t.index)?.tinted(&factor)),
+            None => Arc::new(ConstantTexture::new(factor)),
+        };
+        if pbr.metallic_factor >= 0.5 {
+            Ok(Arc::new(Metal::textured(albedo, pbr.roughness_factor)))
+        } else {
+            Ok(Arc::new(Lambertian::textured(albedo)))
+        }
+    }
+
+    /// Appends one primitive, transformed by `m`, to `data`.
+    fn primitive(&self, prim: &Primitive, m: &Transform, data: &mut MeshData) -> io::Result<()> {
+        let position = *prim.attributes
+                            .get("POSITION")
+                            .ok_or_else(|| invalid("primitive without POSITION".to_string()))?;
+        let (positions, _) = self.read(position, &["VEC3"], 0)?;
+        let first = data.positions.len();
+        let count = positions.len() / 3;
+        let vec = |v: &[f64], i: usize| Vec3::new(v[3 * i] as f32, v[3 * i + 1] as f32, v[3 * i + 2] as f32);
+        for i in 0..count {
+            data.positions.push(m.point(&vec(&positions, i)));
+        }
+
+        let normals = match prim.attributes.get("NORMAL") {
+            Some(&n) => Some(self.read(n, &["VEC3"], count)?.0),
+            None => None,
+        };
+        let first_normal = data.normals.len();
+        if let Some(ref normals) = normals {
+            for i in 0..normals.len() / 3 {
+                data.normals.push(unit_vector(m.normal(&vec(normals, i))));
+            }
+        }
+        let uvs = match prim.attributes.get("TEXCOORD_0") {
+            Some(&t) => Some(self.read(t, &["VEC2"], count)?.0),
+            None => None,
+        };
+        let first_uv = data.uvs.len();
+        if let Some(ref uvs) = uvs {
+            // glTF puts v = 0 at the top of the image, image textures at the bottom
+            data.uvs.extend(uvs.chunks(2).map(|t| (t[0] as f32, 1. - t[1] as f32)));
+        }
+
+        let indices: Vec<usize> = match prim.indices {
+            Some(i) => self.read(i, &["SCALAR"], 0)?.0.iter().map(|&i| i as usize).collect(),
+            None => (0..count).collect(),
+        };
+        let triangles: Vec<[usize; 3]> = match prim.mode {
+            4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // strips alternate their winding
+            5 => (2..indices.len())
+                     .map(|i| if i % 2 == 0 {
+                              [indices[i - 2], indices[i - 1], indices[i]]
+                          } else {
+                              [indices[i - 1], indices[i - 2], indices[i]]
+                          })
+                     .collect(),
+            6 => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+            // points and lines have no surface to render
+            _ => vec![],
+        };
+        let material = prim.material.map_or(0, |m| m + 1);
+        let mirrored = m.determinant() < 0.;
+        for t in triangles.iter() {
+            if let Some(&i) = t.iter().find(|&&i| i >= count) {
+                return Err(invalid(format!("index {} out of range ({} vertices)", i, count)));
+            }
+            // a mirroring transform flips the winding, which decides the facing
+            let t = if mirrored { [t[0], t[2], t[1]] } else { *t };
+            let offset = |base: usize| [base + t[0], base + t[1], base + t[2]];
+            data.faces.push(Face {
+                                positions: offset(first),
+                                normals: normals.as_ref().map(|_| offset(first_normal)),
+                                uvs: uvs.as_ref().map(|_| offset(first_uv)),
+                                material,
+                            });
+        }
+        Ok(())
+    }
+
+    fn node(&self,
+            index: usize,
+            parent: &Transform,
+            depth: usize,
+            data: &mut MeshData,
+            cameras: &mut Vec<GltfCamera>)
+            -> io::Result<()> {
+        let node = self.doc.nodes.get(index).ok_or_else(|| invalid(format!("missing node {}", index)))?;
+        if depth > self.doc.nodes.len() {
+            return Err(invalid(format!("node {} is its own ancestor", index)));
+        }
+        let m = match Transform::from_matrix(local_matrix(node)) {
+            Some(local) => parent.clone() * local,
+            // a zero scale hides the node and everything below it
+            None => return Ok(()),
+        };
+        if let Some(mesh) = node.mesh {
+            let def = self.doc.meshes.get(mesh).ok_or_else(|| invalid(format!("node {}: missing mesh {}", index, mesh)))?;
+            for (p, prim) in def.primitives.iter().enumerate() {
+                self.primitive(prim, &m, data)
+                    .map_err(|e| io::Error::new(e.kind(), format!("mesh {} primitive {}: {}", mesh, p, e)))?;
+            }
+        }
+        if let Some(camera) = node.camera {
+            let def = self.doc
+                          .cameras
+                          .get(camera)
+                          .ok_or_else(|| invalid(format!("node {}: missing camera {}", index, camera)))?;
+            // orthographic cameras have no equivalent and are skipped
+            if let (Some(p), "perspective") = (def.perspective.as_ref(), def.kind.as_str()) {
+                cameras.push(GltfCamera {
+                                 lookfrom: m.point(&Vec3::new(0., 0., 0.)),
+                                 lookat: m.point(&Vec3::new(0., 0., -1.)),
+                                 vup: unit_vector(m.vector(&Vec3::new(0., 1., 0.))),
+                                 vfov: p.yfov.to_degrees(),
+                                 aspect: p.aspect_ratio,
+                             });
+            }
+        }
+        for &child in node.children.iter() {
+            self.node(child, &m, depth + 1, data, cameras)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a scene from glTF JSON. `bin` is the binary chunk of a `.glb`, used by a buffer without
+/// a `uri`; other buffers and images are read relative to `base_dir`. Primitives without a
+/// material use `default_mat`.
+pub fn parse_gltf(json: &[u8],
+                  bin: Option<&[u8]>,
+                  base_dir: &Path,
+                  default_mat: Arc<dyn Material>)
+                  -> io::Result<GltfScene> {
+    let doc: Document = serde_json::from_slice(json).map_err(|e| invalid(e.to_string()))?;
+    let mut buffers = vec![];
+    for (i, buffer) in doc.buffers.iter().enumerate() {
+        let data = match (&buffer.uri, bin) {
+            (Some(uri), _) => read_uri(uri, base_dir),
+            (None, Some(bin)) => Ok(bin.to_vec()),
+            (None, None) => Err(invalid("no uri and no binary chunk".to_string())),
+        };
+        buffers.push(data.map_err(|e| io::Error::new(e.kind(), format!("buffer {}: {}", i, e)))?);
+    }
+    let loader = Loader {
+        doc: &doc,
+        buffers,
+        base_dir,
+    };
+
+    let mut data = MeshData {
+        materials: vec![default_mat],
+        ..MeshData::default()
+    };
+    for (i, def) in doc.materials.iter().enumerate() {
+        let mat = loader.material(def)
+                        .map_err(|e| io::Error::new(e.kind(), format!("material {}: {}", i, e)))?;
+        data.materials.push(mat);
+    }
+    let roots = match doc.scenes.get(doc.scene.unwrap_or(0)) {
+        Some(scene) => scene.nodes.clone(),
+        // without scenes, every node that is nobody's child is a root
+        None => {
+            (0..doc.nodes.len()).filter(|i| !doc.nodes.iter().any(|n| n.children.contains(i)))
+                                .collect()
+        }
+    };
+    let mut cameras = vec![];
+    for &root in roots.iter() {
+        loader.node(root, &Transform::identity(), 0, &mut data, &mut cameras)?;
+    }
+    let mesh = TriangleMesh::new(data).map_err(invalid)?;
+    Ok(GltfScene { mesh, cameras })
+}
+
+/// Splits a binary `.glb` into its JSON and binary chunks and builds the scene.
+pub fn parse_glb(bytes: &[u8], base_dir: &Path, default_mat: Arc<dyn Material>) -> io::Result<GltfScene> {
+    let word = |at: usize| -> io::Result<u32> {
+        bytes.get(at..at + 4)
+             .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+             .ok_or_else(|| invalid("truncated glb".to_string()))
+    };
+    if !bytes.starts_with(b"glTF") {
+        return Err(invalid("not a binary glTF file".to_string()));
+    }
+    if word(4)? != 2 {
+        return Err(invalid(format!("unsupported glb version {}", word(4)?)));
+    }
+    let mut json = None;
+    let mut bin = None;
+    let mut at = 12;
+    while at < bytes.len() {
+        let length = word(at)? as usize;
+        let kind = word(at + 4)?;
+        let chunk = bytes.get(at + 8..at + 8 + length).ok_or_else(|| invalid("truncated glb chunk".to_string()))?;
+        match kind {
+            0x4E4F_534A => json = Some(chunk),
+            0x004E_4942 => bin = Some(chunk),
+            _ => {}
+        }
+        at += 8 + length;
+    }
+    let json = json.ok_or_else(|| invalid("glb without a JSON chunk".to_string()))?;
+    parse_gltf(json, bin, base_dir, default_mat)
+}
+
+/// Loads a `.gltf` (with its `.bin` and image files) or a `.glb`.
+pub fn load<P: AsRef<Path>>(path: P, default_mat: Arc<dyn Material>) -> io::Result<GltfScene> {
+    let path = path.as_ref();
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(b"glTF") {
+        parse_glb(&bytes, base_dir, default_mat)
+    } else {
+        parse_gltf(&bytes, None, base_dir, default_mat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::{decode_base64, parse_glb, parse_gltf};
+    use utils::film::Film;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::tonemap::DisplayTransform;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    /// One triangle in the xy plane, drawn by a node moved to z = -5 and a mirrored copy, seen by
+    /// a camera at z = 2.
+    const JSON: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 3]}],
+        "nodes": [
+            {"translation": [0, 0, -5], "children": [1, 2]},
+            {"mesh": 0},
+            {"mesh": 0, "scale": [-1, 1, 1]},
+            {"camera": 0, "translation": [0, 0, 2]}
+        ],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "TEXCOORD_0": 1}, "indices": 2, "material": 0}]}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}, "metallicFactor": 0}}],
+        "textures": [{"source": 0}],
+        "images": [{"bufferView": 3, "mimeType": "image/png"}],
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 0.5, "znear": 0.1}}],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+            {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"},
+            {"bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 24},
+            {"buffer": 0, "byteOffset": 60, "byteLength": 6},
+            {"buffer": 0, "byteOffset": 68, "byteLength": PNG_LENGTH}
+        ],
+        "buffers": [{"byteLength": BUFFER_LENGTH}]
+    }"#;
+
+    fn glb() -> Vec<u8> {
+        let mut bin = vec![];
+        for v in [0f32, 0., 0., 1., 0., 0., 0., 1., 0., 0., 1., 1., 1., 0., 0.].iter() {
+            bin.extend_from_slice(&v.to_le_bytes());
+        }
+        for i in [0u16, 1, 2, 0].iter() {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        let mut film = Film::new(1, 1);
+        film.add_sample(0, 0, Vec3::new(1., 0., 0.));
+        let mut png = vec![];
+        film.write_to(&mut png, "png".parse().unwrap(), &DisplayTransform::default()).unwrap();
+        let png_length = png.len();
+        bin.extend(png);
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let mut json = JSON.replace("PNG_LENGTH", &png_length.to_string())
+                           .replace("BUFFER_LENGTH", &bin.len().to_string())
+                           .into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut out = b"glTF".to_vec();
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"JSON");
+        out.extend(json);
+        out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"BIN\0");
+        out.extend(bin);
+        out
+    }
+
+    #[test]
+    fn loads_nodes_materials_textures_and_cameras() {
+        let mut sampler = Sampler::new(0);
+        let scene = parse_glb(&glb(), Path::new("."), dummy()).unwrap();
+        assert_eq!(scene.mesh.len(), 2);
+        assert_eq!(scene.mesh.data.materials[1].name(), "lambertian");
+
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0.2, 0.2, 0.), &Vec3::new(0., 0., -1.));
+        assert!(scene.mesh.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 5.).abs() < 1e-5);
+        assert!((rec.u - 0.2).abs() < 1e-5 && (rec.v - 0.2).abs() < 1e-5, "{} {}", rec.u, rec.v);
+        // the mirrored copy on the other side of x = 0 still faces +z
+        let mirrored = Ray::new(&Vec3::new(-0.2, 0.2, 0.), &Vec3::new(0., 0., -1.));
+        assert!(scene.mesh.hit(&mirrored, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!(rec.normal.z() > 0.99, "{:?}", rec.normal);
+
+        assert_eq!(scene.cameras.len(), 1);
+        let cam = &scene.cameras[0];
+        assert_eq!(cam.lookfrom, Vec3::new(0., 0., 2.));
+        assert_eq!(cam.lookat, Vec3::new(0., 0., 1.));
+        assert!((cam.vfov - 0.5f32.to_degrees()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn reports_broken_references() {
+        let no_buffer = JSON.replace("PNG_LENGTH", "10").replace("BUFFER_LENGTH", "100");
+        let msg = parse_gltf(no_buffer.as_bytes(), None, Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("buffer 0"), "{}", msg);
+        let short = no_buffer.replace("\"baseColorTexture\": {\"index\": 0}, ", "")
+                             .replace("\"count\": 3, \"type\": \"VEC3\"", "\"count\": 30, \"type\": \"VEC3\"");
+        let msg = parse_gltf(short.as_bytes(), Some(&[0; 100]), Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("accessor 0"), "{}", msg);
+        // sizes that overflow are errors rather than panics
+        let huge = short.replace("\"count\": 30,", "\"count\": 18446744073709551615,");
+        let msg = parse_gltf(huge.as_bytes(), Some(&[0; 100]), Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("18446744073709551615 elements"), "{}", msg);
+        // without a buffer view nothing backs the count, so it is not trusted either
+        let unbacked = short.replace("{\"bufferView\": 0, \"componentType\": 5126, \"count\": 30,",
+                                     "{\"componentType\": 5126, \"count\": 4000000000000,");
+        let msg = parse_gltf(unbacked.as_bytes(), Some(&[0; 100]), Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("accessor 0: 4000000000000 elements without a buffer view"), "{}", msg);
+        let unbacked = no_buffer.replace("\"baseColorTexture\": {\"index\": 0}, ", "")
+                                .replace("{\"bufferView\": 1, \"componentType\": 5126, \"count\": 3,",
+                                         "{\"componentType\": 5126, \"count\": 4000000000000,");
+        let msg = parse_gltf(unbacked.as_bytes(), Some(&[0; 100]), Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("accessor 1: 4000000000000 elements"), "{}", msg);
+        let far = no_buffer.replace("\"baseColorTexture\": {\"index\": 0}, ", "")
+                           .replace("\"byteLength\": 24", "\"byteLength\": 18446744073709551615");
+        let msg = parse_gltf(far.as_bytes(), Some(&[0; 100]), Path::new("."), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("buffer view 1"), "{}", msg);
+    }
+
+    #[test]
+    fn decodes_base64() {
+        assert_eq!(decode_base64("aGVsbG8gd29ybGQ="), Some(b"hello world".to_vec()));
+        assert_eq!(decode_base64("AAEC/w=="), Some(vec![0, 1, 2, 255]));
+        assert_eq!(decode_base64("a$b"), None);
+    }
+}
@@ -1,20 +1,28 @@
 use std::vec::Vec;
+use std::sync::Arc;
 use utils::{vec3, ray, material};
+use utils::aabb::{Aabb, surrounding_box};
+use utils::random::Sampler;
 
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct HitRecord {
     pub t: f32,
+    /// Surface coordinates of the hit, used for texture lookups.
+    pub u: f32,
+    pub v: f32,
     pub p: vec3::Vec3,
     pub normal: vec3::Vec3,
-    pub mat: Box<material::Material>,
+    pub mat: Arc<dyn material::Material>,
 }
 
 #[allow(dead_code)]
 impl HitRecord {
-    pub fn new(m: Box<material::Material>) -> Self {
+    pub fn new(m: Arc<dyn material::Material>) -> Self {
         Self {
             t: 0.,
+            u: 0.,
+            v: 0.,
             p: vec3::Vec3::new(0., 0., 0.),
             normal: vec3::Vec3::new(0., 0., 0.),
             mat: m,
@@ -22,34 +30,124 @@ impl HitRecord {
     }
 }
 
-pub trait Hitable {
-    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool;
+#[allow(dead_code)]
+pub trait Hitable: Send + Sync {
+    /// `sampler` supplies the random numbers of objects that decide at random where a ray stops,
+    /// such as participating media, so they follow the render's seed.
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool;
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool;
+    /// Fraction of the light travelling along `r` between `t_min` and `t_max` that gets through,
+    /// for shadow rays. Solid objects block all of it wherever `r` hits them; participating media
+    /// let part of it through instead of being hit.
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        let mut rec = HitRecord::new(material::dummy());
+        if self.hit(r, t_min, t_max, &mut rec, sampler) {
+            0.
+        } else {
+            1.
+        }
+    }
+    /// Density per unit solid angle with which `random` picks `direction` from `origin`. Zero if
+    /// the direction misses, or for shapes that cannot be sampled as lights.
+    fn pdf_value(&self, _origin: &vec3::Vec3, _direction: &vec3::Vec3) -> f32 {
+        0.
+    }
+    /// A direction from `origin` towards a random point on the shape, for sampling it as a light.
+    /// Only meaningful where `pdf_value` is implemented.
+    fn random(&self, _origin: &vec3::Vec3, _sampler: &mut Sampler) -> vec3::Vec3 {
+        vec3::Vec3::new(1., 0., 0.)
+    }
 }
 
+macro_rules! forward_hitable {
+    ($pointer:ident) => {
+        impl<H: Hitable + ?Sized> Hitable for $pointer<H> {
+            fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+                (**self).hit(r, t_min, t_max, rec, sampler)
+            }
+
+            fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+                (**self).bounding_box(t0, t1, output_box)
+            }
+
+            fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+                (**self).transmittance(r, t_min, t_max, sampler)
+            }
+
+            fn pdf_value(&self, origin: &vec3::Vec3, direction: &vec3::Vec3) -> f32 {
+                (**self).pdf_value(origin, direction)
+            }
+
+            fn random(&self, origin: &vec3::Vec3, sampler: &mut Sampler) -> vec3::Vec3 {
+                (**self).random(origin, sampler)
+            }
+        }
+    };
+}
+
+// Lets wrappers such as `FlipFace` hold objects whose type is only known at run time, and lets a
+// light be shared between the world and the list of lights that is sampled directly.
+forward_hitable!(Box);
+forward_hitable!(Arc);
+
+/// Reverses the normals of `H`, e.g. to make a one-sided rectangle face the other way.
 #[allow(dead_code)]
-pub struct HitableList {
-    pub list: Vec<Box<Hitable>>,
+pub struct FlipFace<H: Hitable> {
+    pub inner: H,
+}
+
+#[allow(dead_code)]
+impl<H: Hitable> FlipFace<H> {
+    pub fn new(inner: H) -> Self {
+        Self { inner }
+    }
 }
 
-#[cfg(feature = "concurrency")]
-unsafe impl Sync for HitableList {}
-#[cfg(feature = "concurrency")]
-unsafe impl Send for HitableList {}
+impl<H: Hitable> Hitable for FlipFace<H> {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !self.inner.hit(r, t_min, t_max, rec, sampler) {
+            return false;
+        }
+        rec.normal = -rec.normal.clone();
+        true
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        self.inner.bounding_box(t0, t1, output_box)
+    }
+
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        self.inner.transmittance(r, t_min, t_max, sampler)
+    }
+
+    fn pdf_value(&self, origin: &vec3::Vec3, direction: &vec3::Vec3) -> f32 {
+        self.inner.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &vec3::Vec3, sampler: &mut Sampler) -> vec3::Vec3 {
+        self.inner.random(origin, sampler)
+    }
+}
+
+#[allow(dead_code)]
+pub struct HitableList {
+    pub list: Vec<Box<dyn Hitable>>,
+}
 
 #[allow(dead_code)]
 impl HitableList {
-    pub fn new(hitable: Vec<Box<Hitable>>) -> Self {
+    pub fn new(hitable: Vec<Box<dyn Hitable>>) -> Self {
         Self { list: hitable }
     }
 }
 
 impl Hitable for HitableList {
-    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
         let mut temp_rec = HitRecord::new(rec.mat.clone());
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
         for h in self.list.iter() {
-            if h.hit(r, t_min, closest_so_far, &mut temp_rec) {
+            if h.hit(r, t_min, closest_so_far, &mut temp_rec, sampler) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
                 *rec = temp_rec.clone();
@@ -57,4 +155,81 @@ impl Hitable for HitableList {
         }
         hit_anything
     }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        if self.list.is_empty() {
+            return false;
+        }
+        let mut temp_box = Aabb::default();
+        let mut first_box = true;
+        for h in self.list.iter() {
+            if !h.bounding_box(t0, t1, &mut temp_box) {
+                return false;
+            }
+            *output_box = if first_box {
+                temp_box.clone()
+            } else {
+                surrounding_box(output_box, &temp_box)
+            };
+            first_box = false;
+        }
+        true
+    }
+
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        let mut tr = 1.;
+        for h in self.list.iter() {
+            tr *= h.transmittance(r, t_min, t_max, sampler);
+            if tr == 0. {
+                break;
+            }
+        }
+        tr
+    }
+
+    /// The list samples each of its objects equally often.
+    fn pdf_value(&self, origin: &vec3::Vec3, direction: &vec3::Vec3) -> f32 {
+        if self.list.is_empty() {
+            return 0.;
+        }
+        self.list.iter().map(|h| h.pdf_value(origin, direction)).sum::<f32>() / self.list.len() as f32
+    }
+
+    fn random(&self, origin: &vec3::Vec3, sampler: &mut Sampler) -> vec3::Vec3 {
+        let n = self.list.len();
+        if n == 0 {
+            return vec3::Vec3::new(1., 0., 0.);
+        }
+        let i = ((sampler.drand48() * n as f32) as usize).min(n - 1);
+        self.list[i].random(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use super::{Hitable, HitableList, HitRecord};
+    use utils::material::{dummy, Lambertian};
+    use utils::ray::Ray;
+    use utils::sphere::Sphere;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn hitable_list_can_cross_threads() {
+        assert_send_sync::<HitableList>();
+        assert_send_sync::<Box<dyn Hitable>>();
+
+        let list = HitableList::new(vec![get_sphere!(Lambertian, Vec3::new(0.5, 0.5, 0.5), Vec3::new(0., 0., -1.), 0.5)]);
+        let r = Ray::new(&Vec3::new(0., 0., 0.), &Vec3::new(0., 0., -1.));
+        let mut sampler = Sampler::new(0);
+        let hit = thread::scope(|s| {
+                                    s.spawn(|| list.hit(&r, 0.001, f32::MAX, &mut HitRecord::new(dummy()), &mut sampler))
+                                     .join()
+                                     .unwrap()
+                                });
+        assert!(hit);
+    }
 }
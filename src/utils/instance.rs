@@ -0,0 +1,236 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::Aabb;
+use utils::ray::Ray;
+use utils::random::Sampler;
+use utils::vec3::{unit_vector, Vec3};
+use utils::transform::Transform;
+
+/// Places shared geometry in the world through an affine transform. Rays are moved into object
+/// space for the hit test, and the hit point and normal are moved back out, so many instances can
+/// point at the same `Arc`.
+#[allow(dead_code)]
+pub struct Instance<H: Hitable + ?Sized> {
+    pub object: Arc<H>,
+    to_world: Transform,
+    to_object: Transform,
+}
+
+#[allow(dead_code)]
+impl<H: Hitable + ?Sized> Instance<H> {
+    pub fn new(object: Arc<H>, transform: Transform) -> Self {
+        Self {
+            object,
+            to_object: transform.inverse(),
+            to_world: transform,
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.to_world
+    }
+}
+
+impl<H: Hitable + ?Sized> Hitable for Instance<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        if !self.object.hit(&self.to_object.ray(r), t_min, t_max, rec, sampler) {
+            return false;
+        }
+        rec.p = self.to_world.point(&rec.p);
+        rec.normal = unit_vector(self.to_world.normal(&rec.normal));
+        true
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        self.object.transmittance(&self.to_object.ray(r), t_min, t_max, sampler)
+    }
+
+    /// The object's pdf in object space, converted to world-space solid angle. The linear part `A`
+    /// of the transform stretches the solid angle around a unit direction `ω` by |det A| / |A ω|³,
+    /// which is one for rigid motions.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+        let local = self.to_object.vector(direction);
+        let pdf = self.object.pdf_value(&self.to_object.point(origin), &local);
+        let stretch = direction.len() / local.len();
+        pdf * stretch * stretch * stretch / self.to_world.determinant().abs()
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        self.to_world.vector(&self.object.random(&self.to_object.point(origin), sampler))
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::default();
+        if !self.object.bounding_box(t0, t1, &mut object_box) {
+            return false;
+        }
+        *output_box = self.to_world.bounding_box(&object_box);
+        true
+    }
+}
+
+macro_rules! transform_wrapper {
+    ($(#[$doc:meta])* $name:ident, $arg:ident: $ty:ty, $transform:expr) => {
+        $(#[$doc])*
+        #[allow(dead_code)]
+        pub struct $name<H: Hitable> {
+            instance: Instance<H>,
+        }
+
+        #[allow(dead_code)]
+        impl<H: Hitable> $name<H> {
+            pub fn new(object: H, $arg: $ty) -> Self {
+                Self { instance: Instance::new(Arc::new(object), $transform) }
+            }
+        }
+
+        impl<H: Hitable> Hitable for $name<H> {
+            fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+                self.instance.hit(r, t_min, t_max, rec, sampler)
+            }
+
+            fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+                self.instance.bounding_box(t0, t1, output_box)
+            }
+
+            fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+                self.instance.transmittance(r, t_min, t_max, sampler)
+            }
+
+            fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+                self.instance.pdf_value(origin, direction)
+            }
+
+            fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+                self.instance.random(origin, sampler)
+            }
+        }
+    };
+}
+
+transform_wrapper!(
+    /// Moves `H` by `offset`.
+    Translate, offset: Vec3, Transform::translate(&offset));
+transform_wrapper!(
+    /// Rotates `H` about the x axis by `degrees`, counter-clockwise looking down the axis.
+    RotateX, degrees: f32, Transform::rotate_x(degrees));
+transform_wrapper!(
+    /// Rotates `H` about the y axis by `degrees`, counter-clockwise looking down the axis.
+    RotateY, degrees: f32, Transform::rotate_y(degrees));
+transform_wrapper!(
+    /// Rotates `H` about the z axis by `degrees`, counter-clockwise looking down the axis.
+    RotateZ, degrees: f32, Transform::rotate_z(degrees));
+transform_wrapper!(
+    /// Scales `H` about the origin by a non-zero factor per axis.
+    Scale, factors: Vec3, Transform::scale(&factors));
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Instance, RotateY, Scale, Translate};
+    use utils::aabb::Aabb;
+    use utils::bvh::BvhNode;
+    use utils::hitable::{Hitable, HitableList, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::rect::BoxShape;
+    use utils::sphere::Sphere;
+    use utils::transform::Transform;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+    use utils::sampling::{random_unit_vector, uniform_sphere_pdf};
+
+    fn unit_box() -> BoxShape {
+        BoxShape::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.), dummy())
+    }
+
+    #[test]
+    fn translated_and_rotated_boxes_hit_in_world_space() {
+        let mut sampler = Sampler::new(0);
+        let mut rec = HitRecord::new(dummy());
+        let moved = Translate::new(unit_box(), Vec3::new(10., 0., 0.));
+        let r = Ray::new(&Vec3::new(10.5, 0.5, 5.), &Vec3::new(0., 0., -1.));
+        assert!(moved.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert_eq!(rec.t, 4.);
+        assert_eq!(rec.p, Vec3::new(10.5, 0.5, 1.));
+
+        // a quarter turn about y sends the +x face to -z
+        let turned = RotateY::new(unit_box(), 90.);
+        let r = Ray::new(&Vec3::new(0.5, 0.5, -5.), &Vec3::new(0., 0., 1.));
+        assert!(turned.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 4.).abs() < 1e-5 && (rec.normal.z() + 1.).abs() < 1e-5, "{} {:?}", rec.t, rec.normal);
+        let mut bbox = Aabb::default();
+        assert!(turned.bounding_box(0., 1., &mut bbox));
+        assert!(bbox.min.z() < -0.99 && bbox.max.z().abs() < 1e-5, "{:?}", bbox);
+    }
+
+    #[test]
+    fn scaled_spheres_keep_unit_normals() {
+        let mut sampler = Sampler::new(0);
+        let mut rec = HitRecord::new(dummy());
+        let flat = Scale::new(Sphere::new(Vec3::new(0., 0., 0.), 1., dummy()), Vec3::new(4., 1., 1.));
+        let r = Ray::new(&Vec3::new(3., 0., 5.), &Vec3::new(0., 0., -1.));
+        assert!(flat.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.normal.len() - 1.).abs() < 1e-5);
+        assert!(rec.normal.x() > 0.1 && rec.normal.z() > 0.1, "{:?}", rec.normal);
+    }
+
+    #[test]
+    fn shared_instances_work_inside_a_bvh() {
+        let mut sampler = Sampler::new(0);
+        let shared: Arc<dyn Hitable> = Arc::new(unit_box());
+        let place = |i: usize| {
+            Transform::translate(&Vec3::new(3. * i as f32, 0., 0.)) * Transform::rotate_z(20. * i as f32)
+        };
+        let copies: Vec<Box<dyn Hitable>> =
+            (0..20).map(|i| Box::new(Instance::new(shared.clone(), place(i))) as Box<dyn Hitable>)
+                   .collect();
+        let bvh = BvhNode::new(HitableList::new(copies), 0., 1.);
+        let mut rec = HitRecord::new(dummy());
+        for i in 0..20 {
+            let center = place(i).point(&Vec3::new(0.5, 0.5, 0.5));
+            let r = Ray::new(&Vec3::new(center.x(), center.y(), 5.), &Vec3::new(0., 0., -1.));
+            assert!(bvh.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler), "copy {}", i);
+            assert!((rec.t - 4.).abs() < 1e-4);
+        }
+        assert_eq!(Arc::strong_count(&shared), 21);
+    }
+
+    #[test]
+    fn transformed_lights_are_sampled_in_world_space() {
+        let mut sampler = Sampler::new(2);
+        let ball = Sphere::new(Vec3::new(0., 0., 0.), 1., dummy());
+        let transform = Transform::translate(&Vec3::new(0.5, 0., -3.)) * Transform::rotate_y(30.) *
+                        Transform::scale(&Vec3::new(2., 0.5, 1.));
+        let light = Instance::new(Arc::new(ball), transform);
+        let origin = Vec3::new(0., 0., 0.);
+        let n = 200000;
+        // uniform directions: the fraction that hits gives the solid angle, and the pdf integrates to one
+        let (mut hits, mut integral) = (0, 0.);
+        for _ in 0..n {
+            let d = random_unit_vector(&mut sampler);
+            let mut rec = HitRecord::new(dummy());
+            hits += light.hit(&Ray::new(&origin, &d), 0.001, f32::MAX, &mut rec, &mut sampler) as usize;
+            integral += light.pdf_value(&origin, &d) / uniform_sphere_pdf();
+        }
+        let solid_angle = hits as f32 / n as f32 / uniform_sphere_pdf();
+        assert!((integral / n as f32 - 1.).abs() < 0.02, "{}", integral / n as f32);
+
+        // sampled directions hit the light, and weighing them by 1 / pdf gives the same solid angle
+        let mut estimate = 0.;
+        for _ in 0..n {
+            let d = light.random(&origin, &mut sampler);
+            let mut rec = HitRecord::new(dummy());
+            assert!(light.hit(&Ray::new(&origin, &d), 0.001, f32::MAX, &mut rec, &mut sampler));
+            estimate += 1. / light.pdf_value(&origin, &d);
+        }
+        let estimate = estimate / n as f32;
+        assert!((estimate - solid_angle).abs() < 0.02 * solid_angle, "{} vs {}", estimate, solid_angle);
+
+        // rigid motions leave the pdf as it is
+        let moved = Translate::new(Sphere::new(Vec3::new(0., 0., 0.), 1., dummy()), Vec3::new(0., 0., -3.));
+        let plain = Sphere::new(Vec3::new(0., 0., -3.), 1., dummy());
+        let d = Vec3::new(0.1, 0.2, -1.);
+        assert!((moved.pdf_value(&origin, &d) - plain.pdf_value(&origin, &d)).abs() < 1e-4);
+    }
+}
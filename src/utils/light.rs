@@ -0,0 +1,256 @@
+use std::f32::consts::PI;
+use utils::vec3::{dot, unit_vector, Vec3};
+use utils::hitable::Hitable;
+use utils::ray::Ray;
+use utils::random::Sampler;
+
+/// Light from a `Light` arriving at a point, before anything in the way is accounted for.
+#[derive(Clone, Debug)]
+pub struct LightSample {
+    /// Unit vector from the point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light; `f32::MAX` for lights at infinity.
+    pub distance: f32,
+    /// Irradiance on a surface facing the light, in W/m² per color channel.
+    pub irradiance: Vec3,
+}
+
+/// A light that is a single point or a single direction. No ray can hit one by chance, so it only
+/// lights the scene through shadow rays, and each point is lit by it from exactly one direction.
+/// Lengths are taken to be meters.
+pub trait Light: Send + Sync {
+    /// Light arriving at `p`, or `None` where this light does not shine.
+    fn illuminate(&self, p: &Vec3) -> Option<LightSample>;
+}
+
+/// Fraction of the light of `sample`, taken at the start of `shadow`, that gets to it past
+/// everything in `world`: zero behind solid objects, and dimmed by any media in between.
+pub fn visibility(world: &dyn Hitable, shadow: &Ray, sample: &LightSample, sampler: &mut Sampler) -> f32 {
+    let t_max = if sample.distance < f32::MAX { sample.distance - 0.001 } else { f32::MAX };
+    world.transmittance(shadow, 0.001, t_max, sampler)
+}
+
+/// Shines equally in every direction. `intensity` is in W/sr.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+}
+
+#[allow(dead_code)]
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self { position, intensity }
+    }
+
+    /// Point light that emits `power` watts in total.
+    pub fn from_power(position: Vec3, power: Vec3) -> Self {
+        Self::new(position, power / (4. * PI))
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, p: &Vec3) -> Option<LightSample> {
+        let to_light = self.position.clone() - p.clone();
+        let distance = to_light.len();
+        if distance <= 0. {
+            return None;
+        }
+        Some(LightSample {
+                 direction: to_light / distance,
+                 distance,
+                 irradiance: self.intensity.clone() / (distance * distance),
+             })
+    }
+}
+
+/// Point light shining into a cone around `direction`. The cone's half-angle is `cos_total`'s
+/// angle; the light fades smoothly to nothing from `cos_falloff_start` on. `intensity` is in W/sr
+/// at the center of the cone.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub cos_total: f32,
+    pub cos_falloff_start: f32,
+}
+
+#[allow(dead_code)]
+impl SpotLight {
+    /// `angle` is the half-angle of the cone and `falloff_start` the angle where the light starts
+    /// fading, both in degrees; a `falloff_start` at or beyond `angle` gives a hard edge. Angles
+    /// are kept within 0 and 180 degrees, and a NaN one counts as 0.
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, angle: f32, falloff_start: f32) -> Self {
+        // `clamp` passes NaN on, and panics on it as a bound.
+        let degrees = |a: f32, max: f32| if a.is_nan() { 0. } else { a.clamp(0., max) };
+        let angle = degrees(angle, 180.);
+        Self {
+            position,
+            direction: unit_vector(direction),
+            intensity,
+            cos_total: angle.to_radians().cos(),
+            cos_falloff_start: degrees(falloff_start, angle).to_radians().cos(),
+        }
+    }
+
+    /// Spot light that emits `power` watts in total into its cone. `None` when the cone is too
+    /// narrow to hold any solid angle in `f32`, as no finite intensity would send out the power.
+    pub fn from_power(position: Vec3, direction: Vec3, power: Vec3, angle: f32, falloff_start: f32) -> Option<Self> {
+        let mut light = Self::new(position, direction, Vec3::new(0., 0., 0.), angle, falloff_start);
+        let solid_angle = light.power_per_intensity();
+        if solid_angle <= 0. {
+            return None;
+        }
+        light.intensity = power / solid_angle;
+        Some(light)
+    }
+
+    /// Fraction of `intensity` sent out at an angle from the axis whose cosine is `cos_theta`.
+    pub fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.;
+        }
+        if cos_theta <= self.cos_total {
+            return 0.;
+        }
+        let t = (cos_theta - self.cos_total) / (self.cos_falloff_start - self.cos_total);
+        t * t * (3. - 2. * t)
+    }
+
+    /// Solid angle the light would need to cover at full intensity to send out the same power.
+    /// The smoothstep falloff is symmetric in the cosine, so it counts as half of its band.
+    fn power_per_intensity(&self) -> f32 {
+        2. * PI * (1. - 0.5 * (self.cos_falloff_start + self.cos_total))
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, p: &Vec3) -> Option<LightSample> {
+        let to_light = self.position.clone() - p.clone();
+        let distance = to_light.len();
+        if distance <= 0. {
+            return None;
+        }
+        let direction = to_light / distance;
+        let falloff = self.falloff(-dot(&direction, &self.direction));
+        if falloff <= 0. {
+            return None;
+        }
+        Some(LightSample {
+                 direction,
+                 distance,
+                 irradiance: self.intensity.clone() * (falloff / (distance * distance)),
+             })
+    }
+}
+
+/// Parallel light from infinitely far away, such as the sun, travelling along `direction`.
+/// `irradiance` is in W/m² on a surface facing the light.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+}
+
+#[allow(dead_code)]
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        Self {
+            direction: unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _p: &Vec3) -> Option<LightSample> {
+        Some(LightSample {
+                 direction: -self.direction.clone(),
+                 distance: f32::MAX,
+                 irradiance: self.irradiance.clone(),
+             })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::{visibility, DirectionalLight, Light, PointLight, SpotLight};
+    use utils::hitable::HitableList;
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::sphere::Sphere;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    #[test]
+    fn point_light_falls_off_with_the_square_of_the_distance() {
+        let light = PointLight::from_power(Vec3::new(0., 4., 0.), Vec3::new(4. * PI, 8. * PI, 0.));
+        assert_eq!(light.intensity, Vec3::new(1., 2., 0.));
+        let near = light.illuminate(&Vec3::new(0., 2., 0.)).unwrap();
+        assert_eq!(near.direction, Vec3::new(0., 1., 0.));
+        assert_eq!(near.distance, 2.);
+        assert_eq!(near.irradiance, Vec3::new(0.25, 0.5, 0.));
+        let far = light.illuminate(&Vec3::new(0., 0., 0.)).unwrap();
+        assert_eq!(far.irradiance, Vec3::new(1. / 16., 1. / 8., 0.));
+    }
+
+    #[test]
+    fn spot_light_fades_out_at_the_edge_of_its_cone() {
+        let spot = SpotLight::new(Vec3::new(0., 1., 0.), Vec3::new(0., -2., 0.), Vec3::new(1., 1., 1.), 30., 20.);
+        let at_angle = |degrees: f32| {
+            let t = degrees.to_radians();
+            spot.illuminate(&Vec3::new(t.sin(), 1. - t.cos(), 0.)).map_or(0., |s| s.irradiance.x())
+        };
+        assert!((at_angle(0.) - 1.).abs() < 1e-6);
+        assert!((at_angle(19.) - 1.).abs() < 1e-6);
+        assert!(at_angle(22.) < 1. && at_angle(22.) > at_angle(28.) && at_angle(28.) > 0.);
+        assert_eq!(at_angle(31.), 0.);
+        assert!(spot.illuminate(&Vec3::new(0., 2., 0.)).is_none());
+
+        // A NaN angle shuts the cone instead of panicking.
+        let shut = SpotLight::new(Vec3::new(0., 1., 0.), Vec3::new(0., -1., 0.), Vec3::new(1., 1., 1.), f32::NAN, 20.);
+        assert!(shut.illuminate(&Vec3::new(0.1, 0., 0.)).is_none());
+        let soft = SpotLight::new(Vec3::new(0., 1., 0.), Vec3::new(0., -1., 0.), Vec3::new(1., 1., 1.), 30., f32::NAN);
+        assert_eq!(soft.cos_falloff_start, 1.);
+
+        // Summing the intensity over the sphere gives back the power.
+        let (origin, axis, watts) = (Vec3::new(0., 0., 0.), Vec3::new(0., 0., 1.), Vec3::new(5., 5., 5.));
+        let spot = SpotLight::from_power(origin.clone(), axis.clone(), watts.clone(), 40., 10.).unwrap();
+        let n = 20000;
+        let power = (0..n).map(|i| {
+                              let cos_theta = 1. - 2. * (i as f32 + 0.5) / n as f32;
+                              spot.intensity.x() * spot.falloff(cos_theta)
+                          })
+                          .sum::<f32>() * 4. * PI / n as f32;
+        assert!((power - 5.).abs() < 0.1, "{}", power);
+        // a cone too narrow for f32 has no solid angle to spread the power over
+        assert!(SpotLight::from_power(origin, axis, watts, 1e-4, 1e-4).is_none());
+    }
+
+    #[test]
+    fn directional_light_is_the_same_everywhere_and_casts_shadows() {
+        let mut sampler = Sampler::new(0);
+        let sun = DirectionalLight::new(Vec3::new(0., -3., 0.), Vec3::new(2., 2., 2.));
+        let lit = sun.illuminate(&Vec3::new(5., -1., 7.)).unwrap();
+        assert_eq!(lit.direction, Vec3::new(0., 1., 0.));
+        assert_eq!(lit.irradiance, Vec3::new(2., 2., 2.));
+
+        let world = HitableList::new(vec![Box::new(Sphere::new(Vec3::new(0., 2., 0.), 0.5, dummy()))]);
+        let shadow = |p: Vec3| Ray::new(&p, &lit.direction);
+        assert_eq!(visibility(&world, &shadow(Vec3::new(0., 0., 0.)), &lit, &mut sampler), 0.);
+        assert_eq!(visibility(&world, &shadow(Vec3::new(1., 0., 0.)), &lit, &mut sampler), 1.);
+
+        // A point light only looks as far as itself.
+        let bulb = PointLight::new(Vec3::new(0., 1., 0.), Vec3::new(1., 1., 1.));
+        let sample = bulb.illuminate(&Vec3::new(0., 0., 0.)).unwrap();
+        let toward = Ray::new(&Vec3::new(0., 0., 0.), &sample.direction);
+        assert_eq!(visibility(&world, &toward, &sample, &mut sampler), 1.);
+        let between = HitableList::new(vec![Box::new(Sphere::new(Vec3::new(0., 0.5, 0.), 0.1, dummy()))]);
+        assert_eq!(visibility(&between, &toward, &sample, &mut sampler), 0.);
+    }
+}
@@ -1,12 +1,44 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, OnceLock};
 use utils::vec3::{dot, unit_vector, Vec3};
 use utils::ray::Ray;
-use utils::sphere::random_in_unit_sphere;
+use utils::sampling::{cosine_hemisphere_pdf, random_cosine_direction, random_in_unit_sphere, Onb};
 use utils::hitable::HitRecord;
-use utils::random::drand48;
+use utils::random::Sampler;
+use utils::texture::{ConstantTexture, Texture};
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool;
-    fn box_clone(&self) -> Box<Material>;
+/// A direction picked by `Material::scatter`.
+#[derive(Clone, Debug)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// Fraction of the light arriving along `ray` that is sent back along the incoming ray,
+    /// already divided by `pdf`: `eval / pdf` for any material that is not specular.
+    pub attenuation: Vec3,
+    /// Density per unit solid angle with which `ray` was picked; zero when `specular`.
+    pub pdf: f32,
+    /// Whether `ray` is one of a few exact directions (a mirror or glass) that `eval` and `pdf`
+    /// cannot describe, so lights can't be sampled at this hit.
+    pub specular: bool,
+}
+
+pub trait Material: Send + Sync {
+    /// Picks the direction the incoming ray continues in, or `None` if it is absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord>;
+    /// Radiance leaving the surface on its own, added whether or not the ray scatters.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(0., 0., 0.)
+    }
+    /// Light sent back along `r_in` per unit of radiance arriving along `scattered`, per unit
+    /// solid angle: the BSDF times the cosine, or the albedo times the phase function in a medium.
+    /// Zero for specular materials.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Vec3 {
+        Vec3::new(0., 0., 0.)
+    }
+    /// Density per unit solid angle with which `scatter` picks `scattered`. Zero for specular
+    /// materials.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
+        0.
+    }
     fn name(&self) -> String;
 }
 
@@ -16,12 +48,6 @@ pub struct DummyMat {
     pub albedo: Vec3,
 }
 
-impl Clone for Box<Material> {
-    fn clone(&self) -> Box<Material> {
-        self.box_clone()
-    }
-}
-
 #[allow(dead_code)]
 impl DummyMat {
     pub fn new() -> Self {
@@ -29,12 +55,21 @@ impl DummyMat {
     }
 }
 
-impl Material for DummyMat {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _attenuation: &mut Vec3, _scattered: &mut Ray) -> bool {
-        true
+impl Default for DummyMat {
+    fn default() -> Self {
+        Self::new()
     }
-    fn box_clone(&self) -> Box<Material> {
-        Box::new((*self).clone())
+}
+
+/// Shared placeholder for `HitRecord`s that have not hit anything yet.
+pub fn dummy() -> Arc<dyn Material> {
+    static DUMMY: OnceLock<Arc<dyn Material>> = OnceLock::new();
+    DUMMY.get_or_init(|| Arc::new(DummyMat::new())).clone()
+}
+
+impl Material for DummyMat {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<ScatterRecord> {
+        None
     }
     fn name(&self) -> String {
         "dummy".to_string()
@@ -44,42 +79,59 @@ impl Material for DummyMat {
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Vec3,
+    pub albedo: Arc<dyn Texture>,
 }
 
 #[allow(dead_code)]
 impl Lambertian {
     pub fn new(a: Vec3) -> Self {
+        Self::textured(Arc::new(ConstantTexture::new(a)))
+    }
+
+    pub fn textured(a: Arc<dyn Texture>) -> Self {
         Self { albedo: a }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
-        let target = rec.normal.clone() + random_in_unit_sphere();
-        let s_ray = Ray::new(&rec.p, &target);
-        *scattered = s_ray.clone();
-        *attenuation = self.albedo.clone();
-        true
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let direction = Onb::from_w(&rec.normal).local(&random_cosine_direction(sampler));
+        let ray = Ray::with_time(&rec.p, &direction, r_in.time());
+        Some(ScatterRecord {
+                 pdf: self.pdf(r_in, rec, &ray),
+                 ray,
+                 attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+                 specular: false,
+             })
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, scattered)
     }
-    fn box_clone(&self) -> Box<Material> {
-        Box::new((*self).clone())
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        cosine_hemisphere_pdf(dot(&rec.normal, &unit_vector(scattered.direction().clone())))
     }
     fn name(&self) -> String {
         "lambertian".to_string()
     }
 }
 
+/// Reflects around the mirror direction: directions `fuzz` away from a perfect mirror are picked
+/// by moving the mirrored unit vector to a uniform point of a ball of radius `fuzz` around its tip.
+/// Rays pushed below the surface are absorbed. Without fuzz the metal is specular.
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct Metal {
-    pub albedo: Vec3,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f32,
 }
 
 #[allow(dead_code)]
 impl Metal {
     pub fn new(a: Vec3, f: f32) -> Self {
+        Self::textured(Arc::new(ConstantTexture::new(a)), f)
+    }
+
+    pub fn textured(a: Arc<dyn Texture>, f: f32) -> Self {
         Self {
             albedo: a,
             fuzz: if f < 1. { f } else { 1. },
@@ -88,16 +140,40 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
         let uv = unit_vector(r_in.direction().clone());
-        let reflected = reflect(&uv, &rec.normal) + random_in_unit_sphere() * self.fuzz;
-        let s_ray = Ray::new(&rec.p, &reflected);
-        *scattered = s_ray.clone();
-        *attenuation = self.albedo.clone();
-        dot(scattered.direction(), &rec.normal) > 0.
+        let reflected = reflect(&uv, &rec.normal) + random_in_unit_sphere(sampler) * self.fuzz;
+        let ray = Ray::with_time(&rec.p, &reflected, r_in.time());
+        if dot(ray.direction(), &rec.normal) <= 0. {
+            return None;
+        }
+        Some(ScatterRecord {
+                 pdf: self.pdf(r_in, rec, &ray),
+                 ray,
+                 attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+                 specular: self.fuzz <= 0.,
+             })
     }
-    fn box_clone(&self) -> Box<Material> {
-        Box::new((*self).clone())
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, scattered)
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        if self.fuzz <= 0. || dot(scattered.direction(), &rec.normal) <= 0. {
+            return 0.;
+        }
+        // The directions inside the cone seen from the origin cover the part of the ray that
+        // crosses the ball, so the density is that segment's share of the ball's volume.
+        let mirrored = reflect(&unit_vector(r_in.direction().clone()), &rec.normal);
+        let b = dot(&unit_vector(scattered.direction().clone()), &mirrored);
+        let discriminant = b * b - 1. + self.fuzz * self.fuzz;
+        if discriminant <= 0. {
+            return 0.;
+        }
+        let (near, far) = ((b - discriminant.sqrt()).max(0.), b + discriminant.sqrt());
+        if far <= 0. {
+            return 0.;
+        }
+        (far.powi(3) - near.powi(3)) / (4. * PI * self.fuzz.powi(3))
     }
     fn name(&self) -> String {
         "metal".to_string()
@@ -118,14 +194,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
         let outward_normal: Vec3;
         let reflected = reflect(r_in.direction(), &rec.normal);
         let ni_over_nt: f32;
         let mut refracted: Vec3 = Vec3::new(0., 0., 0.);
-        let reflect_prob: f32;
         let cosine: f32;
-        *attenuation = Vec3::new(1., 1., 1.);
 
         if dot(r_in.direction(), &rec.normal) > 0. {
             outward_normal = rec.normal.clone() * -1.;
@@ -134,30 +208,55 @@ impl Material for Dielectric {
         } else {
             outward_normal = rec.normal.clone();
             ni_over_nt = 1.0 / self.ref_idx;
-            cosine = dot(r_in.direction(), &rec.normal) / r_in.direction().len() * -1.;
+            cosine = -(dot(r_in.direction(), &rec.normal) / r_in.direction().len());
         }
 
-        if refract(r_in.direction(), &outward_normal, ni_over_nt, &mut refracted) {
-            reflect_prob = schlick(cosine, self.ref_idx);
+        let reflect_prob = if refract(r_in.direction(), &outward_normal, ni_over_nt, &mut refracted) {
+            schlick(cosine, self.ref_idx)
         } else {
-            reflect_prob = 1.;
-        }
+            1.
+        };
 
-        if drand48() < reflect_prob {
-            let s_ray = Ray::new(&rec.p, &reflected);
-            *scattered = s_ray.clone();
-        } else {
-            let s_ray = Ray::new(&rec.p, &refracted);
-            *scattered = s_ray.clone();
-        }
+        let direction = if sampler.drand48() < reflect_prob { reflected } else { refracted };
+        Some(ScatterRecord {
+                 ray: Ray::with_time(&rec.p, &direction, r_in.time()),
+                 attenuation: Vec3::new(1., 1., 1.),
+                 pdf: 0.,
+                 specular: true,
+             })
+    }
+    fn name(&self) -> String {
+        "dielectric".to_string()
+    }
+}
+
+/// Emits `emit` from both sides and absorbs everything that hits it.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture>,
+}
 
-        true
+#[allow(dead_code)]
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> Self {
+        Self::textured(Arc::new(ConstantTexture::new(emit)))
+    }
+
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<ScatterRecord> {
+        None
     }
-    fn box_clone(&self) -> Box<Material> {
-        Box::new((*self).clone())
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        self.emit.value(rec.u, rec.v, &rec.p)
     }
     fn name(&self) -> String {
-        "dielectric".to_string()
+        "diffuse_light".to_string()
     }
 }
 
@@ -181,3 +280,41 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     r0 = r0 * r0;
     r0 + (1. - r0) * (1. - cosine).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::{dummy, reflect, Material, Metal};
+    use utils::hitable::HitRecord;
+    use utils::random::Sampler;
+    use utils::ray::Ray;
+    use utils::sampling::random_around;
+    use utils::vec3::{unit_vector, Vec3};
+
+    #[test]
+    fn metal_pdf_integrates_to_the_share_of_rays_it_reflects() {
+        let mut rec = HitRecord::new(dummy());
+        rec.normal = Vec3::new(0., 1., 0.);
+        let incoming = Ray::new(&Vec3::new(-1., 1., 0.), &Vec3::new(1., -0.6, 0.));
+        let mut sampler = Sampler::new(5);
+        let mirrored = reflect(&unit_vector(incoming.direction().clone()), &rec.normal);
+        let n = 200000;
+        for &fuzz in &[0.2, 0.7] {
+            let metal = Metal::new(Vec3::new(1., 1., 1.), fuzz);
+            let reflected = (0..n).filter(|_| metal.scatter(&incoming, &rec, &mut sampler).is_some()).count();
+            // Only the cone of directions that meet the fuzz ball has a density.
+            let cos_max = (1. - fuzz * fuzz).sqrt();
+            let integral = (0..n).map(|_| {
+                                     let cos_theta = 1. - (1. - cos_max) * sampler.drand48();
+                                     let direction = random_around(&mirrored, cos_theta, &mut sampler);
+                                     metal.pdf(&incoming, &rec, &Ray::new(&rec.p, &direction))
+                                 })
+                                 .sum::<f32>() / n as f32 * 2. * PI * (1. - cos_max);
+            let share = reflected as f32 / n as f32;
+            assert!((integral - share).abs() < 0.02, "fuzz {}: {} vs {}", fuzz, integral, share);
+            assert!(metal.scatter(&incoming, &rec, &mut sampler).is_none_or(|s| !s.specular && s.pdf > 0.));
+        }
+        let mirror = Metal::new(Vec3::new(1., 1., 1.), 0.).scatter(&incoming, &rec, &mut sampler).unwrap();
+        assert!(mirror.specular);
+    }
+}
@@ -0,0 +1,507 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::Aabb;
+use utils::ray::Ray;
+use utils::vec3::{dot, unit_vector, Vec3};
+use utils::material::{Material, ScatterRecord};
+use utils::random::Sampler;
+use utils::sampling::random_around;
+use utils::texture::{ConstantTexture, Texture};
+use utils::voxel::VoxelGrid;
+
+/// A volume of uniform density filling a closed `boundary`, such as fog or smoke. A ray crossing it
+/// scatters at an exponentially distributed distance, and the scattering direction is chosen by
+/// the `phase` material, usually `Isotropic` or `HenyeyGreenstein`.
+#[allow(dead_code)]
+pub struct ConstantMedium<H: Hitable> {
+    pub boundary: H,
+    neg_inv_density: f32,
+    phase: Arc<dyn Material>,
+}
+
+#[allow(dead_code)]
+impl<H: Hitable> ConstantMedium<H> {
+    pub fn new(boundary: H, density: f32, phase: Arc<dyn Material>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1. / density,
+            phase,
+        }
+    }
+
+    /// Where `r` enters and leaves the boundary within `[t_min, t_max]`, even if it starts inside.
+    fn span(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> Option<(f32, f32)> {
+        let mut enter = HitRecord::new(self.phase.clone());
+        if !self.boundary.hit(r, f32::NEG_INFINITY, f32::INFINITY, &mut enter, sampler) {
+            return None;
+        }
+        let mut exit = HitRecord::new(self.phase.clone());
+        if !self.boundary.hit(r, enter.t + 0.0001, f32::INFINITY, &mut exit, sampler) {
+            return None;
+        }
+        let t_enter = enter.t.max(t_min).max(0.);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+        Some((t_enter, t_exit))
+    }
+}
+
+impl<H: Hitable> Hitable for ConstantMedium<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        let (t_enter, t_exit) = match self.span(r, t_min, t_max, sampler) {
+            Some(span) => span,
+            None => return false,
+        };
+        let ray_len = r.direction().len();
+        let distance_inside = (t_exit - t_enter) * ray_len;
+        let hit_distance = self.neg_inv_density * (1. - sampler.drand48()).ln();
+        if hit_distance > distance_inside {
+            return false;
+        }
+        rec.t = t_enter + hit_distance / ray_len;
+        rec.p = r.point_at_parameter(rec.t);
+        // volumes have no surface, so the normal and texture coordinates are arbitrary
+        rec.normal = Vec3::new(1., 0., 0.);
+        rec.u = 0.;
+        rec.v = 0.;
+        rec.mat = self.phase.clone();
+        true
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(t0, t1, output_box)
+    }
+
+    /// Beer–Lambert falloff over the distance `r` travels inside the boundary.
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        match self.span(r, t_min, t_max, sampler) {
+            Some((t_enter, t_exit)) => ((t_exit - t_enter) * r.direction().len() / self.neg_inv_density).exp(),
+            None => 1.,
+        }
+    }
+}
+
+/// A heterogeneous medium whose density comes from a voxel grid, scaled by `density`. Collisions
+/// are found by delta tracking against the grid's largest density, and `transmittance` estimates
+/// the fraction of light that crosses a segment by ratio tracking.
+///
+/// At a collision the medium absorbs `1 - albedo` of the light and emits `emission` in its place,
+/// then scatters the rest through a Henyey–Greenstein lobe with mean cosine `g`. Albedo and
+/// emission are set for the whole grid and multiplied by the grid's own per-voxel values where it
+/// stores them.
+#[allow(dead_code)]
+pub struct GridMedium {
+    pub grid: Arc<VoxelGrid>,
+    density: f32,
+    majorant: f32,
+    albedo: Vec3,
+    emission: Vec3,
+    g: f32,
+    /// Collision material shared by every hit when the grid stores no per-voxel colors.
+    uniform: Option<Arc<dyn Material>>,
+}
+
+#[allow(dead_code)]
+impl GridMedium {
+    /// A white, isotropic, non-emissive medium, unless the grid stores its own albedo or emission.
+    pub fn new(grid: Arc<VoxelGrid>, density: f32) -> Self {
+        let emission = if grid.emission.is_some() { 1. } else { 0. };
+        let mut medium = Self {
+            majorant: grid.max_density() * density,
+            grid,
+            density,
+            albedo: Vec3::new(1., 1., 1.),
+            emission: Vec3::new(emission, emission, emission),
+            g: 0.,
+            uniform: None,
+        };
+        medium.update_uniform();
+        medium
+    }
+
+    pub fn with_albedo(mut self, albedo: Vec3) -> Self {
+        self.albedo = albedo;
+        self.update_uniform();
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Vec3) -> Self {
+        self.emission = emission;
+        self.update_uniform();
+        self
+    }
+
+    pub fn with_anisotropy(mut self, g: f32) -> Self {
+        self.g = g.clamp(-1., 1.);
+        self.update_uniform();
+        self
+    }
+
+    fn update_uniform(&mut self) {
+        self.uniform = if self.grid.albedo.is_none() && self.grid.emission.is_none() {
+            Some(Arc::new(VolumeCollision {
+                              albedo: self.albedo.clone(),
+                              emission: self.emission.clone(),
+                              g: self.g,
+                          }))
+        } else {
+            None
+        };
+    }
+
+    /// Scaled density at `p`.
+    pub fn density_at(&self, p: &Vec3) -> f32 {
+        self.grid.density_at(p) * self.density
+    }
+
+    /// The part of `[t_min, t_max]` where `r` is inside the grid bounds.
+    fn clip(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for a in 0..3 {
+            let inv_d = 1. / r.direction()[a];
+            let mut near = (self.grid.bounds.min[a] - r.origin()[a]) * inv_d;
+            let mut far = (self.grid.bounds.max[a] - r.origin()[a]) * inv_d;
+            if inv_d < 0. {
+                ::std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from a zero direction inside the slab leaves the interval unchanged
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    /// Ray-parameter steps between tentative collisions, exponentially distributed with the
+    /// majorant as rate.
+    fn step(&self, r: &Ray, sampler: &mut Sampler) -> f32 {
+        -(1. - sampler.drand48()).ln() / (self.majorant * r.direction().len())
+    }
+
+    fn collision(&self, p: &Vec3) -> Arc<dyn Material> {
+        if let Some(ref m) = self.uniform {
+            return m.clone();
+        }
+        let white = || Vec3::new(1., 1., 1.);
+        Arc::new(VolumeCollision {
+                     albedo: self.albedo.clone() * self.grid.albedo_at(p).unwrap_or_else(white),
+                     emission: self.emission.clone() * self.grid.emission_at(p).unwrap_or_else(white),
+                     g: self.g,
+                 })
+    }
+}
+
+impl Hitable for GridMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        let (mut t, t_exit) = match self.clip(r, t_min, t_max) {
+            Some(span) if self.majorant > 0. => span,
+            _ => return false,
+        };
+        // delta tracking: tentative collisions at the majorant rate are real with probability
+        // density over majorant, otherwise the ray carries on unchanged
+        loop {
+            t += self.step(r, sampler);
+            if t >= t_exit {
+                return false;
+            }
+            let p = r.point_at_parameter(t);
+            if sampler.drand48() * self.majorant < self.density_at(&p) {
+                rec.t = t;
+                rec.normal = Vec3::new(1., 0., 0.);
+                rec.u = 0.;
+                rec.v = 0.;
+                rec.mat = self.collision(&p);
+                rec.p = p;
+                return true;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        *output_box = self.grid.bounds.clone();
+        true
+    }
+
+    /// Ratio-tracking estimate of the transmittance of `r` between `t_min` and `t_max`. It is
+    /// unbiased and, unlike counting which rays get through, weighs every tentative collision by
+    /// how likely it was to be real, so shadow rays through thin media are far less noisy.
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut Sampler) -> f32 {
+        let (mut t, t_exit) = match self.clip(r, t_min, t_max) {
+            Some(span) if self.majorant > 0. => span,
+            _ => return 1.,
+        };
+        let mut tr = 1.;
+        loop {
+            t += self.step(r, sampler);
+            if t >= t_exit {
+                return tr;
+            }
+            tr *= 1. - self.density_at(&r.point_at_parameter(t)) / self.majorant;
+        }
+    }
+}
+
+/// What a real collision inside a `GridMedium` does: emit for the absorbed fraction and scatter
+/// the rest.
+struct VolumeCollision {
+    albedo: Vec3,
+    emission: Vec3,
+    g: f32,
+}
+
+impl Material for VolumeCollision {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let cos_theta = sample_henyey_greenstein(self.g, sampler);
+        let dir = random_around(r_in.direction(), cos_theta, sampler);
+        let ray = Ray::with_time(&rec.p, &dir, r_in.time());
+        Some(phase_sample(self.albedo.clone(), henyey_greenstein(cos_theta, self.g), ray))
+    }
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        (Vec3::new(1., 1., 1.) - self.albedo.clone()) * self.emission.clone()
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.albedo.clone() * self.pdf(r_in, rec, scattered)
+    }
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f32 {
+        henyey_greenstein(cosine_between(r_in, scattered), self.g)
+    }
+    fn name(&self) -> String {
+        "volume".to_string()
+    }
+}
+
+/// Henyey–Greenstein phase function: the density, per steradian, of scattering by an angle whose
+/// cosine is `cos_theta`. `g` is the mean cosine; positive values scatter forward.
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1. + g * g - 2. * g * cos_theta;
+    (1. - g * g) / (4. * PI * denom * denom.sqrt())
+}
+
+/// A phase function picks directions in proportion to its value, so the attenuation is the albedo.
+fn phase_sample(albedo: Vec3, pdf: f32, ray: Ray) -> ScatterRecord {
+    ScatterRecord {
+        ray,
+        attenuation: albedo,
+        pdf,
+        specular: false,
+    }
+}
+
+fn cosine_between(r_in: &Ray, scattered: &Ray) -> f32 {
+    dot(&unit_vector(r_in.direction().clone()), &unit_vector(scattered.direction().clone()))
+}
+
+/// Draws a scattering cosine from `henyey_greenstein`.
+fn sample_henyey_greenstein(g: f32, sampler: &mut Sampler) -> f32 {
+    let xi = sampler.drand48();
+    if g.abs() < 1e-3 {
+        return 1. - 2. * xi;
+    }
+    let q = (1. - g * g) / (1. - g + 2. * g * xi);
+    ((1. + g * g - q * q) / (2. * g)).clamp(-1., 1.)
+}
+
+/// Phase function that scatters equally in every direction.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+#[allow(dead_code)]
+impl Isotropic {
+    pub fn new(a: Vec3) -> Self {
+        Self::textured(Arc::new(ConstantTexture::new(a)))
+    }
+
+    pub fn textured(a: Arc<dyn Texture>) -> Self {
+        Self { albedo: a }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let dir = random_around(r_in.direction(), 1. - 2. * sampler.drand48(), sampler);
+        let ray = Ray::with_time(&rec.p, &dir, r_in.time());
+        Some(phase_sample(self.albedo.value(rec.u, rec.v, &rec.p), 1. / (4. * PI), ray))
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, scattered)
+    }
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
+        1. / (4. * PI)
+    }
+    fn name(&self) -> String {
+        "isotropic".to_string()
+    }
+}
+
+/// Anisotropic phase function for media like haze (`g > 0`, forward scattering) or dust that
+/// throws light back (`g < 0`). `g` is clamped to `[-1, 1]`; zero is the same as `Isotropic`.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f32,
+}
+
+#[allow(dead_code)]
+impl HenyeyGreenstein {
+    pub fn new(a: Vec3, g: f32) -> Self {
+        Self::textured(Arc::new(ConstantTexture::new(a)), g)
+    }
+
+    pub fn textured(a: Arc<dyn Texture>, g: f32) -> Self {
+        Self {
+            albedo: a,
+            g: g.clamp(-1., 1.),
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let cos_theta = sample_henyey_greenstein(self.g, sampler);
+        let dir = random_around(r_in.direction(), cos_theta, sampler);
+        let ray = Ray::with_time(&rec.p, &dir, r_in.time());
+        Some(phase_sample(self.albedo.value(rec.u, rec.v, &rec.p), henyey_greenstein(cos_theta, self.g), ray))
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, scattered)
+    }
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f32 {
+        henyey_greenstein(cosine_between(r_in, scattered), self.g)
+    }
+    fn name(&self) -> String {
+        "henyey_greenstein".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+    use super::{henyey_greenstein, ConstantMedium, GridMedium, HenyeyGreenstein, Isotropic};
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::{dummy, Material};
+    use utils::random::Sampler;
+    use utils::ray::Ray;
+    use utils::rect::BoxShape;
+    use utils::vec3::{dot, unit_vector, Vec3};
+    use utils::voxel::VoxelGrid;
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        let mut sampler = Sampler::new(0);
+        let unit = BoxShape::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.), dummy());
+        let fog = ConstantMedium::new(unit, 1.5, Arc::new(Isotropic::new(Vec3::new(1., 1., 1.))));
+        let mut rec = HitRecord::new(dummy());
+        let n = 4000;
+        let mut scattered = 0;
+        for i in 0..n {
+            let (x, y) = ((i % 64) as f32 / 64. + 0.005, (i / 64) as f32 / 64. + 0.005);
+            let r = Ray::new(&Vec3::new(x, y, 3.), &Vec3::new(0., 0., -2.));
+            if fog.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler) {
+                scattered += 1;
+                assert!(rec.p.z() >= 0. && rec.p.z() <= 1., "{:?}", rec.p);
+            }
+        }
+        let expected = 1. - (-1.5f32).exp();
+        let fraction = scattered as f32 / n as f32;
+        assert!((fraction - expected).abs() < 0.03, "{} vs {}", fraction, expected);
+
+        // a ray starting inside only sees the medium ahead of it
+        let inside = Ray::new(&Vec3::new(0.5, 0.5, 0.5), &Vec3::new(0., 0., 1.));
+        if fog.hit(&inside, 0.001, f32::MAX, &mut rec, &mut sampler) {
+            assert!(rec.t > 0. && rec.t <= 0.5);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_samples_match_its_mean_cosine() {
+        let mut sampler = Sampler::new(3);
+        let rec = HitRecord::new(dummy());
+        let incoming = Ray::new(&Vec3::new(0., 0., 0.), &Vec3::new(1., 2., -1.));
+        let forward = unit_vector(incoming.direction().clone());
+        let mean_cosine = |phase: &dyn Material, sampler: &mut Sampler| {
+            let n = 20000;
+            (0..n).map(|_| {
+                      let scattered = phase.scatter(&incoming, &rec, sampler).unwrap().ray;
+                      dot(&unit_vector(scattered.direction().clone()), &forward)
+                  })
+                  .sum::<f32>() / n as f32
+        };
+        let white = Vec3::new(1., 1., 1.);
+        for &g in &[-0.5, 0., 0.7] {
+            let mean = mean_cosine(&HenyeyGreenstein::new(white.clone(), g), &mut sampler);
+            assert!((mean - g).abs() < 0.02, "g = {}: mean cosine {}", g, mean);
+        }
+        assert!(mean_cosine(&Isotropic::new(white), &mut sampler).abs() < 0.02);
+
+        // the phase function integrates to one over the sphere
+        let n = 10000;
+        let integral: f32 = (0..n).map(|i| {
+                                      let cos_theta = -1. + 2. * (i as f32 + 0.5) / n as f32;
+                                      henyey_greenstein(cos_theta, 0.7) * 2. * PI * 2. / n as f32
+                                  })
+                                  .sum();
+        assert!((integral - 1.).abs() < 1e-3, "{}", integral);
+    }
+
+    /// Two voxels along x with densities 0 and 2; the optical depth across the unit cube is 1.
+    fn ramp() -> GridMedium {
+        let bounds = Aabb::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+        GridMedium::new(Arc::new(VoxelGrid::new([2, 1, 1], bounds, vec![0., 1.])), 2.)
+    }
+
+    #[test]
+    fn grid_tracking_matches_the_optical_depth() {
+        let medium = ramp();
+        let expected = (-1f32).exp();
+        let across = Ray::new(&Vec3::new(-1., 0.5, 0.5), &Vec3::new(2., 0., 0.));
+        let mut sampler = Sampler::new(11);
+        let n = 4000;
+        let ratio = (0..n).map(|_| medium.transmittance(&across, 0., f32::MAX, &mut sampler)).sum::<f32>() / n as f32;
+        assert!((ratio - expected).abs() < 0.01, "{} vs {}", ratio, expected);
+
+        // delta tracking lets the same fraction through, counted over slightly different rays
+        let mut rec = HitRecord::new(dummy());
+        let passed = (0..n).filter(|&i| {
+                               let y = (i as f32 + 0.5) / n as f32;
+                               let r = Ray::new(&Vec3::new(-1., y, 0.5), &Vec3::new(2., 0., 0.));
+                               !medium.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler)
+                           })
+                           .count();
+        let fraction = passed as f32 / n as f32;
+        assert!((fraction - expected).abs() < 0.03, "{} vs {}", fraction, expected);
+
+        // collisions never happen where the density is zero
+        let thin_end = Ray::new(&Vec3::new(0.1, -1., 0.5), &Vec3::new(0., 1., 0.));
+        assert!(!medium.hit(&thin_end, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert_eq!(medium.transmittance(&thin_end, 0., f32::MAX, &mut sampler), 1.);
+    }
+
+    #[test]
+    fn collisions_use_grid_and_voxel_colors() {
+        let mut sampler = Sampler::new(1);
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(-1., 0.5, 0.5), &Vec3::new(1., 0., 0.));
+        let dense = ramp().with_albedo(Vec3::new(0.5, 0.5, 0.5)).with_emission(Vec3::new(2., 0., 0.));
+        while !dense.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler) {}
+        assert_eq!(rec.mat.emitted(&r, &rec), Vec3::new(1., 0., 0.));
+
+        let mut grid = (*ramp().grid).clone();
+        grid.albedo = Some(vec![Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.)]);
+        let tinted = GridMedium::new(Arc::new(grid), 2.).with_albedo(Vec3::new(0.5, 0.25, 1.));
+        let collision = tinted.collision(&Vec3::new(0.5, 0.5, 0.5));
+        let scattered = collision.scatter(&r, &rec, &mut sampler).unwrap();
+        assert_eq!(scattered.attenuation, Vec3::new(0.25, 0.125, 0.5));
+        assert_eq!(collision.emitted(&r, &rec), Vec3::new(0., 0., 0.));
+    }
+}
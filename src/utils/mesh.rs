@@ -0,0 +1,272 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitableList, HitRecord};
+use utils::aabb::Aabb;
+use utils::bvh::BvhNode;
+use utils::ray::Ray;
+use utils::random::Sampler;
+use utils::vec3::{cross, dot, unit_vector, Vec3};
+use utils::material::{Lambertian, Material};
+use utils::texture::Texture;
+use utils::triangle::{intersect, triangle_box};
+
+/// One triangle of a mesh as indices into the mesh's buffers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    /// Index into `MeshData::materials`.
+    pub material: usize,
+}
+
+/// Vertex buffers shared by every triangle of a mesh.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    /// Linear per-vertex colors, parallel to `positions`, or empty.
+    pub colors: Vec<Vec3>,
+    pub faces: Vec<Face>,
+    pub materials: Vec<Arc<dyn Material>>,
+}
+
+impl MeshData {
+    /// Checks that every face only refers to existing vertices, normals, UVs and materials.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.colors.is_empty() && self.colors.len() != self.positions.len() {
+            return Err(format!("{} vertex colors for {} vertices", self.colors.len(), self.positions.len()));
+        }
+        for (i, f) in self.faces.iter().enumerate() {
+            let ok = f.positions.iter().all(|&p| p < self.positions.len()) &&
+                     f.normals.is_none_or(|n| n.iter().all(|&n| n < self.normals.len())) &&
+                     f.uvs.is_none_or(|t| t.iter().all(|&t| t < self.uvs.len())) &&
+                     f.material < self.materials.len();
+            if !ok {
+                return Err(format!("face {} refers to missing mesh data", i));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A triangle that reads its vertices from a shared `MeshData`, so large meshes only store each
+/// vertex once.
+pub struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+    /// Diffuse material of the interpolated vertex colors, if the mesh has any.
+    colored: Option<Arc<dyn Material>>,
+}
+
+/// The colors at a triangle's corners, blended at the barycentric coordinates passed as `u` and
+/// `v`.
+struct TriangleColors([Vec3; 3]);
+
+impl Texture for TriangleColors {
+    fn value(&self, u: f32, v: f32, _p: &Vec3) -> Vec3 {
+        let c = &self.0;
+        c[0].clone() * (1. - u - v) + c[1].clone() * u + c[2].clone() * v
+    }
+}
+
+impl Hitable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        let mesh = &self.mesh;
+        let face = &mesh.faces[self.index];
+        let p = |k: usize| &mesh.positions[face.positions[k]];
+        let (t, b1, b2) = match intersect(r, p(0), p(1), p(2), t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let b0 = 1. - b1 - b2;
+        let geometric = unit_vector(cross(&(p(1).clone() - p(0).clone()), &(p(2).clone() - p(0).clone())));
+        rec.normal = match face.normals {
+            Some(n) => {
+                let n = |k: usize| mesh.normals[n[k]].clone();
+                let smooth = n(0) * b0 + n(1) * b1 + n(2) * b2;
+                if smooth.squared_len() > 0. {
+                    // keep the shading normal on the side the winding order says is outside
+                    let smooth = unit_vector(smooth);
+                    if dot(&smooth, &geometric) < 0. {
+                        -smooth
+                    } else {
+                        smooth
+                    }
+                } else {
+                    geometric
+                }
+            }
+            None => geometric,
+        };
+        let (u, v) = match face.uvs {
+            // vertex colors look up the corners by barycentric coordinates
+            _ if self.colored.is_some() => (b1, b2),
+            Some(uv) => {
+                let uv = |k: usize| mesh.uvs[uv[k]];
+                (uv(0).0 * b0 + uv(1).0 * b1 + uv(2).0 * b2, uv(0).1 * b0 + uv(1).1 * b1 + uv(2).1 * b2)
+            }
+            None => (b1, b2),
+        };
+        rec.t = t;
+        rec.u = u;
+        rec.v = v;
+        rec.p = r.point_at_parameter(t);
+        rec.mat = match self.colored {
+            Some(ref colored) => colored.clone(),
+            None => mesh.materials[face.material].clone(),
+        };
+        true
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        let face = &self.mesh.faces[self.index];
+        let p = |k: usize| &self.mesh.positions[face.positions[k]];
+        *output_box = triangle_box(p(0), p(1), p(2));
+        true
+    }
+}
+
+/// Vertex colors replace the face material with a diffuse one, made once per face so hits do not
+/// allocate.
+fn colored(data: &MeshData, index: usize) -> Option<Arc<dyn Material>> {
+    if data.colors.is_empty() {
+        return None;
+    }
+    let c = |k: usize| data.colors[data.faces[index].positions[k]].clone();
+    let texture = TriangleColors([c(0), c(1), c(2)]);
+    Some(Arc::new(Lambertian::textured(Arc::new(texture))))
+}
+
+/// Indexed triangle mesh with optional per-vertex normals, which are interpolated for smooth
+/// shading, UVs and colors. Its triangles are kept in their own BVH.
+pub struct TriangleMesh {
+    pub data: Arc<MeshData>,
+    bvh: Option<BvhNode>,
+}
+
+#[allow(dead_code)]
+impl TriangleMesh {
+    pub fn new(data: MeshData) -> Result<Self, String> {
+        data.validate()?;
+        let data = Arc::new(data);
+        let triangles: Vec<Box<dyn Hitable>> = (0..data.faces.len()).map(|index| {
+                                                                            Box::new(MeshTriangle {
+                                                                                         colored: colored(&data, index),
+                                                                                         mesh: data.clone(),
+                                                                                         index,
+                                                                                     }) as
+                                                                            Box<dyn Hitable>
+                                                                        })
+                                                                        .collect();
+        let bvh = if triangles.is_empty() {
+            None
+        } else {
+            Some(BvhNode::new(HitableList::new(triangles), 0., 1.))
+        };
+        Ok(Self { data, bvh })
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.faces.is_empty()
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        match self.bvh {
+            Some(ref bvh) => bvh.hit(r, t_min, t_max, rec, sampler),
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        match self.bvh {
+            Some(ref bvh) => bvh.bounding_box(t0, t1, output_box),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Face, MeshData, TriangleMesh};
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::random::Sampler;
+
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+
+    /// A unit square at z = -1 split into two triangles, with normals tilted outwards along x.
+    fn square() -> MeshData {
+        MeshData {
+            positions: vec![Vec3::new(0., 0., -1.), Vec3::new(1., 0., -1.), Vec3::new(1., 1., -1.), Vec3::new(0., 1., -1.)],
+            normals: vec![Vec3::new(-1., 0., 1.), Vec3::new(1., 0., 1.)],
+            uvs: vec![(0., 0.), (2., 0.), (2., 2.), (0., 2.)],
+            colors: vec![],
+            faces: vec![Face {
+                            positions: [0, 1, 2],
+                            normals: Some([0, 1, 1]),
+                            uvs: Some([0, 1, 2]),
+                            material: 0,
+                        },
+                        Face {
+                            positions: [0, 2, 3],
+                            normals: Some([0, 1, 0]),
+                            uvs: Some([0, 2, 3]),
+                            material: 0,
+                        }],
+            materials: vec![dummy()],
+        }
+    }
+
+    #[test]
+    fn interpolates_normals_and_uvs() {
+        let mut sampler = Sampler::new(0);
+        let mesh = TriangleMesh::new(square()).unwrap();
+        assert_eq!(mesh.len(), 2);
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0.5, 0.25, 0.), &Vec3::new(0., 0., -1.));
+        assert!(mesh.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.u - 1.).abs() < 1e-5 && (rec.v - 0.5).abs() < 1e-5, "{} {}", rec.u, rec.v);
+        // halfway between the two tilted normals points straight at the viewer
+        assert!(rec.normal.x().abs() < 1e-5 && (rec.normal.z() - 1.).abs() < 1e-5, "{:?}", rec.normal);
+        let left = Ray::new(&Vec3::new(0.1, 0.5, 0.), &Vec3::new(0., 0., -1.));
+        assert!(mesh.hit(&left, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!(rec.normal.x() < -0.5);
+    }
+
+    #[test]
+    fn interpolates_vertex_colors() {
+        let mut sampler = Sampler::new(0);
+        let mut data = square();
+        data.colors = vec![Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.), Vec3::new(0., 0., 0.)];
+        let mesh = TriangleMesh::new(data).unwrap();
+        let r = Ray::new(&Vec3::new(0.5, 0.25, 0.), &Vec3::new(0., 0., -1.));
+        let mut first = HitRecord::new(dummy());
+        let mut second = HitRecord::new(dummy());
+        assert!(mesh.hit(&r, 0.001, f32::MAX, &mut first, &mut sampler));
+        assert!(mesh.hit(&r, 0.001, f32::MAX, &mut second, &mut sampler));
+        // the face's material is made once, not on every hit
+        assert!(Arc::ptr_eq(&first.mat, &second.mat));
+        let color = first.mat.scatter(&r, &first, &mut Sampler::new(1)).unwrap().attenuation;
+        // barycentric weights (0.5, 0.25, 0.25) in the first triangle
+        assert!((color - Vec3::new(0.5, 0.25, 0.25)).len() < 1e-5);
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let mut data = square();
+        data.faces[1].positions[2] = 4;
+        assert!(TriangleMesh::new(data).is_err());
+        let mut data = square();
+        data.faces[0].material = 1;
+        assert!(TriangleMesh::new(data).is_err());
+    }
+}
@@ -1,23 +1,48 @@
-extern crate rand;
-
-pub mod vec3;
-pub mod ray;
-pub mod hitable;
-pub mod sphere;
-pub mod camera;
-pub mod material;
-pub mod random;
-
 #[macro_export]
 macro_rules! get_sphere {
     ( $m:ident, $x:expr, $y:expr, $r:expr ) => {
         {
-            Box::new(Sphere::new($y, $r, Box::new($m::new($x))))
+            Box::new(Sphere::new($y, $r, ::std::sync::Arc::new($m::new($x))))
         }
     };
     ( $m:ident, $x:expr, $f:expr, $y:expr, $r:expr ) => {
         {
-            Box::new(Sphere::new($y, $r, Box::new($m::new($x, $f))))
+            Box::new(Sphere::new($y, $r, ::std::sync::Arc::new($m::new($x, $f))))
         }
     };
 }
+
+pub mod vec3;
+pub mod transform;
+pub mod ray;
+pub mod hitable;
+pub mod sphere;
+pub mod moving_sphere;
+pub mod rect;
+pub mod quad;
+pub mod plane;
+pub mod instance;
+pub mod medium;
+pub mod voxel;
+pub mod triangle;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod gltf;
+pub mod camera;
+pub mod material;
+pub mod light;
+pub mod texture;
+pub mod noise;
+pub mod random;
+pub mod sampling;
+pub mod aabb;
+pub mod bvh;
+pub mod film;
+pub mod rgbe;
+pub mod exr;
+pub mod tonemap;
+pub mod render;
+pub mod scene;
+pub mod scene_file;
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::{surrounding_box, Aabb};
+use utils::ray::Ray;
+use utils::random::Sampler;
+use utils::vec3::Vec3;
+use utils::material::Material;
+use utils::sphere::hit_sphere;
+
+/// A sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`. Rays
+/// outside that interval see it further along the same line.
+#[allow(dead_code)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    radius: f32,
+    mat: Arc<dyn Material>,
+}
+
+#[allow(dead_code)]
+impl MovingSphere {
+    pub fn new(center0: Vec3, center1: Vec3, time0: f32, time1: f32, radius: f32, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            mat,
+        }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0.clone();
+        }
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        self.center0.clone() + (self.center1.clone() - self.center0.clone()) * s
+    }
+
+    fn box_at(&self, time: f32) -> Aabb {
+        let r = self.radius.abs();
+        let center = self.center(time);
+        Aabb::new(center.clone() - Vec3::new(r, r, r), center + Vec3::new(r, r, r))
+    }
+}
+
+impl Hitable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        hit_sphere(&self.center(r.time()), self.radius, &self.mat, r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32, output_box: &mut Aabb) -> bool {
+        // the path is a straight line, so the boxes at both ends cover everything in between
+        *output_box = surrounding_box(&self.box_at(t0), &self.box_at(t1));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MovingSphere;
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    fn sliding() -> MovingSphere {
+        MovingSphere::new(Vec3::new(0., 0., -5.), Vec3::new(4., 0., -5.), 0., 1., 1., dummy())
+    }
+
+    #[test]
+    fn hits_follow_the_ray_time() {
+        let mut sampler = Sampler::new(0);
+        let sphere = sliding();
+        let mut rec = HitRecord::new(dummy());
+        let origin = Vec3::new(0., 0., 0.);
+        let dir = Vec3::new(0., 0., -1.);
+        assert!(sphere.hit(&Ray::with_time(&origin, &dir, 0.), 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 4.).abs() < 1e-5);
+        assert!(!sphere.hit(&Ray::with_time(&origin, &dir, 1.), 0.001, f32::MAX, &mut rec, &mut sampler));
+
+        let ahead = Vec3::new(2., 0., 0.);
+        assert!(sphere.hit(&Ray::with_time(&ahead, &dir, 0.5), 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!(!sphere.hit(&Ray::with_time(&ahead, &dir, 0.), 0.001, f32::MAX, &mut rec, &mut sampler));
+    }
+
+    #[test]
+    fn bounding_box_covers_the_whole_motion() {
+        let mut bbox = Aabb::default();
+        assert!(sliding().bounding_box(0., 1., &mut bbox));
+        assert_eq!(bbox.min, Vec3::new(-1., -1., -6.));
+        assert_eq!(bbox.max, Vec3::new(5., 1., -4.));
+    }
+}
@@ -0,0 +1,137 @@
+use utils::vec3::{dot, unit_vector, Vec3};
+use utils::random::Sampler;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient (Perlin) noise over random unit vectors on a 256³ lattice. The lattice, and so every
+/// value, is fixed by the seed.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+#[allow(dead_code)]
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut sampler = Sampler::new(seed);
+        let ranvec = (0..POINT_COUNT).map(|_| random_unit_vector(&mut sampler)).collect();
+        let perm_x = generate_perm(&mut sampler);
+        let perm_y = generate_perm(&mut sampler);
+        let perm_z = generate_perm(&mut sampler);
+        Self {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    /// Smooth noise in about `[-1, 1]` that is zero on every lattice point.
+    pub fn noise(&self, p: &Vec3) -> f32 {
+        let (u, v, w) = (p.x() - p.x().floor(), p.y() - p.y().floor(), p.z() - p.z().floor());
+        let (i, j, k) = (p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64);
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+
+        // Hermite smoothing of the weights hides the lattice
+        let (uu, vv, ww) = (u * u * (3. - 2. * u), v * v * (3. - 2. * v), w * w * (3. - 2. * w));
+        let mut accum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let g = &self.ranvec[self.perm_x[wrap(i + di)] ^ self.perm_y[wrap(j + dj)] ^
+                                         self.perm_z[wrap(k + dk)]];
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1. - fi) * (1. - uu)) * (fj * vv + (1. - fj) * (1. - vv)) *
+                             (fk * ww + (1. - fk) * (1. - ww)) * dot(g, &weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Fractal Brownian motion: `octaves` layers of noise, each at twice the frequency and half
+    /// the amplitude of the previous one.
+    pub fn fbm(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut accum = 0.;
+        let mut temp_p = p.clone();
+        let mut weight = 1.;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p = temp_p * 2.;
+        }
+        accum
+    }
+
+    /// Like `fbm` but summing absolute values, which gives the sharp creases of turbulence.
+    pub fn turbulence(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut accum = 0.;
+        let mut temp_p = p.clone();
+        let mut weight = 1.;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p).abs();
+            weight *= 0.5;
+            temp_p = temp_p * 2.;
+        }
+        accum
+    }
+}
+
+fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    loop {
+        let p = Vec3::new(sampler.drand48(), sampler.drand48(), sampler.drand48()) * 2. - Vec3::new(1., 1., 1.);
+        let len = p.squared_len();
+        if len > 1e-6 && len <= 1. {
+            return unit_vector(p);
+        }
+    }
+}
+
+/// Fisher-Yates shuffle of `0..POINT_COUNT`.
+fn generate_perm(sampler: &mut Sampler) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = (sampler.drand48() * (i + 1) as f32) as usize;
+        p.swap(i, target.min(i));
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Perlin;
+    use utils::vec3::Vec3;
+
+    fn points() -> Vec<Vec3> {
+        (0..500).map(|i| {
+                        let t = i as f32;
+                        Vec3::new(t * 0.37 - 50., t * 0.11 + 3.3, (t * 0.73).sin() * 20.)
+                    })
+                .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_noise() {
+        let (a, b, c) = (Perlin::new(7), Perlin::new(7), Perlin::new(8));
+        let pts = points();
+        assert!(pts.iter().all(|p| a.noise(p) == b.noise(p)));
+        assert!(pts.iter().any(|p| a.noise(p) != c.noise(p)));
+    }
+
+    #[test]
+    fn noise_is_bounded_and_continuous() {
+        let perlin = Perlin::new(1);
+        for p in points().iter() {
+            let n = perlin.noise(p);
+            assert!((-1.1..=1.1).contains(&n), "{}", n);
+            let near = perlin.noise(&(p.clone() + Vec3::new(1e-3, 1e-3, 1e-3)));
+            assert!((n - near).abs() < 0.02, "{} vs {}", n, near);
+            assert!(perlin.turbulence(p, 7) >= 0.);
+        }
+        assert_eq!(perlin.noise(&Vec3::new(3., -2., 5.)), 0.);
+    }
+}
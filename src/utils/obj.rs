@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use utils::vec3::Vec3;
+use utils::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use utils::mesh::{Face, MeshData, TriangleMesh};
+use utils::texture::{ConstantTexture, ImageTexture, Texture};
+
+/// The parts of an MTL material the renderer understands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Vec3,
+    /// `Ks`
+    pub specular: Vec3,
+    /// `Ns`, the Phong exponent.
+    pub shininess: f32,
+    /// `Ni`
+    pub ior: f32,
+    /// `d`, or `1 - Tr`.
+    pub dissolve: f32,
+    /// `Ke`
+    pub emission: Vec3,
+    pub illum: u32,
+    /// `map_Kd`, relative to the MTL file.
+    pub diffuse_map: Option<String>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::new(0., 0., 0.),
+            shininess: 0.,
+            ior: 1.,
+            dissolve: 1.,
+            emission: Vec3::new(0., 0., 0.),
+            illum: 2,
+            diffuse_map: None,
+        }
+    }
+
+    /// Picks the closest of our materials: emitters become `DiffuseLight`, transparent or
+    /// refracting (`illum` 4, 6, 7) ones `Dielectric`, mostly specular ones `Metal` with a fuzz that
+    /// shrinks as `Ns` grows, and everything else `Lambertian`.
+    pub fn build(&self, base_dir: &Path) -> io::Result<Arc<dyn Material>> {
+        let max = |c: &Vec3| c.x().max(c.y()).max(c.z());
+        if max(&self.emission) > 0. {
+            return Ok(Arc::new(DiffuseLight::new(self.emission.clone())));
+        }
+        if self.dissolve < 1. || [4, 6, 7].contains(&self.illum) {
+            let ior = if self.ior > 1. { self.ior } else { 1.5 };
+            return Ok(Arc::new(Dielectric::new(ior)));
+        }
+        if self.illum == 3 || (max(&self.specular) > 0. && max(&self.specular) >= max(&self.diffuse)) {
+            let fuzz = (2. / (self.shininess + 2.)).sqrt();
+            return Ok(Arc::new(Metal::new(self.specular.clone(), fuzz)));
+        }
+        let albedo: Arc<dyn Texture> = match self.diffuse_map {
+            Some(ref map) => Arc::new(ImageTexture::load(base_dir.join(map))?),
+            None => Arc::new(ConstantTexture::new(self.diffuse.clone())),
+        };
+        Ok(Arc::new(Lambertian::textured(albedo)))
+    }
+}
+
+fn invalid(line: usize, msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+fn floats(line: usize, args: &[&str], n: usize) -> io::Result<Vec<f32>> {
+    if args.len() < n {
+        return Err(invalid(line, format!("expected {} numbers", n)));
+    }
+    args[..n].iter()
+             .map(|a| a.parse::<f32>().map_err(|_| invalid(line, format!("invalid number `{}`", a))))
+             .collect()
+}
+
+pub fn parse_mtl(source: &str) -> io::Result<Vec<MtlMaterial>> {
+    let mut materials: Vec<MtlMaterial> = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial::new(&args.join(" ")));
+            continue;
+        }
+        let m = match materials.last_mut() {
+            Some(m) => m,
+            None => return Err(invalid(line_no, format!("`{}` before `newmtl`", keyword))),
+        };
+        let color = |args: &[&str]| -> io::Result<Vec3> {
+            let c = floats(line_no, args, 3)?;
+            Ok(Vec3::new(c[0], c[1], c[2]))
+        };
+        match keyword {
+            "Kd" => m.diffuse = color(&args)?,
+            "Ks" => m.specular = color(&args)?,
+            "Ke" => m.emission = color(&args)?,
+            "Ns" => m.shininess = floats(line_no, &args, 1)?[0],
+            "Ni" => m.ior = floats(line_no, &args, 1)?[0],
+            "d" => m.dissolve = floats(line_no, &args, 1)?[0],
+            "Tr" => m.dissolve = 1. - floats(line_no, &args, 1)?[0],
+            "illum" => m.illum = floats(line_no, &args, 1)?[0] as u32,
+            // options such as `-s 1 1 1` come before the file name
+            "map_Kd" => m.diffuse_map = args.last().map(|s| s.to_string()),
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+/// Resolves a 1-based, possibly negative (relative to the end) OBJ index.
+fn index(line: usize, s: &str, len: usize) -> io::Result<usize> {
+    let i: i64 = s.parse().map_err(|_| invalid(line, format!("invalid index `{}`", s)))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if i == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(invalid(line, format!("index {} out of range", i)));
+    }
+    Ok(resolved as usize)
+}
+
+/// A face only gets UVs or normals if all of its corners have them.
+fn all_three(a: Option<usize>, b: Option<usize>, c: Option<usize>) -> Option<[usize; 3]> {
+    match (a, b, c) {
+        (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+        _ => None,
+    }
+}
+
+/// Parses an OBJ file into mesh buffers. Polygons are split into triangle fans. `mtl` is called
+/// with each `mtllib` name and returns the materials it defines; faces before any `usemtl`, or
+/// with an unknown material, use `default_mat`.
+pub fn parse_obj<F>(source: &str, mut mtl: F, default_mat: Arc<dyn Material>) -> io::Result<MeshData>
+    where F: FnMut(&str) -> io::Result<Vec<(String, Arc<dyn Material>)>>
+{
+    let mut data = MeshData {
+        materials: vec![default_mat],
+        ..MeshData::default()
+    };
+    let mut library: HashMap<String, usize> = HashMap::new();
+    let mut current = 0;
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+        match keyword {
+            "v" => {
+                let p = floats(line_no, &args, 3)?;
+                data.positions.push(Vec3::new(p[0], p[1], p[2]));
+            }
+            "vn" => {
+                let n = floats(line_no, &args, 3)?;
+                data.normals.push(Vec3::new(n[0], n[1], n[2]));
+            }
+            "vt" => {
+                let t = floats(line_no, &args, 1)?;
+                let v = if args.len() > 1 { floats(line_no, &args, 2)?[1] } else { 0. };
+                data.uvs.push((t[0], v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(invalid(line_no, "faces need at least three vertices".to_string()));
+                }
+                let mut corners = vec![];
+                for a in args.iter() {
+                    let mut parts = a.split('/');
+                    let p = index(line_no, parts.next().unwrap_or(""), data.positions.len())?;
+                    let t = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(index(line_no, t, data.uvs.len())?),
+                        _ => None,
+                    };
+                    let n = match parts.next() {
+                        Some(n) if !n.is_empty() => Some(index(line_no, n, data.normals.len())?),
+                        _ => None,
+                    };
+                    corners.push((p, t, n));
+                }
+                for k in 1..corners.len() - 1 {
+                    let c = [corners[0], corners[k], corners[k + 1]];
+                    data.faces.push(Face {
+                                        positions: [c[0].0, c[1].0, c[2].0],
+                                        uvs: all_three(c[0].1, c[1].1, c[2].1),
+                                        normals: all_three(c[0].2, c[1].2, c[2].2),
+                                        material: current,
+                                    });
+                }
+            }
+            "mtllib" => {
+                for (name, mat) in mtl(&args.join(" "))? {
+                    library.insert(name, data.materials.len());
+                    data.materials.push(mat);
+                }
+            }
+            "usemtl" => current = library.get(&args.join(" ")).cloned().unwrap_or(0),
+            _ => {}
+        }
+    }
+    Ok(data)
+}
+
+/// Loads an OBJ file and the MTL libraries it references, which are looked up next to it.
+pub fn load<P: AsRef<Path>>(path: P, default_mat: Arc<dyn Material>) -> io::Result<TriangleMesh> {
+    let path = path.as_ref();
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let source = fs::read_to_string(path)?;
+    let load_mtl = |name: &str| -> io::Result<Vec<(String, Arc<dyn Material>)>> {
+        let mtl_path = base_dir.join(name);
+        let mtl_dir = mtl_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let with_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", mtl_path.display(), e));
+        let source = fs::read_to_string(&mtl_path).map_err(with_path)?;
+        parse_mtl(&source).map_err(with_path)?
+                          .iter()
+                          .map(|m| Ok((m.name.clone(), m.build(&mtl_dir).map_err(with_path)?)))
+                          .collect()
+    };
+    let data = parse_obj(&source, load_mtl, default_mat)?;
+    TriangleMesh::new(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+    use super::{parse_mtl, parse_obj};
+    use utils::material::{dummy, Material};
+
+    const MTL: &str = "
+# three materials
+newmtl matte
+Kd 0.5 0.25 1
+
+newmtl chrome
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+Ns 500
+
+newmtl glass
+Ni 1.33
+d 0.2
+illum 4
+
+newmtl lamp
+Ke 10 10 10
+";
+
+    fn library(_name: &str) -> io::Result<Vec<(String, Arc<dyn Material>)>> {
+        parse_mtl(MTL)?.iter()
+                       .map(|m| Ok((m.name.clone(), m.build(Path::new("."))?)))
+                       .collect()
+    }
+
+    #[test]
+    fn maps_mtl_onto_materials() {
+        let mats = library("x.mtl").unwrap();
+        let names: Vec<String> = mats.iter().map(|(_, m)| m.name()).collect();
+        assert_eq!(names, vec!["lambertian", "metal", "dielectric", "diffuse_light"]);
+        let parsed = parse_mtl(MTL).unwrap();
+        assert_eq!(parsed[2].ior, 1.33);
+        assert_eq!(parsed[2].dissolve, 0.2);
+        assert!(parse_mtl("Kd 1 1 1").is_err());
+    }
+
+    #[test]
+    fn parses_faces_polygons_and_negative_indices() {
+        let obj = "
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+f 1 2 3
+usemtl chrome
+f 1/1/1 2/2/1 3/3/1 4/3/1
+usemtl missing
+f -4//1 -3//1 -2//1
+";
+        let data = parse_obj(obj, library, dummy()).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.faces.len(), 4);
+        assert_eq!(data.faces[0].material, 0);
+        assert_eq!(data.faces[0].normals, None);
+        assert_eq!(data.materials[data.faces[1].material].name(), "metal");
+        assert_eq!(data.faces[2].positions, [0, 2, 3]);
+        assert_eq!(data.faces[2].uvs, Some([0, 2, 2]));
+        assert_eq!(data.faces[3].positions, [0, 1, 2]);
+        assert_eq!(data.faces[3].uvs, None);
+        assert_eq!(data.faces[3].normals, Some([0, 0, 0]));
+        assert_eq!(data.faces[3].material, 0);
+
+        let err = parse_obj("v 0 0 0\nf 1 2 3\n", library, dummy()).err().unwrap();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::Aabb;
+use utils::ray::Ray;
+use utils::random::Sampler;
+use utils::vec3::{cross, dot, unit_vector, Vec3};
+use utils::material::Material;
+
+/// Infinite plane through `point` facing `normal`. It has no bounding box, so it is kept outside
+/// the BVH (see `bvh::build`). `(u, v)` are distances from `point` along two axes in the plane.
+#[allow(dead_code)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    u_axis: Vec3,
+    v_axis: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+#[allow(dead_code)]
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, m: Arc<dyn Material>) -> Self {
+        let normal = unit_vector(normal);
+        let helper = if normal.x().abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v_axis = unit_vector(cross(&normal, &helper));
+        let u_axis = cross(&v_axis, &normal);
+        Self {
+            point,
+            normal,
+            u_axis,
+            v_axis,
+            mat: m,
+        }
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        let denom = dot(&self.normal, r.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = dot(&self.normal, &(self.point.clone() - r.origin().clone())) / denom;
+        if !(t > t_min && t < t_max) {
+            return false;
+        }
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        let offset = rec.p.clone() - self.point.clone();
+        rec.u = dot(&offset, &self.u_axis);
+        rec.v = dot(&offset, &self.v_axis);
+        rec.normal = self.normal.clone();
+        rec.mat = self.mat.clone();
+        true
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, _output_box: &mut Aabb) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Plane;
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    #[test]
+    fn plane_is_hit_anywhere_but_has_no_bounds() {
+        let mut sampler = Sampler::new(0);
+        let plane = Plane::new(Vec3::new(0., -1., 0.), Vec3::new(0., 2., 0.), dummy());
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(1000., 1., -5000.), &Vec3::new(0.3, -1., 0.2));
+        assert!(plane.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 2.).abs() < 1e-3);
+        assert_eq!(rec.normal.e, (0., 1., 0.));
+        let up = Ray::new(&Vec3::new(0., 1., 0.), &Vec3::new(0., 1., 0.));
+        assert!(!plane.hit(&up, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!(!plane.bounding_box(0., 1., &mut Aabb::default()));
+    }
+}
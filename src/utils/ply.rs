@@ -0,0 +1,416 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+use utils::vec3::Vec3;
+use utils::material::Material;
+use utils::mesh::{Face, MeshData, TriangleMesh};
+use utils::tonemap::srgb_eotf;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Scale that maps the full range of an integer color channel to `[0, 1]`.
+    fn color_scale(self) -> Option<f64> {
+        match self {
+            Scalar::U8 => Some(255.),
+            Scalar::U16 => Some(65535.),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Property {
+    Scalar(Scalar, String),
+    /// A list with its count type and item type.
+    List(Scalar, Scalar, String),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match *self {
+            Property::Scalar(_, ref name) | Property::List(_, _, ref name) => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses the header and returns the format, the elements and where the body starts.
+fn parse_header(bytes: &[u8]) -> io::Result<(Format, Vec<Element>, usize)> {
+    const END: &[u8] = b"end_header";
+    let end = bytes.windows(END.len())
+                   .position(|w| w == END)
+                   .ok_or_else(|| invalid("missing `end_header`".to_string()))?;
+    let body = match bytes[end + END.len()..].iter().position(|&b| b == b'\n') {
+        Some(newline) => end + END.len() + newline + 1,
+        None => bytes.len(),
+    };
+    let header = str::from_utf8(&bytes[..end]).map_err(|_| invalid("header is not text".to_string()))?;
+    let mut lines = header.lines().enumerate();
+    match lines.next() {
+        Some((_, magic)) if magic.trim() == "ply" => {}
+        _ => return Err(invalid("not a PLY file".to_string())),
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for (i, line) in lines {
+        let line_no = i + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let scalar = |s: &str| Scalar::parse(s).ok_or_else(|| invalid(format!("line {}: unknown type `{}`", line_no, s)));
+        match words.first().cloned() {
+            Some("format") if words.len() == 3 => {
+                format = Some(match words[1] {
+                                  "ascii" => Format::Ascii,
+                                  "binary_little_endian" => Format::BinaryLittleEndian,
+                                  "binary_big_endian" => Format::BinaryBigEndian,
+                                  f => return Err(invalid(format!("line {}: unknown format `{}`", line_no, f))),
+                              })
+            }
+            Some("element") if words.len() == 3 => {
+                let count = words[2].parse()
+                                    .map_err(|_| invalid(format!("line {}: invalid count `{}`", line_no, words[2])))?;
+                elements.push(Element {
+                                  name: words[1].to_string(),
+                                  count,
+                                  properties: vec![],
+                              });
+            }
+            Some("property") => {
+                let property = match words.len() {
+                    3 => Property::Scalar(scalar(words[1])?, words[2].to_string()),
+                    5 if words[1] == "list" => Property::List(scalar(words[2])?, scalar(words[3])?, words[4].to_string()),
+                    _ => return Err(invalid(format!("line {}: malformed property", line_no))),
+                };
+                match elements.last_mut() {
+                    Some(e) => e.properties.push(property),
+                    None => return Err(invalid(format!("line {}: property before any element", line_no))),
+                }
+            }
+            Some("comment") | Some("obj_info") | None => {}
+            Some(other) => return Err(invalid(format!("line {}: unexpected `{}` in header", line_no, other))),
+        }
+    }
+    let format = format.ok_or_else(|| invalid("missing `format` line".to_string()))?;
+    // entries without properties take up no bytes, so nothing in the body would bound their count
+    if let Some(e) = elements.iter().find(|e| e.count > 0 && e.properties.is_empty()) {
+        return Err(invalid(format!("element `{}` has {} entries but no properties", e.name, e.count)));
+    }
+    Ok((format, elements, body))
+}
+
+/// Reads the values of the body one at a time, whatever the encoding.
+enum Values<'a> {
+    Ascii(str::SplitWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Values<'a> {
+    fn read(&mut self, t: Scalar) -> io::Result<f64> {
+        match *self {
+            Values::Ascii(ref mut words) => {
+                let word = words.next().ok_or_else(|| invalid("unexpected end of file".to_string()))?;
+                word.parse::<f64>().map_err(|_| invalid(format!("invalid number `{}`", word)))
+            }
+            Values::Binary { data, ref mut pos, big_endian } => {
+                let size = t.size();
+                if *pos + size > data.len() {
+                    return Err(invalid("unexpected end of file".to_string()));
+                }
+                let mut raw = [0u8; 8];
+                raw[..size].copy_from_slice(&data[*pos..*pos + size]);
+                if big_endian {
+                    raw[..size].reverse();
+                }
+                *pos += size;
+                Ok(match t {
+                       Scalar::I8 => raw[0] as i8 as f64,
+                       Scalar::U8 => raw[0] as f64,
+                       Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                       Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                       Scalar::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                       Scalar::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                       Scalar::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                       Scalar::F64 => f64::from_le_bytes(raw),
+                   })
+            }
+        }
+    }
+}
+
+/// Position of the first property called any of `names`.
+fn find(element: &Element, names: &[&str]) -> Option<usize> {
+    element.properties.iter().position(|p| names.contains(&p.name()))
+}
+
+/// Parses an ASCII or binary PLY file. Vertices need `x`, `y` and `z` and may have normals
+/// (`nx`, `ny`, `nz`), texture coordinates (`u`/`v`, `s`/`t`) and colors (`red`, `green`,
+/// `blue`); polygons in `face` are split into triangle fans. Integer colors are taken to be sRGB,
+/// and are only kept if `vertex_colors` is set. Other elements are skipped.
+pub fn parse_ply(bytes: &[u8], mat: Arc<dyn Material>, vertex_colors: bool) -> io::Result<MeshData> {
+    let (format, elements, body) = parse_header(bytes)?;
+    let mut values = match format {
+        Format::Ascii => {
+            let text = str::from_utf8(&bytes[body..]).map_err(|_| invalid("body is not text".to_string()))?;
+            Values::Ascii(text.split_whitespace())
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            Values::Binary {
+                data: &bytes[body..],
+                pos: 0,
+                big_endian: format == Format::BinaryBigEndian,
+            }
+        }
+    };
+    let mut data = MeshData {
+        materials: vec![mat],
+        ..MeshData::default()
+    };
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    for element in elements.iter() {
+        let position = [find(element, &["x"]), find(element, &["y"]), find(element, &["z"])];
+        let normal = [find(element, &["nx"]), find(element, &["ny"]), find(element, &["nz"])];
+        let uv = [find(element, &["u", "s", "texture_u", "texture_s"]), find(element, &["v", "t", "texture_v", "texture_t"])];
+        let color = [find(element, &["red", "diffuse_red"]),
+                     find(element, &["green", "diffuse_green"]),
+                     find(element, &["blue", "diffuse_blue"])];
+        let indices = find(element, &["vertex_indices", "vertex_index"]);
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex {
+            if position.iter().any(Option::is_none) {
+                return Err(invalid("vertices need x, y and z".to_string()));
+            }
+            has_normals = normal.iter().all(Option::is_some);
+            has_uvs = uv.iter().all(Option::is_some);
+        }
+        if is_face {
+            match indices.map(|i| &element.properties[i]) {
+                Some(&Property::List(..)) => {}
+                _ => return Err(invalid("faces need a `vertex_indices` list".to_string())),
+            }
+        }
+        let keep_colors = is_vertex && vertex_colors && color.iter().all(Option::is_some);
+        let mut scalars = vec![0.; element.properties.len()];
+        let mut list = vec![];
+        for n in 0..element.count {
+            let context = |e: io::Error| invalid(format!("{} {} of {}: {}", element.name, n, element.count, e));
+            for (k, property) in element.properties.iter().enumerate() {
+                match *property {
+                    Property::Scalar(t, _) => scalars[k] = values.read(t).map_err(context)?,
+                    Property::List(count_type, item_type, _) => {
+                        let count = values.read(count_type).map_err(context)?;
+                        if count < 0. {
+                            return Err(context(invalid(format!("negative list length {}", count))));
+                        }
+                        let items = (0..count as usize).map(|_| values.read(item_type))
+                                                       .collect::<io::Result<Vec<f64>>>()
+                                                       .map_err(context)?;
+                        if Some(k) == indices {
+                            list = items;
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                let get = |k: Option<usize>| scalars[k.unwrap()] as f32;
+                data.positions.push(Vec3::new(get(position[0]), get(position[1]), get(position[2])));
+                if has_normals {
+                    data.normals.push(Vec3::new(get(normal[0]), get(normal[1]), get(normal[2])));
+                }
+                if has_uvs {
+                    data.uvs.push((get(uv[0]), get(uv[1])));
+                }
+                if keep_colors {
+                    let channel = |k: Option<usize>| {
+                        let k = k.unwrap();
+                        match element.properties[k] {
+                            Property::Scalar(t, _) if t.color_scale().is_some() => {
+                                srgb_eotf((scalars[k] / t.color_scale().unwrap()) as f32)
+                            }
+                            _ => scalars[k] as f32,
+                        }
+                    };
+                    data.colors.push(Vec3::new(channel(color[0]), channel(color[1]), channel(color[2])));
+                }
+            } else if is_face {
+                if list.len() < 3 {
+                    return Err(context(invalid(format!("{} vertices", list.len()))));
+                }
+                if let Some(i) = list.iter().find(|&&i| i < 0.) {
+                    return Err(context(invalid(format!("negative vertex index {}", i))));
+                }
+                let corners: Vec<usize> = list.iter().map(|&i| i as usize).collect();
+                for k in 1..corners.len() - 1 {
+                    let positions = [corners[0], corners[k], corners[k + 1]];
+                    data.faces.push(Face {
+                                        positions,
+                                        normals: if has_normals { Some(positions) } else { None },
+                                        uvs: if has_uvs { Some(positions) } else { None },
+                                        material: 0,
+                                    });
+                }
+            }
+        }
+    }
+    let vertices = data.positions.len();
+    for (n, face) in data.faces.iter().enumerate() {
+        if let Some(&i) = face.positions.iter().find(|&&i| i >= vertices) {
+            return Err(invalid(format!("face triangle {}: vertex index {} out of range ({} vertices)", n, i, vertices)));
+        }
+    }
+    Ok(data)
+}
+
+/// Loads a PLY file as a mesh made of `mat`, or of its vertex colors if `vertex_colors` is set and
+/// the file has them.
+pub fn load<P: AsRef<Path>>(path: P, mat: Arc<dyn Material>, vertex_colors: bool) -> io::Result<TriangleMesh> {
+    let bytes = fs::read(path)?;
+    let data = parse_ply(&bytes, mat, vertex_colors)?;
+    TriangleMesh::new(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_ply;
+    use utils::material::dummy;
+    use utils::vec3::Vec3;
+
+    const HEADER: &str = "ply
+format FORMAT 1.0
+comment a colored quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    fn quad(format: &str) -> Vec<u8> {
+        HEADER.replace("FORMAT", format).into_bytes()
+    }
+
+    /// The same quad in binary, with every multi-byte value written by `put`.
+    fn binary(format: &str, put: fn(&mut Vec<u8>, [u8; 4])) -> Vec<u8> {
+        let mut bytes = quad(format);
+        let corners = [(0., 0., 255u8), (1., 0., 0), (1., 1., 255), (0., 1., 0)];
+        for &(x, y, c) in corners.iter() {
+            for v in [x, y, 0.0f32].iter() {
+                put(&mut bytes, v.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[c, 0, 0]);
+        }
+        bytes.push(4);
+        for i in 0..4i32 {
+            put(&mut bytes, i.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn little(out: &mut Vec<u8>, b: [u8; 4]) {
+        out.extend_from_slice(&b);
+    }
+
+    fn big(out: &mut Vec<u8>, mut b: [u8; 4]) {
+        b.reverse();
+        out.extend_from_slice(&b);
+    }
+
+    #[test]
+    fn ascii_and_binary_encodings_agree() {
+        let mut ascii = quad("ascii");
+        ascii.extend_from_slice(b"0 0 0 255 0 0\n1 0 0 0 0 0\n1 1 0 255 0 0\n0 1 0 0 0 0\n4 0 1 2 3\n");
+        let encodings = [ascii, binary("binary_little_endian", little), binary("binary_big_endian", big)];
+        for bytes in encodings.iter() {
+            let data = parse_ply(bytes, dummy(), true).unwrap();
+            assert_eq!(data.positions.len(), 4);
+            assert_eq!(data.positions[2], Vec3::new(1., 1., 0.));
+            assert_eq!(data.faces.len(), 2);
+            assert_eq!(data.faces[1].positions, [0, 2, 3]);
+            assert_eq!(data.colors[0], Vec3::new(1., 0., 0.));
+            assert_eq!(data.colors[1], Vec3::new(0., 0., 0.));
+            assert!(parse_ply(bytes, dummy(), false).unwrap().colors.is_empty());
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_reported() {
+        let mut bytes = binary("binary_little_endian", little);
+        bytes.truncate(bytes.len() - 3);
+        let msg = parse_ply(&bytes, dummy(), false).err().unwrap().to_string();
+        assert!(msg.contains("face 0 of 1") && msg.contains("end of file"), "{}", msg);
+
+        let mut ascii = quad("ascii");
+        ascii.extend_from_slice(b"0 0 0 1 1 1\n1 0 0 1 1 1\n1 1 0 1 1 1\n0 1 0 1 1 1\n3 0 1 7\n");
+        let msg = parse_ply(&ascii, dummy(), false).err().unwrap().to_string();
+        assert!(msg.contains("vertex index 7"), "{}", msg);
+
+        let msg = parse_ply(b"ply\nformat utf16 1.0\nend_header\n", dummy(), false).err().unwrap().to_string();
+        assert!(msg.contains("line 2") && msg.contains("utf16"), "{}", msg);
+        assert!(parse_ply(b"solid cube\n", dummy(), false).is_err());
+        let empty = b"ply\nformat ascii 1.0\nelement vertex 18446744073709551615\nend_header\n";
+        let msg = parse_ply(empty, dummy(), false).err().unwrap().to_string();
+        assert!(msg.contains("element `vertex`") && msg.contains("no properties"), "{}", msg);
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::{Aabb, surrounding_box};
+use utils::ray::Ray;
+use utils::vec3::{cross, dot, Vec3};
+use utils::material::Material;
+use utils::random::Sampler;
+use utils::rect::area_pdf;
+
+/// Parallelogram with corner `q` and edges `u` and `v`. It faces `u × v`, and `(u, v)` on a hit
+/// are the coordinates along the two edges in `[0, 1]`.
+#[allow(dead_code)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    normal: Vec3,
+    d: f32,
+    /// `n / |n|²` for `n = u × v`, which turns a point on the plane into edge coordinates.
+    w: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+#[allow(dead_code)]
+impl Quad {
+    /// `None` when `u` and `v` are parallel or zero, which leaves the quad without an area or a
+    /// normal.
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, m: Arc<dyn Material>) -> Option<Self> {
+        let n = cross(&u, &v);
+        let area = n.len();
+        if !(area > 0. && area.is_finite()) {
+            return None;
+        }
+        let normal = n.clone() / area;
+        let d = dot(&normal, &q);
+        let w = n.clone() / dot(&n, &n);
+        Some(Self {
+                 q,
+                 u,
+                 v,
+                 normal,
+                 d,
+                 w,
+                 mat: m,
+             })
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        let denom = dot(&self.normal, r.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = (self.d - dot(&self.normal, r.origin())) / denom;
+        if !(t > t_min && t < t_max) {
+            return false;
+        }
+        let p = r.point_at_parameter(t);
+        let planar = p.clone() - self.q.clone();
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return false;
+        }
+        rec.t = t;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.p = p;
+        rec.normal = self.normal.clone();
+        rec.mat = self.mat.clone();
+        true
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        let corners = [self.q.clone() + self.u.clone(),
+                       self.q.clone() + self.v.clone(),
+                       self.q.clone() + self.u.clone() + self.v.clone()];
+        let mut bbox = Aabb::new(self.q.clone(), self.q.clone());
+        for c in corners.iter() {
+            bbox = surrounding_box(&bbox, &Aabb::new(c.clone(), c.clone()));
+        }
+        // keep some thickness along axes the quad is flat in
+        let pad = |lo: f32, hi: f32| if hi - lo < 1e-4 { (lo - 1e-4, hi + 1e-4) } else { (lo, hi) };
+        let (x0, x1) = pad(bbox.min.x(), bbox.max.x());
+        let (y0, y1) = pad(bbox.min.y(), bbox.max.y());
+        let (z0, z1) = pad(bbox.min.z(), bbox.max.z());
+        *output_box = Aabb::new(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1));
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new(self.mat.clone());
+        // surfaces draw no random numbers while hitting, so any sampler does
+        if !self.hit(&Ray::new(origin, direction), 0.001, f32::MAX, &mut rec, &mut Sampler::new(0)) {
+            return 0.;
+        }
+        let area = cross(&self.u, &self.v).len();
+        area_pdf(area, rec.t, direction, dot(&self.normal, direction).abs())
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        self.q.clone() + self.u.clone() * sampler.drand48() + self.v.clone() * sampler.drand48() - origin.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Quad;
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    #[test]
+    fn tilted_quad_hits_inside_its_edges() {
+        let mut sampler = Sampler::new(0);
+        // a unit square tilted 45 degrees about the x axis
+        let h = 0.5f32.sqrt();
+        let quad = Quad::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., h, -h), dummy()).unwrap();
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0.25, 0.5 * h, 5.), &Vec3::new(0., 0., -1.));
+        assert!(quad.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.u - 0.25).abs() < 1e-5 && (rec.v - 0.5).abs() < 1e-5, "{} {}", rec.u, rec.v);
+        assert!((rec.normal.y() - h).abs() < 1e-6 && (rec.normal.z() - h).abs() < 1e-6);
+        let outside = Ray::new(&Vec3::new(1.25, 0.5 * h, 5.), &Vec3::new(0., 0., -1.));
+        assert!(!quad.hit(&outside, 0.001, f32::MAX, &mut rec, &mut sampler));
+
+        let mut bbox = Aabb::default();
+        assert!(quad.bounding_box(0., 1., &mut bbox));
+        assert!((bbox.max.y() - h).abs() < 1e-6 && (bbox.min.z() + h).abs() < 1e-6);
+        let flat = Quad::new(Vec3::new(0., 1., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.), dummy()).unwrap();
+        assert!(flat.bounding_box(0., 1., &mut bbox));
+        assert!(bbox.min.y() < 1. && bbox.max.y() > 1.);
+    }
+
+    #[test]
+    fn degenerate_quads_are_rejected() {
+        let q = Vec3::new(0., 0., 0.);
+        assert!(Quad::new(q.clone(), Vec3::new(1., 0., 0.), Vec3::new(-2., 0., 0.), dummy()).is_none());
+        assert!(Quad::new(q.clone(), Vec3::new(0., 0., 0.), Vec3::new(0., 1., 0.), dummy()).is_none());
+        assert!(Quad::new(q, Vec3::new(1., 0., 0.), Vec3::new(f32::NAN, 1., 0.), dummy()).is_none());
+    }
+}
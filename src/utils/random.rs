@@ -1,8 +1,49 @@
-extern crate rand;
+/// Small seedable PCG32 generator. Every pixel gets its own stream derived from the render seed,
+/// so an image does not depend on the order or thread its pixels are rendered in.
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    state: u64,
+    inc: u64,
+}
+
+#[allow(dead_code)]
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut s = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        s.next_u32();
+        s.state = s.state.wrapping_add(splitmix64(seed));
+        s.next_u32();
+        s
+    }
 
-use self::rand::{thread_rng, Rng};
+    pub fn for_pixel(seed: u64, i: usize, j: usize) -> Self {
+        Self::with_stream(seed, ((j as u64) << 32) | i as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform value in `[0, 1)`.
+    pub fn drand48(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1. / (1 << 24) as f32)
+    }
+}
 
-pub fn drand48() -> f32 {
-    let mut rng = thread_rng();
-    rng.gen_range(0., 1.)
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
@@ -5,14 +5,22 @@ use utils::vec3;
 pub struct Ray {
     pub a: vec3::Vec3,
     pub b: vec3::Vec3,
+    /// Moment within the camera shutter interval the ray samples; moving objects are hit where
+    /// they are at this time.
+    pub time: f32,
 }
 
 #[allow(dead_code)]
 impl Ray {
     pub fn new(a: &vec3::Vec3, b: &vec3::Vec3) -> Self {
+        Self::with_time(a, b, 0.)
+    }
+
+    pub fn with_time(a: &vec3::Vec3, b: &vec3::Vec3, time: f32) -> Self {
         Self {
             a: a.clone(),
             b: b.clone(),
+            time,
         }
     }
 
@@ -24,6 +32,10 @@ impl Ray {
         &self.b
     }
 
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
     pub fn point_at_parameter(&self, t: f32) -> vec3::Vec3 {
         let z: vec3::Vec3 = self.a.clone() + self.b.clone() * t;
         z
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+use utils::hitable::{FlipFace, Hitable, HitableList, HitRecord};
+use utils::aabb::Aabb;
+use utils::ray::Ray;
+use utils::vec3::Vec3;
+use utils::material::Material;
+use utils::random::Sampler;
+
+/// Converts the uniform density `1 / area` over a flat light into a density per unit solid angle
+/// seen along `direction`, which reaches the light at ray parameter `t`. `cos_scaled` is the
+/// cosine between the direction and the light's normal, times the length of `direction`.
+pub fn area_pdf(area: f32, t: f32, direction: &Vec3, cos_scaled: f32) -> f32 {
+    let len2 = direction.squared_len();
+    let distance2 = t * t * len2;
+    let cosine = cos_scaled / len2.sqrt();
+    if cosine <= 0. {
+        return 0.;
+    }
+    distance2 / (cosine * area)
+}
+
+/// Half thickness given to the bounding box of a flat rectangle so it has some volume.
+const PAD: f32 = 1e-4;
+
+macro_rules! axis_rect {
+    ($(#[$doc:meta])* $name:ident, $a0:ident, $a1:ident, $b0:ident, $b1:ident, $a:expr, $b:expr, $c:expr) => {
+        $(#[$doc])*
+        #[allow(dead_code)]
+        pub struct $name {
+            pub $a0: f32,
+            pub $a1: f32,
+            pub $b0: f32,
+            pub $b1: f32,
+            pub k: f32,
+            mat: Arc<dyn Material>,
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn new($a0: f32, $a1: f32, $b0: f32, $b1: f32, k: f32, m: Arc<dyn Material>) -> Self {
+                Self {
+                    $a0,
+                    $a1,
+                    $b0,
+                    $b1,
+                    k,
+                    mat: m,
+                }
+            }
+        }
+
+        impl Hitable for $name {
+            fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+                let t = (self.k - r.origin()[$c]) / r.direction()[$c];
+                if !(t > t_min && t < t_max) {
+                    return false;
+                }
+                let a = r.origin()[$a] + t * r.direction()[$a];
+                let b = r.origin()[$b] + t * r.direction()[$b];
+                if a < self.$a0 || a > self.$a1 || b < self.$b0 || b > self.$b1 {
+                    return false;
+                }
+                rec.t = t;
+                rec.u = (a - self.$a0) / (self.$a1 - self.$a0);
+                rec.v = (b - self.$b0) / (self.$b1 - self.$b0);
+                rec.p = r.point_at_parameter(t);
+                let mut normal = [0.; 3];
+                normal[$c] = 1.;
+                rec.normal = Vec3::new(normal[0], normal[1], normal[2]);
+                rec.mat = self.mat.clone();
+                true
+            }
+
+            fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+                let mut min = [0.; 3];
+                let mut max = [0.; 3];
+                min[$a] = self.$a0;
+                max[$a] = self.$a1;
+                min[$b] = self.$b0;
+                max[$b] = self.$b1;
+                min[$c] = self.k - PAD;
+                max[$c] = self.k + PAD;
+                *output_box = Aabb::new(Vec3::new(min[0], min[1], min[2]), Vec3::new(max[0], max[1], max[2]));
+                true
+            }
+
+            fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+                let mut rec = HitRecord::new(self.mat.clone());
+                // surfaces draw no random numbers while hitting, so any sampler does
+                if !self.hit(&Ray::new(origin, direction), 0.001, f32::MAX, &mut rec, &mut Sampler::new(0)) {
+                    return 0.;
+                }
+                let area = (self.$a1 - self.$a0) * (self.$b1 - self.$b0);
+                area_pdf(area, rec.t, direction, direction[$c].abs())
+            }
+
+            fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+                let mut p = [0.; 3];
+                p[$a] = self.$a0 + sampler.drand48() * (self.$a1 - self.$a0);
+                p[$b] = self.$b0 + sampler.drand48() * (self.$b1 - self.$b0);
+                p[$c] = self.k;
+                Vec3::new(p[0], p[1], p[2]) - origin.clone()
+            }
+        }
+    };
+}
+
+axis_rect!(
+    /// Rectangle in the plane `z = k` facing +z, with `u` along x and `v` along y.
+    XyRect, x0, x1, y0, y1, 0, 1, 2);
+axis_rect!(
+    /// Rectangle in the plane `y = k` facing +y, with `u` along x and `v` along z.
+    XzRect, x0, x1, z0, z1, 0, 2, 1);
+axis_rect!(
+    /// Rectangle in the plane `x = k` facing +x, with `u` along y and `v` along z.
+    YzRect, y0, y1, z0, z1, 1, 2, 0);
+
+/// Axis-aligned box between the corners `p0` and `p1`, made of six rectangles facing outwards.
+#[allow(dead_code)]
+pub struct BoxShape {
+    pub p0: Vec3,
+    pub p1: Vec3,
+    sides: HitableList,
+}
+
+#[allow(dead_code)]
+impl BoxShape {
+    pub fn new(p0: Vec3, p1: Vec3, m: Arc<dyn Material>) -> Self {
+        let (x0, y0, z0) = (p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let (x1, y1, z1) = (p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+        let sides: Vec<Box<dyn Hitable>> = vec![Box::new(XyRect::new(x0, x1, y0, y1, z1, m.clone())),
+                                                Box::new(FlipFace::new(XyRect::new(x0, x1, y0, y1, z0, m.clone()))),
+                                                Box::new(XzRect::new(x0, x1, z0, z1, y1, m.clone())),
+                                                Box::new(FlipFace::new(XzRect::new(x0, x1, z0, z1, y0, m.clone()))),
+                                                Box::new(YzRect::new(y0, y1, z0, z1, x1, m.clone())),
+                                                Box::new(FlipFace::new(YzRect::new(y0, y1, z0, z1, x0, m)))];
+        Self {
+            p0: Vec3::new(x0, y0, z0),
+            p1: Vec3::new(x1, y1, z1),
+            sides: HitableList::new(sides),
+        }
+    }
+}
+
+impl Hitable for BoxShape {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, sampler: &mut Sampler) -> bool {
+        self.sides.hit(r, t_min, t_max, rec, sampler)
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::new(self.p0.clone(), self.p1.clone());
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+        self.sides.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        self.sides.random(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxShape, XzRect, YzRect};
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    #[test]
+    fn rect_hit_reports_uv_and_normal() {
+        let mut sampler = Sampler::new(0);
+        let rect = XzRect::new(-1., 3., 0., 2., 5., dummy());
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0., 0., 0.5), &Vec3::new(0., 1., 0.));
+        assert!(rect.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert_eq!(rec.t, 5.);
+        assert_eq!((rec.u, rec.v), (0.25, 0.25));
+        assert_eq!(rec.normal.e, (0., 1., 0.));
+        let miss = Ray::new(&Vec3::new(0., 0., 2.5), &Vec3::new(0., 1., 0.));
+        assert!(!rect.hit(&miss, 0.001, f32::MAX, &mut rec, &mut sampler));
+        let parallel = Ray::new(&Vec3::new(0., 0., 0.5), &Vec3::new(1., 0., 0.));
+        assert!(!rect.hit(&parallel, 0.001, f32::MAX, &mut rec, &mut sampler));
+
+        let mut bbox = Aabb::default();
+        assert!(YzRect::new(0., 1., 0., 1., 2., dummy()).bounding_box(0., 1., &mut bbox));
+        assert!(bbox.min.x() < 2. && bbox.max.x() > 2.);
+    }
+
+    #[test]
+    fn box_normals_point_outwards() {
+        let mut sampler = Sampler::new(0);
+        let b = BoxShape::new(Vec3::new(1., 1., 1.), Vec3::new(-1., -1., -1.), dummy());
+        let dirs = [Vec3::new(1., 0., 0.),
+                    Vec3::new(-1., 0., 0.),
+                    Vec3::new(0., 1., 0.),
+                    Vec3::new(0., -1., 0.),
+                    Vec3::new(0., 0., 1.),
+                    Vec3::new(0., 0., -1.)];
+        for d in dirs.iter() {
+            // shoot from outside towards the center
+            let r = Ray::new(&(d.clone() * 5.), &(d.clone() * -1.));
+            let mut rec = HitRecord::new(dummy());
+            assert!(b.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+            assert_eq!(rec.t, 4.);
+            assert_eq!(rec.normal.e, d.e);
+        }
+        let mut bbox = Aabb::default();
+        assert!(b.bounding_box(0., 1., &mut bbox));
+        assert_eq!(bbox.min.e, (-1., -1., -1.));
+    }
+}
@@ -0,0 +1,911 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use utils::vec3::{Vec3, unit_vector};
+use utils::ray::Ray;
+use utils::hitable::{Hitable, HitRecord};
+use utils::camera::Camera;
+use utils::material::dummy;
+use utils::light::{visibility, Light};
+use utils::sampling::random_in_unit_sphere;
+use utils::random::Sampler;
+use utils::film::{Film, Pixel};
+
+/// How a hit is turned into a color. The early chapters shade by surface normal or with a fixed
+/// 50% grey diffuse bounce before materials are introduced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    Normal,
+    Diffuse,
+    Material,
+}
+
+/// How `Shading::Material` estimates the light arriving along a camera ray.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Integrator {
+    /// Follows `scatter` until the path leaves the scene, is absorbed or runs out of bounces.
+    #[serde(rename = "brute")]
+    Brute,
+    /// Also samples the scene's lights directly at every diffuse hit; see `next_event_color`.
+    #[serde(rename = "nee")]
+    NextEvent,
+    /// Weighs light sampling against `scatter` with the power heuristic; see `mis_color`.
+    #[serde(rename = "mis")]
+    Mis,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "brute" => Ok(Integrator::Brute),
+            "nee" => Ok(Integrator::NextEvent),
+            "mis" => Ok(Integrator::Mis),
+            _ => Err(format!("invalid integrator: {}", s)),
+        }
+    }
+}
+
+/// Radiance returned by rays that leave the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    /// The white to blue gradient from the first chapters.
+    Sky,
+    Solid(Vec3),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Vec3 {
+        match *self {
+            Background::Sky => {
+                let unit_direction = unit_vector(r.direction().clone());
+                let t: f32 = 0.5 * (unit_direction.y() + 1.);
+                Vec3::new(1., 1., 1.) * (1. - t) + Vec3::new(0.5, 0.7, 1.) * t
+            }
+            Background::Solid(ref col) => col.clone(),
+        }
+    }
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    /// Accepts `sky`, `black` or an `r,g,b` triple.
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "sky" => return Ok(Background::Sky),
+            "black" => return Ok(Background::Solid(Vec3::new(0., 0., 0.))),
+            _ => {}
+        }
+        let c: Vec<f32> = s.split(',')
+                           .map(|v| v.trim().parse::<f32>())
+                           .collect::<Result<_, _>>()
+                           .map_err(|_| format!("invalid background: {}", s))?;
+        if c.len() != 3 {
+            return Err(format!("invalid background: {}", s));
+        }
+        Ok(Background::Solid(Vec3::new(c[0], c[1], c[2])))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: i32,
+    /// Bounces before Russian roulette may end a path; see `Path`. It is off by default, with
+    /// `min_depth` equal to `max_depth`.
+    pub min_depth: i32,
+    pub shading: Shading,
+    pub integrator: Integrator,
+    pub background: Background,
+    /// Base seed for the per-pixel samplers; the same seed always gives the same image.
+    pub seed: u64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 200,
+            height: 100,
+            samples: 100,
+            max_depth: 50,
+            min_depth: 50,
+            shading: Shading::Material,
+            integrator: Integrator::Brute,
+            background: Background::Sky,
+            seed: 0,
+        }
+    }
+}
+
+/// A camera path on its way through the scene. It ends after `max_depth` bounces, and from
+/// `min_depth` bounces on Russian roulette may end it sooner: the less light the path can still
+/// carry back to the camera, the likelier it stops, and paths that go on are scaled up by the
+/// same chance so the image stays unbiased. Dark paths stop early and bright ones keep going.
+#[derive(Clone, Debug)]
+pub struct Path {
+    /// Bounces taken so far.
+    pub depth: i32,
+    pub max_depth: i32,
+    pub min_depth: i32,
+    /// Product of the attenuations so far, over the chances of surviving the roulette.
+    pub throughput: Vec3,
+}
+
+impl Path {
+    pub fn new(max_depth: i32, min_depth: i32) -> Self {
+        Self {
+            depth: 0,
+            max_depth,
+            min_depth,
+            throughput: Vec3::new(1., 1., 1.),
+        }
+    }
+
+    pub fn can_bounce(&self) -> bool {
+        self.depth < self.max_depth
+    }
+
+    /// Takes a bounce that scatters with `attenuation` and returns the weight of the light found
+    /// beyond it, or `None` if the roulette ends the path here.
+    pub fn bounce(&mut self, attenuation: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let mut weight = attenuation.clone();
+        if self.depth >= self.min_depth {
+            let throughput = self.throughput.clone() * weight.clone();
+            let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(1.);
+            if sampler.drand48() >= survival {
+                return None;
+            }
+            weight = weight / survival;
+        }
+        self.depth += 1;
+        self.throughput = self.throughput.clone() * weight.clone();
+        Some(weight)
+    }
+}
+
+/// Counts gathered over a render.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    /// Camera paths traced.
+    pub paths: u64,
+    /// Bounces taken by all of them.
+    pub bounces: u64,
+}
+
+impl RenderStats {
+    pub fn add_path(&mut self, path: &Path) {
+        self.paths += 1;
+        self.bounces += path.depth as u64;
+    }
+
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+    }
+
+    /// Mean number of bounces per camera path.
+    pub fn average_path_length(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.;
+        }
+        self.bounces as f64 / self.paths as f64
+    }
+}
+
+pub fn color(r: &Ray,
+             world: &dyn Hitable,
+             delta_lights: &[Box<dyn Light>],
+             background: &Background,
+             path: &mut Path,
+             sampler: &mut Sampler)
+             -> Vec3 {
+    let mut rec = HitRecord::new(dummy());
+    if world.hit(r, 0.001, f32::MAX, &mut rec, sampler) {
+        let emitted = rec.mat.as_ref().emitted(r, &rec);
+        if path.can_bounce() {
+            if let Some(scattered) = rec.mat.as_ref().scatter(r, &rec, sampler) {
+                let direct = if scattered.specular {
+                    Vec3::new(0., 0., 0.)
+                } else {
+                    delta_light_color(r, &rec, world, delta_lights, sampler)
+                };
+                return match path.bounce(&scattered.attenuation, sampler) {
+                    Some(weight) => {
+                        let indirect = color(&scattered.ray, world, delta_lights, background, path, sampler);
+                        emitted + direct + weight * indirect
+                    }
+                    None => emitted + direct,
+                };
+            }
+        }
+        return emitted;
+    }
+    background.color(r)
+}
+
+/// Path tracing with next-event estimation. Wherever the material is not specular, a shadow ray
+/// is also sent towards a point sampled on `lights`, and the path continuing from that hit no
+/// longer counts light from `lights` it runs into, so every light path is counted exactly once.
+/// It converges to the same image as `color`, with far less noise from small lights.
+pub fn next_event_color(r: &Ray,
+                        world: &dyn Hitable,
+                        lights: &dyn Hitable,
+                        delta_lights: &[Box<dyn Light>],
+                        background: &Background,
+                        path: &mut Path,
+                        sampler: &mut Sampler)
+                        -> Vec3 {
+    trace_next_event(r, world, lights, delta_lights, background, path, sampler, true)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn trace_next_event(r: &Ray,
+                    world: &dyn Hitable,
+                    lights: &dyn Hitable,
+                    delta_lights: &[Box<dyn Light>],
+                    background: &Background,
+                    path: &mut Path,
+                    sampler: &mut Sampler,
+                    count_lights: bool)
+                    -> Vec3 {
+    let mut rec = HitRecord::new(dummy());
+    if !world.hit(r, 0.001, f32::MAX, &mut rec, sampler) {
+        return background.color(r);
+    }
+    let emitted = if count_lights || !on_light(lights, r, &rec, sampler) {
+        rec.mat.emitted(r, &rec)
+    } else {
+        Vec3::new(0., 0., 0.)
+    };
+    if !path.can_bounce() {
+        return emitted;
+    }
+    let scattered = match rec.mat.scatter(r, &rec, sampler) {
+        Some(scattered) => scattered,
+        None => return emitted,
+    };
+    let direct = if scattered.specular {
+        Vec3::new(0., 0., 0.)
+    } else {
+        let sampled = sample_lights(r, &rec, world, lights, sampler).map_or(Vec3::new(0., 0., 0.), |(light, _, _)| light);
+        sampled + delta_light_color(r, &rec, world, delta_lights, sampler)
+    };
+    match path.bounce(&scattered.attenuation, sampler) {
+        Some(weight) => {
+            let (ray, specular) = (&scattered.ray, scattered.specular);
+            let indirect = trace_next_event(ray, world, lights, delta_lights, background, path, sampler, specular);
+            emitted + direct + weight * indirect
+        }
+        None => emitted + direct,
+    }
+}
+
+/// Path tracing with multiple importance sampling. Like `next_event_color` it samples `lights`
+/// at every hit that is not specular, but light reached both that way and by following `scatter`
+/// is kept from each strategy, weighted by `power_heuristic`. Small lights seen from rough surfaces
+/// are found by the shadow rays and large lights in sharp glossy reflections by `scatter`, so
+/// neither is as noisy as with one of the strategies alone.
+pub fn mis_color(r: &Ray,
+                 world: &dyn Hitable,
+                 lights: &dyn Hitable,
+                 delta_lights: &[Box<dyn Light>],
+                 background: &Background,
+                 path: &mut Path,
+                 sampler: &mut Sampler)
+                 -> Vec3 {
+    trace_mis(r, world, lights, delta_lights, background, path, sampler, None)
+}
+
+/// `scatter_pdf` is the density with which the last hit picked `r`, or `None` for camera rays and
+/// specular bounces, whose light is not weighed against anything.
+#[allow(clippy::too_many_arguments)]
+fn trace_mis(r: &Ray,
+             world: &dyn Hitable,
+             lights: &dyn Hitable,
+             delta_lights: &[Box<dyn Light>],
+             background: &Background,
+             path: &mut Path,
+             sampler: &mut Sampler,
+             scatter_pdf: Option<f32>)
+             -> Vec3 {
+    let mut rec = HitRecord::new(dummy());
+    if !world.hit(r, 0.001, f32::MAX, &mut rec, sampler) {
+        return background.color(r);
+    }
+    let emitted = rec.mat.emitted(r, &rec);
+    let emitted = match scatter_pdf {
+        Some(pdf) if on_light(lights, r, &rec, sampler) => {
+            emitted * power_heuristic(pdf, lights.pdf_value(r.origin(), r.direction()))
+        }
+        _ => emitted,
+    };
+    if !path.can_bounce() {
+        return emitted;
+    }
+    let scattered = match rec.mat.scatter(r, &rec, sampler) {
+        Some(scattered) => scattered,
+        None => return emitted,
+    };
+    let (direct, pdf) = if scattered.specular {
+        (Vec3::new(0., 0., 0.), None)
+    } else {
+        let direct = sample_lights(r, &rec, world, lights, sampler)
+            .map_or(Vec3::new(0., 0., 0.), |(light, light_pdf, pdf)| light * power_heuristic(light_pdf, pdf));
+        (direct + delta_light_color(r, &rec, world, delta_lights, sampler), Some(scattered.pdf))
+    };
+    match path.bounce(&scattered.attenuation, sampler) {
+        Some(weight) => {
+            let indirect = trace_mis(&scattered.ray, world, lights, delta_lights, background, path, sampler, pdf);
+            emitted + direct + weight * indirect
+        }
+        None => emitted + direct,
+    }
+}
+
+/// Veach's power heuristic with an exponent of 2: the weight of a sample taken with density
+/// `pdf` when `other_pdf` could have produced it too.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. { a / (a + b) } else { 0. }
+}
+
+/// Shadow ray from the hit `rec` towards a point sampled on `lights`. If it reaches that light,
+/// returns the light reflected back along `r` divided by the light's pdf, then the densities with
+/// which `lights` and the material pick the direction.
+fn sample_lights(r: &Ray,
+                 rec: &HitRecord,
+                 world: &dyn Hitable,
+                 lights: &dyn Hitable,
+                 sampler: &mut Sampler)
+                 -> Option<(Vec3, f32, f32)> {
+    let direction = lights.random(&rec.p, sampler);
+    let light_pdf = lights.pdf_value(&rec.p, &direction);
+    if light_pdf <= 0. || !light_pdf.is_finite() {
+        return None;
+    }
+    let shadow = Ray::with_time(&rec.p, &direction, r.time());
+    let reflected = rec.mat.eval(r, rec, &shadow);
+    if reflected == Vec3::new(0., 0., 0.) {
+        return None;
+    }
+    let mut light_rec = HitRecord::new(dummy());
+    if !lights.hit(&shadow, 0.001, f32::MAX, &mut light_rec, sampler) {
+        return None;
+    }
+    // anything solid in front of the light blocks it, and media dim it
+    let visible = world.transmittance(&shadow, 0.001, light_rec.t - 1e-4 * light_rec.t.max(1.), sampler);
+    if visible == 0. {
+        return None;
+    }
+    let light = light_rec.mat.emitted(&shadow, &light_rec) * reflected * visible / light_pdf;
+    Some((light, light_pdf, rec.mat.pdf(r, rec, &shadow)))
+}
+
+/// Light that `delta_lights` reflect at the hit `rec` back along `r`. Each of them reaches `rec`
+/// from a single direction, so one shadow ray per light gives it exactly, without any noise.
+fn delta_light_color(r: &Ray,
+                     rec: &HitRecord,
+                     world: &dyn Hitable,
+                     delta_lights: &[Box<dyn Light>],
+                     sampler: &mut Sampler)
+                     -> Vec3 {
+    let mut total = Vec3::new(0., 0., 0.);
+    for light in delta_lights {
+        let sample = match light.illuminate(&rec.p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let shadow = Ray::with_time(&rec.p, &sample.direction, r.time());
+        let reflected = rec.mat.eval(r, rec, &shadow);
+        if reflected != Vec3::new(0., 0., 0.) {
+            let visible = visibility(world, &shadow, &sample, sampler);
+            total = total + reflected * sample.irradiance * visible;
+        }
+    }
+    total
+}
+
+/// Whether `rec`, the closest hit along `r`, lies on one of `lights`.
+fn on_light(lights: &dyn Hitable, r: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> bool {
+    let mut light_rec = HitRecord::new(dummy());
+    lights.hit(r, 0.001, f32::MAX, &mut light_rec, sampler) && (light_rec.t - rec.t).abs() <= 1e-4 * rec.t.max(1.)
+}
+
+pub fn normal_color(r: &Ray, world: &dyn Hitable, background: &Background, sampler: &mut Sampler) -> Vec3 {
+    let mut rec = HitRecord::new(dummy());
+    if world.hit(r, 0., f32::MAX, &mut rec, sampler) {
+        return Vec3::new(rec.normal.x() + 1., rec.normal.y() + 1., rec.normal.z() + 1.) * 0.5;
+    }
+    background.color(r)
+}
+
+pub fn diffuse_color(r: &Ray,
+                     world: &dyn Hitable,
+                     background: &Background,
+                     path: &mut Path,
+                     sampler: &mut Sampler)
+                     -> Vec3 {
+    let mut rec = HitRecord::new(dummy());
+    if world.hit(r, 0.001, f32::MAX, &mut rec, sampler) {
+        if !path.can_bounce() {
+            return Vec3::new(0., 0., 0.);
+        }
+        let target = rec.normal.clone() + random_in_unit_sphere(sampler);
+        let scattered = Ray::with_time(&rec.p, &target, r.time());
+        return match path.bounce(&Vec3::new(0.5, 0.5, 0.5), sampler) {
+            Some(weight) => diffuse_color(&scattered, world, background, path, sampler) * weight,
+            None => Vec3::new(0., 0., 0.),
+        };
+    }
+    background.color(r)
+}
+
+/// `lights` are the emitters that `Integrator::NextEvent` and `Integrator::Mis` sample directly;
+/// an empty list is fine. `delta_lights` light the scene under every integrator of
+/// `Shading::Material`. The path's length is added to `stats`.
+#[allow(clippy::too_many_arguments)]
+pub fn sample(r: &Ray,
+              world: &dyn Hitable,
+              lights: &dyn Hitable,
+              delta_lights: &[Box<dyn Light>],
+              settings: &RenderSettings,
+              sampler: &mut Sampler,
+              stats: &mut RenderStats)
+              -> Vec3 {
+    let background = &settings.background;
+    let path = &mut Path::new(settings.max_depth, settings.min_depth);
+    let col = match (settings.shading, settings.integrator) {
+        (Shading::Normal, _) => normal_color(r, world, background, sampler),
+        (Shading::Diffuse, _) => diffuse_color(r, world, background, path, sampler),
+        (Shading::Material, Integrator::Brute) => color(r, world, delta_lights, background, path, sampler),
+        (Shading::Material, Integrator::NextEvent) => {
+            next_event_color(r, world, lights, delta_lights, background, path, sampler)
+        }
+        (Shading::Material, Integrator::Mis) => mis_color(r, world, lights, delta_lights, background, path, sampler),
+    };
+    stats.add_path(path);
+    col
+}
+
+/// Adds `settings.samples` jittered samples for pixel `(i, j)` to `pixel`, where `j` counts up
+/// from the bottom row. The pixel's random numbers come from its own stream of `settings.seed`.
+/// Returns the stats of the pixel's paths.
+#[allow(clippy::too_many_arguments)]
+pub fn render_pixel(world: &dyn Hitable,
+                    lights: &dyn Hitable,
+                    delta_lights: &[Box<dyn Light>],
+                    cam: &Camera,
+                    settings: &RenderSettings,
+                    i: usize,
+                    j: usize,
+                    pixel: &mut Pixel)
+                    -> RenderStats {
+    let nx = settings.width as f32;
+    let ny = settings.height as f32;
+    let mut sampler = Sampler::for_pixel(settings.seed, i, j);
+    let mut stats = RenderStats::default();
+    for _ in 0..settings.samples {
+        let u: f32 = (i as f32 + sampler.drand48()) / nx;
+        let v: f32 = (j as f32 + sampler.drand48()) / ny;
+        let r = cam.get_ray(u, v, &mut sampler);
+        pixel.add_sample(sample(&r, world, lights, delta_lights, settings, &mut sampler, &mut stats));
+    }
+    stats
+}
+
+/// Renders the whole image on the calling thread.
+pub fn render(world: &dyn Hitable,
+              lights: &dyn Hitable,
+              delta_lights: &[Box<dyn Light>],
+              cam: &Camera,
+              settings: &RenderSettings)
+              -> (Film, RenderStats) {
+    let (width, height) = (settings.width, settings.height);
+    let mut film = Film::new(width, height);
+    let mut stats = RenderStats::default();
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = film.pixel_mut(x, y);
+            stats.merge(&render_pixel(world, lights, delta_lights, cam, settings, x, height - 1 - y, pixel));
+        }
+    }
+    (film, stats)
+}
+
+/// Edge length in pixels of the square tiles handed out by `render_parallel`.
+pub const TILE_SIZE: usize = 16;
+
+/// Number of worker threads `render_parallel` should use when none is given.
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Renders the image in `TILE_SIZE` tiles on `threads` workers. Workers take the next tile from a
+/// shared counter and copy each finished tile into the film, so the result is identical to
+/// `render`.
+pub fn render_parallel(world: &dyn Hitable,
+                       lights: &dyn Hitable,
+                       delta_lights: &[Box<dyn Light>],
+                       cam: &Camera,
+                       settings: &RenderSettings,
+                       threads: usize)
+                       -> (Film, RenderStats) {
+    let (width, height) = (settings.width, settings.height);
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+    let tile_count = tiles_x * tiles_y;
+    let next_tile = AtomicUsize::new(0);
+    let film = Mutex::new((Film::new(width, height), RenderStats::default()));
+
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                loop {
+                    let tile = next_tile.fetch_add(1, Ordering::SeqCst);
+                    if tile >= tile_count {
+                        return;
+                    }
+                    let x0 = (tile % tiles_x) * TILE_SIZE;
+                    let y0 = (tile / tiles_x) * TILE_SIZE;
+                    let x1 = (x0 + TILE_SIZE).min(width);
+                    let y1 = (y0 + TILE_SIZE).min(height);
+
+                    let mut pixels = vec![Pixel::default(); (x1 - x0) * (y1 - y0)];
+                    let mut tile_pixels = pixels.iter_mut();
+                    let mut tile_stats = RenderStats::default();
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            let pixel = tile_pixels.next().unwrap();
+                            let j = height - 1 - y;
+                            tile_stats.merge(&render_pixel(world, lights, delta_lights, cam, settings, x, j, pixel));
+                        }
+                    }
+
+                    let (ref mut film, ref mut stats) = *film.lock().unwrap();
+                    stats.merge(&tile_stats);
+                    let mut pixels = pixels.into_iter();
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            *film.pixel_mut(x, y) = pixels.next().unwrap();
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    film.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+    use super::{color, mis_color, next_event_color, render, render_parallel, Background, Path, RenderSettings,
+                RenderStats, Shading, TILE_SIZE};
+    use utils::aabb::Aabb;
+    use utils::hitable::{Hitable, HitableList};
+    use utils::light::{DirectionalLight, Light, PointLight, SpotLight};
+    use utils::material::{dummy, DiffuseLight, Lambertian, Material, Metal};
+    use utils::medium::{ConstantMedium, GridMedium, Isotropic};
+    use utils::rect::BoxShape;
+    use utils::voxel::VoxelGrid;
+    use utils::random::Sampler;
+    use utils::quad::Quad;
+    use utils::ray::Ray;
+    use utils::scene::{by_name, camera_preset};
+    use utils::sphere::Sphere;
+    use utils::vec3::Vec3;
+
+    fn mean_and_variance<F: FnMut(&mut Sampler) -> Vec3>(samples: usize, mut f: F) -> (f32, f32) {
+        let mut sampler = Sampler::new(7);
+        let values: Vec<f32> = (0..samples).map(|_| f(&mut sampler).e.0).collect();
+        let mean = values.iter().sum::<f32>() / samples as f32;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / samples as f32;
+        (mean, variance)
+    }
+
+    /// A grey floor and ball lit by a small lamp, and the lamp on its own.
+    fn lamp_over_floor(albedo: f32) -> (HitableList, HitableList) {
+        let grey: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(albedo, albedo, albedo)));
+        let glow = Arc::new(DiffuseLight::new(Vec3::new(8., 8., 8.)));
+        let lamp = Arc::new(Sphere::new(Vec3::new(0., 2., 0.), 0.4, glow));
+        let world = HitableList::new(vec![Box::new(Sphere::new(Vec3::new(0., -100., 0.), 100., grey.clone())),
+                                          Box::new(Sphere::new(Vec3::new(0.6, 0.5, 0.), 0.3, grey)),
+                                          Box::new(lamp.clone())]);
+        (world, HitableList::new(vec![Box::new(lamp) as Box<dyn Hitable>]))
+    }
+
+    #[test]
+    fn next_event_estimation_matches_brute_force_with_less_noise() {
+        let (world, lights) = lamp_over_floor(0.7);
+        let black: Background = "black".parse().unwrap();
+        for target in &[Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.3), Vec3::new(0.6, 0.5, 0.3)] {
+            let origin = Vec3::new(0., 1., 3.);
+            let r = Ray::new(&origin, &(target.clone() - origin.clone()));
+            let n = 40000;
+            let path = || Path::new(8, 8);
+            let (brute, brute_var) = mean_and_variance(n, |s| color(&r, &world, &[], &black, &mut path(), s));
+            let (nee, nee_var) = mean_and_variance(n, |s| {
+                next_event_color(&r, &world, &lights, &[], &black, &mut path(), s)
+            });
+            let error = 4. * ((brute_var + nee_var) / n as f32).sqrt();
+            assert!(brute > 0. && (brute - nee).abs() < error, "{} vs {}", brute, nee);
+            assert!(nee_var < brute_var, "{} vs {}", nee_var, brute_var);
+        }
+    }
+
+    #[test]
+    fn lights_add_emitted_radiance_on_a_black_background() {
+        let light = Arc::new(DiffuseLight::new(Vec3::new(4., 2., 1.)));
+        let world = HitableList::new(vec![Box::new(Sphere::new(Vec3::new(0., 0., -2.), 0.5, light))]);
+        let black: Background = "black".parse().unwrap();
+        let mut sampler = Sampler::new(0);
+        let origin = Vec3::new(0., 0., 0.);
+        let path = || Path::new(50, 50);
+        let (ahead, up) = (Ray::new(&origin, &Vec3::new(0., 0., -1.)), Ray::new(&origin, &Vec3::new(0., 1., 0.)));
+        let at_light = color(&ahead, &world, &[], &black, &mut path(), &mut sampler);
+        assert_eq!(at_light.e, (4., 2., 1.));
+        let away = color(&up, &world, &[], &black, &mut path(), &mut sampler);
+        assert_eq!(away.e, (0., 0., 0.));
+        let sky = color(&up, &world, &[], &Background::Sky, &mut path(), &mut sampler);
+        assert_eq!(sky.e, (0.5, 0.7, 1.));
+    }
+
+    #[test]
+    fn delta_lights_give_exact_direct_light_and_cast_shadows() {
+        let floor = || {
+            Box::new(Quad::new(Vec3::new(-4., 0., 4.),
+                               Vec3::new(8., 0., 0.),
+                               Vec3::new(0., 0., -12.),
+                               Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))
+                     .unwrap())
+        };
+        let open = HitableList::new(vec![floor()]);
+        let white = Arc::new(Lambertian::new(Vec3::new(1., 1., 1.)));
+        let shadowed = HitableList::new(vec![floor(), Box::new(Sphere::new(Vec3::new(0.5, 1., 0.), 0.2, white))]);
+        let no_lights = HitableList::new(vec![]);
+        let black: Background = "black".parse().unwrap();
+        let origin = Vec3::new(0., 1., 3.);
+        let r = Ray::new(&origin, &(Vec3::new(0., 0., 0.) - origin.clone()));
+        let shade = |world: &HitableList, light: Box<dyn Light>| {
+            let lights = [light];
+            let mut sampler = Sampler::new(5);
+            let brute = color(&r, world, &lights, &black, &mut Path::new(1, 1), &mut sampler).x();
+            let nee = next_event_color(&r, world, &no_lights, &lights, &black, &mut Path::new(1, 1), &mut sampler);
+            let mis = mis_color(&r, world, &no_lights, &lights, &black, &mut Path::new(1, 1), &mut sampler);
+            assert_eq!((brute, brute), (nee.x(), mis.x()));
+            brute
+        };
+
+        // Lambertian: albedo / π · I cos θ / d², with the bulb at distance √5 and cos θ = 2 / √5.
+        let bulb = || Box::new(PointLight::new(Vec3::new(1., 2., 0.), Vec3::new(10., 10., 10.)));
+        let expected = 0.5 / PI * 10. * (2. / 5f32.sqrt()) / 5.;
+        assert!((shade(&open, bulb()) - expected).abs() < 1e-5, "{} vs {}", shade(&open, bulb()), expected);
+        assert_eq!(shade(&shadowed, bulb()), 0.);
+
+        let down = Vec3::new(0., -1., 0.);
+        let spot = |angle: f32| {
+            Box::new(SpotLight::new(Vec3::new(0., 2., 2.), down.clone(), Vec3::new(10., 10., 10.), angle, angle))
+        };
+        assert_eq!(shade(&open, spot(30.)), 0.);
+        assert!((shade(&open, spot(60.)) - 0.5 / PI * 10. * (2. / 8f32.sqrt()) / 8.).abs() < 1e-5);
+
+        let sun = Box::new(DirectionalLight::new(down.clone(), Vec3::new(3., 3., 3.)));
+        assert!((shade(&open, sun) - 0.5 / PI * 3.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn shadow_rays_are_dimmed_by_media() {
+        let floor = Quad::new(Vec3::new(-4., 0., 4.),
+                              Vec3::new(8., 0., 0.),
+                              Vec3::new(0., 0., -12.),
+                              Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))
+                    .unwrap();
+        let floor: Arc<dyn Hitable> = Arc::new(floor);
+        let black: Background = "black".parse().unwrap();
+        let no_lights = HitableList::new(vec![]);
+        let origin = Vec3::new(0., 1., 3.);
+        let r = Ray::new(&origin, &(Vec3::new(0., 0., 0.) - origin.clone()));
+
+        // A cloud between the floor and the bulb, away from the camera ray, whose density rises
+        // from 0 to 2.24 along x. The shadow ray crosses it over 0.4 √5, for an optical depth of 1.
+        let bounds = Aabb::new(Vec3::new(0.3, 0.5, -0.5), Vec3::new(0.7, 1.5, 0.5));
+        let grid = Arc::new(VoxelGrid::new([2, 1, 1], bounds, vec![0., 1.]));
+        let cloud = GridMedium::new(grid, 1. / (0.2 * 5f32.sqrt()));
+        let world = HitableList::new(vec![Box::new(floor.clone()), Box::new(cloud)]);
+        let lights: [Box<dyn Light>; 1] = [Box::new(PointLight::new(Vec3::new(1., 2., 0.), Vec3::new(10., 10., 10.)))];
+        let expected = 0.5 / PI * 10. * (2. / 5f32.sqrt()) / 5. * (-1f32).exp();
+        let n = 4000;
+        let (brute, _) = mean_and_variance(n, |s| color(&r, &world, &lights, &black, &mut Path::new(1, 1), s));
+        let (nee, nee_var) = mean_and_variance(n, |s| {
+            next_event_color(&r, &world, &no_lights, &lights, &black, &mut Path::new(1, 1), s)
+        });
+        for &shaded in &[brute, nee] {
+            assert!((shaded - expected).abs() < 0.02 * expected, "{} vs {}", shaded, expected);
+        }
+        // ratio tracking is less noisy than letting each shadow ray through or not
+        let p = (-1f32).exp();
+        let all_or_nothing = expected * expected * (1. - p) / p;
+        assert!(nee_var < all_or_nothing, "{} vs {}", nee_var, all_or_nothing);
+
+        // Fog half a unit thick under a tiny lamp straight above lets e^-0.5 of it through.
+        let glow = Arc::new(DiffuseLight::new(Vec3::new(4., 4., 4.)));
+        let lamp: Arc<dyn Hitable> =
+            Arc::new(Quad::new(Vec3::new(-0.01, 2., -0.01), Vec3::new(0.02, 0., 0.), Vec3::new(0., 0., 0.02), glow)
+                         .unwrap());
+        let slab = BoxShape::new(Vec3::new(-0.5, 0.8, -0.5), Vec3::new(0.5, 1.3, 0.5), dummy());
+        let fog = ConstantMedium::new(slab, 1., Arc::new(Isotropic::new(Vec3::new(1., 1., 1.))));
+        let lights = HitableList::new(vec![Box::new(lamp.clone())]);
+        let open = HitableList::new(vec![Box::new(floor.clone()), Box::new(lamp.clone())]);
+        let foggy = HitableList::new(vec![Box::new(floor), Box::new(lamp), Box::new(fog)]);
+        let shade = |world: &HitableList| {
+            let mut sampler = Sampler::new(5);
+            next_event_color(&r, world, &lights, &[], &black, &mut Path::new(1, 1), &mut sampler).x()
+        };
+        let ratio = shade(&foggy) / shade(&open);
+        assert!((ratio - (-0.5f32).exp()).abs() < 1e-3, "{}", ratio);
+    }
+
+    #[test]
+    fn parses_backgrounds() {
+        assert_eq!("sky".parse(), Ok(Background::Sky));
+        assert_eq!("0.1, 0.2,0.3".parse(), Ok(Background::Solid(Vec3::new(0.1, 0.2, 0.3))));
+        assert!("1,2".parse::<Background>().is_err());
+    }
+
+    #[test]
+    fn parallel_render_lays_out_tiles_like_the_serial_one() {
+        let scene = by_name("normals", 0).unwrap();
+        // partial tiles at the edges, an image smaller than one tile, and more threads than tiles
+        for &(width, height) in [(37, 21), (5, 3), (TILE_SIZE, 2 * TILE_SIZE)].iter() {
+            let settings = RenderSettings {
+                width,
+                height,
+                samples: 2,
+                shading: Shading::Normal,
+                ..RenderSettings::default()
+            };
+            let cam = camera_preset("default", width as f32 / height as f32).unwrap();
+            let serial = render(scene.world.as_ref(), &scene.lights, &[], &cam, &settings).0.values();
+            for &threads in [1, 2, 3, 8].iter() {
+                let parallel = render_parallel(scene.world.as_ref(), &scene.lights, &[], &cam, &settings, threads).0;
+                assert_eq!(parallel.values().len(), width * height);
+                assert!(serial.iter().zip(parallel.values().iter()).all(|(a, b)| a.e == b.e),
+                        "{}x{} on {} threads",
+                        width,
+                        height,
+                        threads);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_render_is_identical_for_any_thread_count() {
+        let settings = RenderSettings {
+            width: 37,
+            height: 21,
+            samples: 4,
+            seed: 42,
+            ..RenderSettings::default()
+        };
+        let scene = by_name("random", settings.seed).unwrap();
+        let cam = camera_preset("random", settings.width as f32 / settings.height as f32).unwrap();
+        let (serial, stats) = render(scene.world.as_ref(), &scene.lights, &[], &cam, &settings);
+        let serial = serial.values();
+        assert_eq!(stats.paths, (37 * 21 * 4) as u64);
+        for threads in 1..4 {
+            let (parallel, parallel_stats) =
+                render_parallel(scene.world.as_ref(), &scene.lights, &[], &cam, &settings, threads);
+            assert!(serial.iter().zip(parallel.values().iter()).all(|(a, b)| a.e == b.e));
+            assert_eq!(parallel_stats, stats);
+        }
+        let again = by_name("random", settings.seed).unwrap();
+        let again = render(again.world.as_ref(), &again.lights, &[], &cam, &settings).0.values();
+        assert!(serial.iter().zip(again.iter()).all(|(a, b)| a.e == b.e));
+    }
+
+    #[test]
+    fn multiple_importance_sampling_tracks_the_better_strategy_on_glossy_plates() {
+        // Veach's test scene: a small bright and a large dim light reflected in plates that range
+        // from nearly mirror-like to rough.
+        let lamp = |x: f32, radius: f32, power: f32| {
+            let emit = Vec3::new(power, power, power);
+            Arc::new(Sphere::new(Vec3::new(x, 2., -6.), radius, Arc::new(DiffuseLight::new(emit))))
+        };
+        let (small, large) = (lamp(-0.6, 0.05, 800.), lamp(0.9, 0.8, 3.));
+        let lights = HitableList::new(vec![Box::new(small.clone()) as Box<dyn Hitable>, Box::new(large.clone())]);
+        let black: Background = "black".parse().unwrap();
+        let origin = Vec3::new(0., 1., 3.);
+        let n = 20000;
+        for &fuzz in &[0.03, 0.1, 0.3, 0.8] {
+            let plate = Quad::new(Vec3::new(-4., 0., 4.),
+                                  Vec3::new(8., 0., 0.),
+                                  Vec3::new(0., 0., -12.),
+                                  Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), fuzz)))
+                            .unwrap();
+            let world = HitableList::new(vec![Box::new(plate), Box::new(small.clone()), Box::new(large.clone())]);
+            for target in &[Vec3::new(-0.1, 0., 0.), Vec3::new(0.15, 0., 0.)] {
+                let r = Ray::new(&origin, &(target.clone() - origin.clone()));
+                let path = || Path::new(4, 4);
+                let (brute, brute_var) = mean_and_variance(n, |s| color(&r, &world, &[], &black, &mut path(), s));
+                let (nee, nee_var) = mean_and_variance(n, |s| {
+                    next_event_color(&r, &world, &lights, &[], &black, &mut path(), s)
+                });
+                let (mis, mis_var) = mean_and_variance(n, |s| {
+                    mis_color(&r, &world, &lights, &[], &black, &mut path(), s)
+                });
+                let error = |a: f32, b: f32| 4. * ((a + b) / n as f32).sqrt();
+                assert!((mis - nee).abs() <= error(mis_var, nee_var), "fuzz {}: {} vs {}", fuzz, mis, nee);
+                assert!((mis - brute).abs() <= error(mis_var, brute_var), "fuzz {}: {} vs {}", fuzz, mis, brute);
+                // MIS stays within an order of magnitude of whichever strategy suits the plate, and
+                // an order of magnitude below the other; the mean² term covers nearly noiseless cases
+                let (best, worst) = (brute_var.min(nee_var), brute_var.max(nee_var));
+                assert!(mis_var <= 10. * best + 1e-3 * mis * mis && mis_var <= 0.1 * worst,
+                        "fuzz {}, target {:?}: variances {} (brute force), {} (lights), {} (MIS)",
+                        fuzz,
+                        target,
+                        brute_var,
+                        nee_var,
+                        mis_var);
+            }
+        }
+    }
+
+    #[test]
+    fn roulette_starts_at_min_depth_and_scales_up_survivors() {
+        let mut sampler = Sampler::new(3);
+        let mut path = Path::new(10, 2);
+        let half = Vec3::new(0.5, 0.25, 0.5);
+        assert_eq!(path.bounce(&half, &mut sampler), Some(half.clone()));
+        assert_eq!(path.bounce(&half, &mut sampler), Some(half.clone()));
+        assert_eq!(path.throughput, Vec3::new(0.25, 0.0625, 0.25));
+        let mut dark = path.clone();
+        assert_eq!(dark.bounce(&Vec3::new(0., 0., 0.), &mut sampler), None);
+        assert_eq!(dark.depth, 2);
+        // The survival chance is the brightest channel of the throughput, 1/8 here.
+        let n = 80000;
+        let mut weights = Vec3::new(0., 0., 0.);
+        for _ in 0..n {
+            let mut next = path.clone();
+            if let Some(weight) = next.bounce(&half, &mut sampler) {
+                assert_eq!(weight, half.clone() * 8.);
+                assert_eq!(next.depth, 3);
+                weights = weights + weight;
+            }
+        }
+        let mean = weights / n as f32;
+        assert!((mean.x() - 0.5).abs() < 0.02 && (mean.y() - 0.25).abs() < 0.01, "{:?}", mean);
+        let finished = Path { depth: 3, ..Path::new(3, 0) };
+        assert!(!finished.can_bounce());
+    }
+
+    #[test]
+    fn russian_roulette_shortens_paths_without_bias() {
+        let (world, lights) = lamp_over_floor(0.8);
+        let black: Background = "black".parse().unwrap();
+        let origin = Vec3::new(0., 1., 3.);
+        let r = Ray::new(&origin, &(Vec3::new(0.3, 0., 0.) - origin.clone()));
+        let n = 40000;
+        let trace = |min_depth: i32| {
+            let mut stats = RenderStats::default();
+            let (mean, variance) = mean_and_variance(n, |s| {
+                let mut path = Path::new(16, min_depth);
+                let col = next_event_color(&r, &world, &lights, &[], &black, &mut path, s);
+                stats.add_path(&path);
+                col
+            });
+            (mean, variance, stats.average_path_length())
+        };
+        let (full, full_var, full_length) = trace(16);
+        let (roulette, roulette_var, roulette_length) = trace(1);
+        let error = 4. * ((full_var + roulette_var) / n as f32).sqrt();
+        assert!((full - roulette).abs() < error, "{} vs {}", full, roulette);
+        assert!(roulette_length < 0.9 * full_length, "{} vs {} bounces", roulette_length, full_length);
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+
+/// Shared-exponent encoding used by Radiance `.hdr` files.
+pub fn float_to_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let v = rgb[0].max(rgb[1]).max(rgb[2]);
+    if v.is_nan() || v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let (mantissa, exponent) = frexp(v);
+    let scale = mantissa * 256. / v;
+    [(rgb[0].max(0.) * scale) as u8,
+     (rgb[1].max(0.) * scale) as u8,
+     (rgb[2].max(0.) * scale) as u8,
+     (exponent + 128) as u8]
+}
+
+pub fn rgbe_to_float(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0., 0., 0.];
+    }
+    let f = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    [(rgbe[0] as f32 + 0.5) * f, (rgbe[1] as f32 + 0.5) * f, (rgbe[2] as f32 + 0.5) * f]
+}
+
+/// Splits `v` into a mantissa in `[0.5, 1)` and a power of two, like C's `frexp`.
+fn frexp(v: f32) -> (f32, i32) {
+    let mut e = v.log2().floor() as i32 + 1;
+    let mut m = v / 2f32.powi(e);
+    if m >= 1. {
+        m /= 2.;
+        e += 1;
+    } else if m < 0.5 {
+        m *= 2.;
+        e -= 1;
+    }
+    (m, e)
+}
+
+/// Writes a Radiance RGBE image. `rgb` holds linear values, top row first. Scanlines use the
+/// adaptive run-length encoding whenever the width allows it.
+pub fn write_hdr(out: &mut dyn Write, width: usize, height: usize, rgb: &[[f32; 3]]) -> io::Result<()> {
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+    let mut line = Vec::with_capacity(width * 4);
+    for row in rgb.chunks(width) {
+        line.clear();
+        let pixels: Vec<[u8; 4]> = row.iter().map(|c| float_to_rgbe(*c)).collect();
+        if !(8..0x8000).contains(&width) {
+            for p in pixels.iter() {
+                line.extend_from_slice(p);
+            }
+        } else {
+            line.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for c in 0..4 {
+                let component: Vec<u8> = pixels.iter().map(|p| p[c]).collect();
+                rle_component(&component, &mut line);
+            }
+        }
+        out.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Run-length encodes one component of a scanline: runs of at least `MIN_RUN` equal bytes become a
+/// `128 + count, value` pair and everything else is copied as `count, bytes...`.
+fn rle_component(data: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let mut cur = 0;
+    while cur < data.len() {
+        let mut beg_run = cur;
+        let mut run_count = 0;
+        let mut old_run_count = 0;
+        while run_count < MIN_RUN && beg_run < data.len() {
+            beg_run += run_count;
+            old_run_count = run_count;
+            run_count = 1;
+            while beg_run + run_count < data.len() && run_count < 127 && data[beg_run] == data[beg_run + run_count] {
+                run_count += 1;
+            }
+        }
+        // a short run right before the long one is cheaper as a run than as literals
+        if old_run_count > 1 && old_run_count == beg_run - cur {
+            out.push((128 + old_run_count) as u8);
+            out.push(data[cur]);
+            cur = beg_run;
+        }
+        while cur < beg_run {
+            let count = (beg_run - cur).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[cur..cur + count]);
+            cur += count;
+        }
+        if run_count >= MIN_RUN {
+            out.push((128 + run_count) as u8);
+            out.push(data[beg_run]);
+            cur += run_count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{float_to_rgbe, rgbe_to_float, write_hdr};
+
+    fn read_rle_scanline(data: &[u8], width: usize) -> (Vec<[u8; 4]>, usize) {
+        assert_eq!(&data[..4], &[2, 2, (width >> 8) as u8, width as u8]);
+        let mut pixels = vec![[0u8; 4]; width];
+        let mut pos = 4;
+        for c in 0..4 {
+            let mut component = Vec::with_capacity(width);
+            while component.len() < width {
+                let count = data[pos] as usize;
+                pos += 1;
+                if count > 128 {
+                    component.extend(::std::iter::repeat_n(data[pos], count - 128));
+                    pos += 1;
+                } else {
+                    component.extend_from_slice(&data[pos..pos + count]);
+                    pos += count;
+                }
+            }
+            for (p, v) in pixels.iter_mut().zip(component) {
+                p[c] = v;
+            }
+        }
+        (pixels, pos)
+    }
+
+    #[test]
+    fn rgbe_round_trip_keeps_high_values() {
+        for &v in [0.01, 0.5, 1., 3.75, 1000.].iter() {
+            let back = rgbe_to_float(float_to_rgbe([v, v * 0.5, 0.]));
+            assert!((back[0] - v).abs() / v < 0.01, "{} -> {}", v, back[0]);
+            assert!((back[1] - v * 0.5).abs() / v < 0.01);
+        }
+        assert_eq!(float_to_rgbe([0., 0., 0.]), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn writes_run_length_encoded_scanlines() {
+        let width = 20;
+        let rgb: Vec<[f32; 3]> = (0..width * 2).map(|i| if i % 7 < 4 { [2., 1., 0.] } else { [i as f32, 0.25, 8.] }).collect();
+        let mut out = vec![];
+        write_hdr(&mut out, width, 2, &rgb).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 20\n";
+        assert!(out.starts_with(header));
+        let mut pos = header.len();
+        for row in rgb.chunks(width) {
+            let (pixels, used) = read_rle_scanline(&out[pos..], width);
+            pos += used;
+            let expected: Vec<[u8; 4]> = row.iter().map(|c| float_to_rgbe(*c)).collect();
+            assert_eq!(pixels, expected);
+        }
+        assert_eq!(pos, out.len());
+    }
+}
@@ -0,0 +1,227 @@
+use std::f32::consts::{FRAC_PI_4, PI};
+use utils::vec3::{cross, unit_vector, Vec3};
+use utils::random::Sampler;
+
+/// Orthonormal basis around a direction `w`, used to turn directions sampled around the z axis
+/// into directions around a surface normal or any other axis.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+#[allow(dead_code)]
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = unit_vector(n.clone());
+        let a = if w.x().abs() > 0.9 { Vec3::new(0., 1., 0.) } else { Vec3::new(1., 0., 0.) };
+        let v = unit_vector(cross(&w, &a));
+        let u = cross(&v, &w);
+        Self { u, v, w }
+    }
+
+    /// The world space direction whose coordinates in this basis are `a`.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u.clone() * a.x() + self.v.clone() * a.y() + self.w.clone() * a.z()
+    }
+}
+
+/// Uniform point inside the unit ball.
+pub fn random_in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
+    loop {
+        let p = Vec3::new(sampler.drand48(), sampler.drand48(), sampler.drand48()) * 2.0 - Vec3::new(1., 1., 1.);
+        if p.squared_len() < 1.0 {
+            return p;
+        }
+    }
+}
+
+/// Uniform direction over the whole sphere.
+pub fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    let z = 1. - 2. * sampler.drand48();
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * sampler.drand48();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1. / (4. * PI)
+}
+
+/// Uniform direction over the hemisphere around +z.
+pub fn random_on_hemisphere(sampler: &mut Sampler) -> Vec3 {
+    let z = sampler.drand48();
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * sampler.drand48();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_hemisphere_pdf() -> f32 {
+    1. / (2. * PI)
+}
+
+/// Direction over the hemisphere around +z with a density proportional to its z, found by
+/// lifting a uniform point of the unit disk onto the hemisphere (Malley's method).
+pub fn random_cosine_direction(sampler: &mut Sampler) -> Vec3 {
+    let (x, y) = concentric_disk(sampler.drand48(), sampler.drand48());
+    Vec3::new(x, y, (1. - x * x - y * y).max(0.).sqrt())
+}
+
+/// Density of `random_cosine_direction` for a direction whose z is `cos_theta`.
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.) / PI
+}
+
+/// Shirley and Chiu's concentric mapping of the square `[0, 1)²` onto the unit disk. It keeps
+/// areas uniform like the polar mapping, but distorts the strata much less and needs no rejection.
+pub fn concentric_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let (a, b) = (2. * u1 - 1., 2. * u2 - 1.);
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, 2. * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn uniform_disk_pdf() -> f32 {
+    1. / PI
+}
+
+/// A unit direction whose angle from `axis` has cosine `cos_theta`, at a uniformly random azimuth.
+pub fn random_around(axis: &Vec3, cos_theta: f32, sampler: &mut Sampler) -> Vec3 {
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * sampler.drand48();
+    Onb::from_w(axis).local(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::{concentric_disk, cosine_hemisphere_pdf, random_around, random_cosine_direction, random_in_unit_sphere,
+                random_on_hemisphere, random_unit_vector, uniform_disk_pdf, uniform_hemisphere_pdf, uniform_sphere_pdf,
+                Onb};
+    use utils::random::Sampler;
+    use utils::vec3::{cross, dot, Vec3};
+
+    const N: usize = 100000;
+
+    /// Mean of `f` over `N` draws.
+    fn mean<F: FnMut(&mut Sampler) -> f32>(mut f: F) -> f32 {
+        let mut sampler = Sampler::new(11);
+        (0..N).map(|_| f(&mut sampler) as f64).sum::<f64>() as f32 / N as f32
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn onb_is_orthonormal_and_right_handed() {
+        for n in &[Vec3::new(0., 0., 1.), Vec3::new(-1., 0., 0.), Vec3::new(0.3, -2., 0.5), Vec3::new(0.95, 0.1, 0.)] {
+            let onb = Onb::from_w(n);
+            for (a, b) in &[(&onb.u, &onb.v), (&onb.v, &onb.w), (&onb.w, &onb.u)] {
+                assert!(dot(a, b).abs() < 1e-6);
+            }
+            for a in &[&onb.u, &onb.v, &onb.w] {
+                assert!((a.len() - 1.).abs() < 1e-6);
+            }
+            assert!(dot(&onb.w, n) > 0.);
+            let z = onb.local(&Vec3::new(0., 0., 1.));
+            assert!((z - onb.w.clone()).len() < 1e-6);
+            let w = cross(&onb.local(&Vec3::new(1., 0., 0.)), &onb.local(&Vec3::new(0., 1., 0.)));
+            assert!((w - onb.w.clone()).len() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn unit_sphere_points_are_uniform_inside_the_ball() {
+        // Uniform in the ball, |p|² = r² with r³ uniform has a mean of 3/5.
+        assert!(close(mean(|s| random_in_unit_sphere(s).squared_len()), 0.6));
+        assert!(close(mean(|s| random_in_unit_sphere(s).z()), 0.));
+        assert!(mean(|s| (random_in_unit_sphere(s).squared_len() < 1.) as i32 as f32) == 1.);
+        // An eighth of the ball's volume lies within half its radius.
+        assert!(close(mean(|s| (random_in_unit_sphere(s).len() < 0.5) as i32 as f32), 0.125));
+    }
+
+    #[test]
+    fn sphere_and_hemisphere_directions_are_uniform() {
+        assert!(close(mean(|s| random_unit_vector(s).len()), 1.));
+        assert!(close(mean(|s| random_unit_vector(s).x()), 0.));
+        assert!(close(mean(|s| random_unit_vector(s).z().powi(2)), 1. / 3.));
+        // Archimedes: the band y > 0.5 holds a quarter of the sphere.
+        assert!(close(mean(|s| (random_unit_vector(s).y() > 0.5) as i32 as f32), 0.25));
+
+        assert!(mean(|s| (random_on_hemisphere(s).z() >= 0.) as i32 as f32) == 1.);
+        assert!(close(mean(|s| random_on_hemisphere(s).z()), 0.5));
+        assert!(close(mean(|s| random_on_hemisphere(s).x()), 0.));
+        // Integrating the constant densities over their domains gives one.
+        assert!(close(uniform_sphere_pdf() * 4. * PI, 1.));
+        assert!(close(uniform_hemisphere_pdf() * 2. * PI, 1.));
+    }
+
+    #[test]
+    fn cosine_directions_follow_their_pdf() {
+        assert!(mean(|s| (random_cosine_direction(s).z() >= 0.) as i32 as f32) == 1.);
+        assert!(close(mean(|s| random_cosine_direction(s).len()), 1.));
+        // E[cos] = 2/3 and E[cos²] = 1/2 under a cosine-weighted density.
+        assert!(close(mean(|s| random_cosine_direction(s).z()), 2. / 3.));
+        assert!(close(mean(|s| random_cosine_direction(s).z().powi(2)), 0.5));
+        // Half the directions have cos² θ below one half.
+        assert!(close(mean(|s| (random_cosine_direction(s).z().powi(2) < 0.5) as i32 as f32), 0.5));
+        // Integrated with uniform hemisphere samples, the pdf comes to one.
+        let integral = mean(|s| cosine_hemisphere_pdf(random_on_hemisphere(s).z()) / uniform_hemisphere_pdf());
+        assert!(close(integral, 1.));
+        assert_eq!(cosine_hemisphere_pdf(-0.5), 0.);
+    }
+
+    #[test]
+    fn concentric_disk_is_uniform_and_continuous() {
+        let disk = |s: &mut Sampler| concentric_disk(s.drand48(), s.drand48());
+        assert!(mean(|s| {
+                    let (x, y) = disk(s);
+                    (x.hypot(y) <= 1.) as i32 as f32
+                }) == 1.);
+        assert!(close(mean(|s| {
+                          let (x, y) = disk(s);
+                          x * x + y * y
+                      }),
+                      0.5));
+        assert!(close(mean(|s| (disk(s).0 > 0.) as i32 as f32), 0.5));
+        assert!(close(mean(|s| {
+                          let (x, y) = disk(s);
+                          (x.hypot(y) < 0.5) as i32 as f32
+                      }),
+                      0.25));
+        assert!(close(uniform_disk_pdf() * PI, 1.));
+        // The square's center and corners land on the disk's center and rim, and neighbouring
+        // points of the square stay close.
+        assert_eq!(concentric_disk(0.5, 0.5), (0., 0.));
+        let (x, y) = concentric_disk(1., 1.);
+        assert!((x.hypot(y) - 1.).abs() < 1e-6);
+        let (a, b) = (concentric_disk(0.8, 0.6), concentric_disk(0.801, 0.601));
+        assert!((a.0 - b.0).hypot(a.1 - b.1) < 0.01);
+    }
+
+    #[test]
+    fn random_around_keeps_the_angle_and_spreads_the_azimuth() {
+        let axis = Vec3::new(1., 2., -2.);
+        let unit = Vec3::new(1., 2., -2.) / 3.;
+        for &cos_theta in &[-0.4, 0.3, 0.99] {
+            let mut sampler = Sampler::new(2);
+            for _ in 0..100 {
+                let d = random_around(&axis, cos_theta, &mut sampler);
+                assert!((dot(&d, &unit) - cos_theta).abs() < 1e-5);
+                assert!((d.len() - 1.).abs() < 1e-5);
+            }
+            // The azimuths average out, leaving only the component along the axis.
+            let along = mean(|s| dot(&random_around(&axis, cos_theta, s), &Vec3::new(0., 0., 1.)));
+            assert!(close(along, cos_theta * unit.z()));
+        }
+    }
+}
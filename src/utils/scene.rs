@@ -0,0 +1,259 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use utils::vec3::Vec3;
+use utils::hitable::{Hitable, HitableList};
+use utils::sphere::Sphere;
+use utils::rect::{BoxShape, XyRect, XzRect, YzRect};
+use utils::hitable::FlipFace;
+use utils::instance::{RotateY, Translate};
+use utils::camera::Camera;
+use utils::material::{DummyMat, Material, Lambertian, Metal, Dielectric, DiffuseLight};
+use utils::medium::{ConstantMedium, Isotropic};
+use utils::bvh::BvhNode;
+use utils::random::Sampler;
+use utils::texture::{MarbleTexture, NoiseTexture};
+use utils::render::{Background, Shading};
+
+/// Names accepted by `by_name`, in chapter order.
+pub const SCENES: &[&str] = &["normals",
+                             "diffuse",
+                             "metal",
+                             "dielectric",
+                             "fov",
+                             "lookat",
+                             "defocus",
+                             "random",
+                             "light",
+                             "noise",
+                             "cornell",
+                             "cornell-rotated",
+                             "smoke"];
+
+/// Names accepted by `camera_preset`.
+pub const CAMERAS: &[&str] = &["default", "fov", "lookat", "defocus", "random", "light", "cornell"];
+
+pub struct Scene {
+    pub world: Box<dyn Hitable>,
+    /// Emitters sampled directly by `Integrator::NextEvent`.
+    pub lights: HitableList,
+    pub camera: &'static str,
+    pub shading: Shading,
+    pub background: Background,
+}
+
+/// `seed` drives the placement of randomly generated scenes such as `random`.
+pub fn by_name(name: &str, seed: u64) -> Option<Scene> {
+    let sky = Background::Sky;
+    let (world, camera, shading, background): (Box<dyn Hitable>, &'static str, Shading, Background) = match name {
+        "normals" => (Box::new(two_spheres()), "default", Shading::Normal, sky),
+        "diffuse" => (Box::new(two_spheres()), "default", Shading::Diffuse, sky),
+        "metal" => (Box::new(metal_scene()), "default", Shading::Material, sky),
+        "dielectric" => (Box::new(dielectric_scene()), "default", Shading::Material, sky),
+        "fov" => (Box::new(fov_scene()), "fov", Shading::Material, sky),
+        "lookat" => (Box::new(dielectric_scene()), "lookat", Shading::Material, sky),
+        "defocus" => (Box::new(dielectric_scene()), "defocus", Shading::Material, sky),
+        "random" => {
+            let world = random_scene(&mut Sampler::new(seed));
+            (Box::new(BvhNode::new(world, 0., 1.)), "random", Shading::Material, sky)
+        }
+        "light" => {
+            let black = Background::Solid(Vec3::new(0., 0., 0.));
+            (Box::new(light_scene()), "light", Shading::Material, black)
+        }
+        "noise" => (Box::new(noise_scene(seed)), "light", Shading::Material, sky),
+        "cornell" => {
+            let black = Background::Solid(Vec3::new(0., 0., 0.));
+            (Box::new(BvhNode::new(cornell_box(), 0., 1.)), "cornell", Shading::Material, black)
+        }
+        "cornell-rotated" => {
+            let black = Background::Solid(Vec3::new(0., 0., 0.));
+            (Box::new(BvhNode::new(cornell_rotated(), 0., 1.)), "cornell", Shading::Material, black)
+        }
+        "smoke" => {
+            let black = Background::Solid(Vec3::new(0., 0., 0.));
+            (Box::new(BvhNode::new(cornell_smoke(), 0., 1.)), "cornell", Shading::Material, black)
+        }
+        _ => return None,
+    };
+    let lights = match name {
+        "light" => HitableList::new(vec![Box::new(light_scene_lamp())]),
+        "cornell" | "cornell-rotated" | "smoke" => HitableList::new(vec![Box::new(cornell_lamp())]),
+        _ => HitableList::new(vec![]),
+    };
+    Some(Scene {
+             world,
+             lights,
+             camera,
+             shading,
+             background,
+         })
+}
+
+pub fn camera_preset(name: &str, aspect: f32) -> Option<Camera> {
+    let vup = Vec3::new(0., 1., 0.);
+    let cam = match name {
+        "default" => Camera::new(),
+        "fov" => Camera::with_fov(90., aspect),
+        "lookat" => Camera::with_lookat(&Vec3::new(2., 1., -1.), &Vec3::new(0., 0., -1.), &vup, 90., aspect),
+        "defocus" => {
+            let lookfrom = Vec3::new(3., 3., 2.);
+            let lookat = Vec3::new(0., 0., -1.);
+            let dist_to_focus = (lookfrom.clone() - lookat.clone()).len();
+            Camera::with_focus(&lookfrom, &lookat, &vup, 20., aspect, 2., dist_to_focus)
+        }
+        "random" => {
+            let lookfrom = Vec3::new(13., 2., 3.);
+            let lookat = Vec3::new(0., 0., -1.);
+            Camera::with_focus(&lookfrom, &lookat, &vup, 30., aspect, 0.1, 10.)
+        }
+        "cornell" => Camera::with_lookat(&Vec3::new(278., 278., -800.), &Vec3::new(278., 278., 0.), &vup, 40., aspect),
+        "light" => Camera::with_lookat(&Vec3::new(26., 3., 6.), &Vec3::new(0., 2., 0.), &vup, 20., aspect),
+        _ => return None,
+    };
+    Some(cam)
+}
+
+pub fn two_spheres() -> HitableList {
+    let mat = Arc::new(DummyMat::new());
+    let s1 = Box::new(Sphere::new(Vec3::new(0., 0., -1.), 0.5, mat.clone()));
+    let s2 = Box::new(Sphere::new(Vec3::new(0., -100.5, -1.), 100., mat));
+    HitableList::new(vec![s1, s2])
+}
+
+pub fn metal_scene() -> HitableList {
+    let s1 = get_sphere!(Lambertian, Vec3::new(0.8, 0.3, 0.3), Vec3::new(0., 0., -1.), 0.5);
+    let s2 = get_sphere!(Lambertian, Vec3::new(0.8, 0.8, 0.), Vec3::new(0., -100.5, -1.), 100.);
+    let s3 = get_sphere!(Metal, Vec3::new(0.8, 0.6, 0.2), 1.0, Vec3::new(1., 0., -1.), 0.5);
+    let s4 = get_sphere!(Metal, Vec3::new(0.8, 0.8, 0.8), 0.3, Vec3::new(-1., 0., -1.), 0.5);
+    HitableList::new(vec![s1, s2, s3, s4])
+}
+
+pub fn dielectric_scene() -> HitableList {
+    let s1 = get_sphere!(Lambertian, Vec3::new(0.1, 0.2, 0.5), Vec3::new(0., 0., -1.), 0.5);
+    let s2 = get_sphere!(Lambertian, Vec3::new(0.8, 0.8, 0.), Vec3::new(0., -100.5, -1.), 100.);
+    let s3 = get_sphere!(Metal, Vec3::new(0.8, 0.6, 0.2), 0.1, Vec3::new(1., 0., -1.), 0.5);
+    let s4 = get_sphere!(Dielectric, 1.5, Vec3::new(-1., 0., -1.), 0.5);
+    let s5 = get_sphere!(Dielectric, 1.5, Vec3::new(-1., 0., -1.), -0.45);
+    HitableList::new(vec![s1, s2, s3, s4, s5])
+}
+
+pub fn fov_scene() -> HitableList {
+    let r: f32 = (PI / 4.).cos();
+    let s1 = get_sphere!(Lambertian, Vec3::new(0., 0., 1.), Vec3::new(-r, 0., -1.), r);
+    let s2 = get_sphere!(Lambertian, Vec3::new(1., 0., 0.), Vec3::new(r, 0., -1.), r);
+    HitableList::new(vec![s1, s2])
+}
+
+pub fn random_scene(sampler: &mut Sampler) -> HitableList {
+    let mut list = HitableList::new(vec![]);
+    list.list.push(get_sphere!(Lambertian, Vec3::new(0.5, 0.5, 0.5), Vec3::new(0., -1000., 0.), 1000.));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = sampler.drand48();
+            let center = Vec3::new(a as f32 + 0.9 * sampler.drand48(), 0.2, b as f32 * 0.9 * sampler.drand48());
+            if (center.clone() - Vec3::new(4., 0.2, 0.)).len() > 0.9 {
+                if choose_mat < 0.8 {
+                    list.list.push(get_sphere!(Lambertian,
+                                               Vec3::new(sampler.drand48() * sampler.drand48(),
+                                                         sampler.drand48() * sampler.drand48(),
+                                                         sampler.drand48() * sampler.drand48()),
+                                               center,
+                                               0.2));
+                } else if choose_mat < 0.95 {
+                    list.list.push(get_sphere!(Metal,
+                                               Vec3::new(0.5 * (1. + sampler.drand48()),
+                                                         0.5 * (1. + sampler.drand48()),
+                                                         0.5 * (1. + sampler.drand48())),
+                                               0.5 * sampler.drand48(),
+                                               center,
+                                               0.2));
+                } else {
+                    list.list.push(get_sphere!(Dielectric, 1.5, center, 0.2));
+                }
+            }
+        }
+    }
+
+    list.list.push(get_sphere!(Dielectric, 1.5, Vec3::new(0., 1., 0.), 1.));
+    list.list.push(get_sphere!(Lambertian, Vec3::new(0.4, 0.2, 0.1), Vec3::new(-4., 1., 0.), 1.));
+    list.list.push(get_sphere!(Metal, Vec3::new(0.7, 0.6, 0.5), 0., Vec3::new(4., 1., 0.), 1.));
+
+    list
+}
+
+fn light_scene_lamp() -> Sphere {
+    Sphere::new(Vec3::new(0., 7., 0.), 2., Arc::new(DiffuseLight::new(Vec3::new(4., 4., 4.))))
+}
+
+/// A ground and a sphere lit only by a glowing sphere above them, meant for a black background.
+pub fn light_scene() -> HitableList {
+    let s1 = get_sphere!(Lambertian, Vec3::new(0.5, 0.5, 0.5), Vec3::new(0., -1000., 0.), 1000.);
+    let s2 = get_sphere!(Lambertian, Vec3::new(0.8, 0.3, 0.3), Vec3::new(0., 2., 0.), 2.);
+    HitableList::new(vec![s1, s2, Box::new(light_scene_lamp())])
+}
+
+/// A marble sphere on turbulent ground; `seed` picks the noise lattice.
+pub fn noise_scene(seed: u64) -> HitableList {
+    let ground = Arc::new(Lambertian::textured(Arc::new(NoiseTexture::new(seed, 4.))));
+    let marble = Arc::new(Lambertian::textured(Arc::new(MarbleTexture::new(seed.wrapping_add(1), 4.))));
+    let s1 = Box::new(Sphere::new(Vec3::new(0., -1000., 0.), 1000., ground));
+    let s2 = Box::new(Sphere::new(Vec3::new(0., 2., 0.), 2., marble));
+    HitableList::new(vec![s1, s2])
+}
+
+/// The ceiling panel lighting the Cornell box.
+fn cornell_lamp() -> FlipFace<XzRect> {
+    let light = Arc::new(DiffuseLight::new(Vec3::new(15., 15., 15.)));
+    FlipFace::new(XzRect::new(213., 343., 227., 332., 554., light))
+}
+
+/// The empty Cornell box: red and green side walls and a white room lit by a ceiling panel.
+fn cornell_walls(white: Arc<dyn Material>) -> Vec<Box<dyn Hitable>> {
+    let red = Arc::new(Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
+    let green = Arc::new(Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
+    vec![Box::new(FlipFace::new(YzRect::new(0., 555., 0., 555., 555., green))),
+         Box::new(YzRect::new(0., 555., 0., 555., 0., red)),
+         Box::new(cornell_lamp()),
+         Box::new(FlipFace::new(XzRect::new(0., 555., 0., 555., 555., white.clone()))),
+         Box::new(XzRect::new(0., 555., 0., 555., 0., white.clone())),
+         Box::new(FlipFace::new(XyRect::new(0., 555., 0., 555., 555., white)))]
+}
+
+/// The two Cornell boxes, tall one first, built from `mat` and turned into place.
+fn cornell_blocks(mat: Arc<dyn Material>) -> (impl Hitable, impl Hitable) {
+    let origin = Vec3::new(0., 0., 0.);
+    let tall = BoxShape::new(origin.clone(), Vec3::new(165., 330., 165.), mat.clone());
+    let short = BoxShape::new(origin, Vec3::new(165., 165., 165.), mat);
+    (Translate::new(RotateY::new(tall, 15.), Vec3::new(265., 0., 295.)),
+     Translate::new(RotateY::new(short, -18.), Vec3::new(130., 0., 65.)))
+}
+
+/// The Cornell box: red and green side walls, a white room lit by a ceiling panel, and two boxes.
+pub fn cornell_box() -> HitableList {
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+    let mut list = cornell_walls(white.clone());
+    list.push(Box::new(BoxShape::new(Vec3::new(130., 0., 65.), Vec3::new(295., 165., 230.), white.clone())));
+    list.push(Box::new(BoxShape::new(Vec3::new(265., 0., 295.), Vec3::new(430., 330., 460.), white)));
+    HitableList::new(list)
+}
+
+/// The Cornell box with both boxes turned about their corners, as in the book.
+pub fn cornell_rotated() -> HitableList {
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+    let mut list = cornell_walls(white.clone());
+    let (tall, short) = cornell_blocks(white);
+    list.push(Box::new(tall));
+    list.push(Box::new(short));
+    HitableList::new(list)
+}
+
+/// The Cornell box with its blocks replaced by black and white smoke.
+pub fn cornell_smoke() -> HitableList {
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+    let mut list = cornell_walls(white.clone());
+    let (tall, short) = cornell_blocks(white);
+    list.push(Box::new(ConstantMedium::new(tall, 0.01, Arc::new(Isotropic::new(Vec3::new(0., 0., 0.))))));
+    list.push(Box::new(ConstantMedium::new(short, 0.01, Arc::new(Isotropic::new(Vec3::new(1., 1., 1.))))));
+    HitableList::new(list)
+}
@@ -0,0 +1,1098 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde_json;
+use serde_path_to_error;
+use toml;
+use utils::vec3::{cross, Vec3};
+use utils::hitable::{Hitable, HitableList};
+use utils::sphere::Sphere;
+use utils::moving_sphere::MovingSphere;
+use utils::rect::BoxShape;
+use utils::quad::Quad;
+use utils::plane::Plane;
+use utils::instance::Instance;
+use utils::medium::{ConstantMedium, GridMedium, HenyeyGreenstein, Isotropic};
+use utils::transform::Transform;
+use utils::{gltf, obj, ply, stl, voxel};
+use utils::camera::Camera;
+use utils::light::{DirectionalLight, Light, PointLight, SpotLight};
+use utils::material::{Material, Lambertian, Metal, Dielectric, DiffuseLight};
+use utils::render::{Background, Integrator, RenderSettings};
+use utils::texture::{CheckerTexture, ConstantTexture, GraniteTexture, ImageTexture, MarbleTexture, NoiseTexture, Texture,
+                     WoodTexture};
+
+/// A scene description as read from a `.toml` or `.json` file.
+///
+/// ```toml
+/// [render]
+/// width = 400
+/// height = 200
+/// background = [0, 0, 0]
+///
+/// [camera]
+/// lookfrom = [3, 3, 2]
+/// lookat = [0, 0, -1]
+/// vfov = 20
+/// aperture = 2
+///
+/// [textures.tiles]
+/// type = "Checker"
+/// odd = [0.2, 0.3, 0.1]
+/// even = [0.9, 0.9, 0.9]
+///
+/// [materials.glass]
+/// type = "Dielectric"
+/// ref_idx = 1.5
+///
+/// [materials.floor]
+/// type = "Lambertian"
+/// albedo = "tiles"
+///
+/// [[objects]]
+/// type = "Sphere"
+/// center = [-1, 0, -1]
+/// radius = 0.5
+/// material = "glass"
+///
+/// [[lights]]
+/// type = "Spot"
+/// position = [0, 4, -1]
+/// direction = [0, -1, 0]
+/// angle = 30
+/// power = [60, 60, 60]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub render: RenderDesc,
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    pub lights: Vec<LightDesc>,
+    /// Directory that relative image paths are resolved against; set by `load`.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderDesc {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: i32,
+    /// Bounces before Russian roulette may end a path; `max_depth`, which leaves it off, when
+    /// left out.
+    pub min_depth: Option<i32>,
+    pub seed: u64,
+    /// `"brute"`, `"nee"`, which samples the scene's lights directly, or `"mis"`.
+    pub integrator: Integrator,
+    /// Solid color seen by rays that miss everything; the sky gradient when left out.
+    pub background: Option<[f32; 3]>,
+}
+
+impl Default for RenderDesc {
+    fn default() -> Self {
+        let settings = RenderSettings::default();
+        Self {
+            width: settings.width,
+            height: settings.height,
+            samples: settings.samples,
+            max_depth: settings.max_depth,
+            min_depth: None,
+            seed: settings.seed,
+            integrator: settings.integrator,
+            background: None,
+        }
+    }
+}
+
+/// Parameters of `Camera::with_focus`. `aspect` defaults to the render width over height and
+/// `focus_dist` to the distance between `lookfrom` and `lookat`. `shutter = [open, close]` blurs
+/// moving objects over that interval.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub lookfrom: [f32; 3],
+    pub lookat: [f32; 3],
+    #[serde(default = "default_vup")]
+    pub vup: [f32; 3],
+    pub vfov: f32,
+    pub aspect: Option<f32>,
+    #[serde(default)]
+    pub aperture: f32,
+    pub focus_dist: Option<f32>,
+    pub shutter: Option<[f32; 2]>,
+}
+
+fn default_vup() -> [f32; 3] {
+    [0., 1., 0.]
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum TextureDesc {
+    Constant { color: [f32; 3] },
+    /// A solid checkerboard of `size` sized cubes.
+    Checker {
+        odd: [f32; 3],
+        even: [f32; 3],
+        #[serde(default = "default_checker_size")]
+        size: f32,
+    },
+    /// A PNG or PPM image, relative to the scene file.
+    Image { path: String },
+    /// Procedural textures; the same `seed` always gives the same pattern.
+    Noise {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+    },
+    Marble {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+    },
+    Wood {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+    },
+    Granite {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+    },
+}
+
+fn default_checker_size() -> f32 {
+    0.5
+}
+
+fn default_noise_scale() -> f32 {
+    4.
+}
+
+/// Either a constant color or the name of an entry under `[textures]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ColorDesc {
+    Color([f32; 3]),
+    Texture(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum MaterialDesc {
+    Lambertian { albedo: ColorDesc },
+    Metal {
+        albedo: ColorDesc,
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Dielectric { ref_idx: f32 },
+    DiffuseLight { emit: ColorDesc },
+    /// Phase functions for `ConstantMedium` objects; `g` is the mean scattering cosine.
+    Isotropic { albedo: ColorDesc },
+    HenyeyGreenstein { albedo: ColorDesc, g: f32 },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum ObjectDesc {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// Sphere moving in a straight line from `center0` at `time0` to `center1` at `time1`.
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+        radius: f32,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// Parallelogram with corner `q` and edges `u` and `v`, facing `u × v`.
+    Quad {
+        q: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// Axis-aligned box between two opposite corners.
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// Infinite plane through `point`.
+    Plane {
+        point: [f32; 3],
+        normal: [f32; 3],
+        #[serde(default)]
+        material: Option<String>,
+    },
+    /// Triangle mesh from an `.obj`, `.ply`, `.stl`, `.gltf` or `.glb` file, relative to the scene
+    /// file. Faces without a material of their own use `material`, or a light grey diffuse if that
+    /// is not given; `vertex_colors` shades a PLY with its vertex colors instead.
+    Mesh {
+        path: String,
+        #[serde(default)]
+        material: Option<String>,
+        #[serde(default)]
+        vertex_colors: bool,
+    },
+    /// Fog or smoke of uniform `density` filling the closed `boundary` object, whose own material
+    /// is not used and may be left out. `material` must be an `Isotropic` or `HenyeyGreenstein`
+    /// phase function.
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f32,
+        material: String,
+    },
+    /// Heterogeneous medium from a voxel grid file, relative to the scene file, whose densities are
+    /// multiplied by `density`. `albedo` and `emission` apply to the whole grid and tint the grid's
+    /// own per-voxel values where it stores them; `g` is the mean scattering cosine.
+    GridMedium {
+        path: String,
+        #[serde(default = "default_density")]
+        density: f32,
+        albedo: Option<[f32; 3]>,
+        emission: Option<[f32; 3]>,
+        #[serde(default)]
+        g: f32,
+    },
+    /// Another object scaled, then rotated by `rotate` degrees about x, y and z in that order, then
+    /// moved by `translate`.
+    Instance {
+        object: Box<ObjectDesc>,
+        #[serde(default)]
+        translate: [f32; 3],
+        #[serde(default)]
+        rotate: [f32; 3],
+        #[serde(default = "default_scale")]
+        scale: [f32; 3],
+    },
+}
+
+/// Point, spot and directional lights, which have no shape and are only seen through the light
+/// they cast. Lengths are in meters. Point and spot lights take either an `intensity` in W/sr or
+/// the total `power` they emit in W; a directional light's `irradiance` is in W/m².
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum LightDesc {
+    Point {
+        position: [f32; 3],
+        intensity: Option<[f32; 3]>,
+        power: Option<[f32; 3]>,
+    },
+    /// `angle` is the half-angle of the cone in degrees; the light fades out from `falloff_start`
+    /// degrees off its axis to the edge, which is hard when it is left out.
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        angle: f32,
+        falloff_start: Option<f32>,
+        intensity: Option<[f32; 3]>,
+        power: Option<[f32; 3]>,
+    },
+    /// Light from infinitely far away travelling along `direction`, such as the sun.
+    Directional { direction: [f32; 3], irradiance: [f32; 3] },
+}
+
+fn default_density() -> f32 {
+    1.
+}
+
+fn default_time1() -> f32 {
+    1.
+}
+
+fn default_scale() -> [f32; 3] {
+    [1., 1., 1.]
+}
+
+impl ObjectDesc {
+    pub fn material(&self) -> Option<&str> {
+        match *self {
+            ObjectDesc::Sphere { ref material, .. } |
+            ObjectDesc::MovingSphere { ref material, .. } |
+            ObjectDesc::Quad { ref material, .. } |
+            ObjectDesc::Box { ref material, .. } |
+            ObjectDesc::Plane { ref material, .. } |
+            ObjectDesc::Mesh { ref material, .. } => material.as_deref(),
+            ObjectDesc::ConstantMedium { ref material, .. } => Some(material),
+            // the wrapped object looks up its own material
+            ObjectDesc::Instance { .. } => None,
+            ObjectDesc::GridMedium { .. } => None,
+        }
+    }
+
+    /// Whether the object is a plain shape, which has to name its material unless it bounds a
+    /// medium.
+    fn needs_material(&self) -> bool {
+        matches!(*self,
+                 ObjectDesc::Sphere { .. } |
+                 ObjectDesc::MovingSphere { .. } |
+                 ObjectDesc::Quad { .. } |
+                 ObjectDesc::Box { .. } |
+                 ObjectDesc::Plane { .. })
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String, io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref path, ref e) => write!(f, "{}: {}", path, e),
+            SceneError::Parse(ref msg) | SceneError::Invalid(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+fn vec3(a: [f32; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+fn parse_error<E: fmt::Display>(e: serde_path_to_error::Error<E>) -> SceneError {
+    let path = e.path().to_string();
+    if path == "." {
+        SceneError::Parse(e.into_inner().to_string())
+    } else {
+        SceneError::Parse(format!("in `{}`: {}", path, e.into_inner()))
+    }
+}
+
+impl TextureDesc {
+    pub fn build(&self, base_dir: &Path) -> Result<Arc<dyn Texture>, SceneError> {
+        Ok(match *self {
+               TextureDesc::Constant { color } => Arc::new(ConstantTexture::new(vec3(color))),
+               TextureDesc::Checker { odd, even, size } => {
+                   if !(size > 0. && size.is_finite()) {
+                       return Err(SceneError::Invalid("`size` must be positive".to_string()));
+                   }
+                   Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(vec3(odd))),
+                                                Arc::new(ConstantTexture::new(vec3(even))),
+                                                size))
+               }
+               TextureDesc::Image { ref path } => {
+                   let path = base_dir.join(path);
+                   let image = ImageTexture::load(&path).map_err(|e| SceneError::Io(path.display().to_string(), e))?;
+                   Arc::new(image)
+               }
+               TextureDesc::Noise { seed, scale } => Arc::new(NoiseTexture::new(seed, scale)),
+               TextureDesc::Marble { seed, scale } => Arc::new(MarbleTexture::new(seed, scale)),
+               TextureDesc::Wood { seed, scale } => Arc::new(WoodTexture::new(seed, scale)),
+               TextureDesc::Granite { seed, scale } => Arc::new(GraniteTexture::new(seed, scale)),
+           })
+    }
+}
+
+impl ColorDesc {
+    fn build(&self, textures: &BTreeMap<&str, Arc<dyn Texture>>) -> Result<Arc<dyn Texture>, String> {
+        match *self {
+            ColorDesc::Color(c) => Ok(Arc::new(ConstantTexture::new(vec3(c)))),
+            ColorDesc::Texture(ref name) => {
+                textures.get(name.as_str())
+                        .cloned()
+                        .ok_or_else(|| format!("unknown texture `{}`", name))
+            }
+        }
+    }
+}
+
+impl MaterialDesc {
+    /// `textures` holds the already built entries of `[textures]` by name.
+    pub fn build(&self, textures: &BTreeMap<&str, Arc<dyn Texture>>) -> Result<Arc<dyn Material>, String> {
+        Ok(match *self {
+               MaterialDesc::Lambertian { ref albedo } => Arc::new(Lambertian::textured(albedo.build(textures)?)),
+               MaterialDesc::Metal { ref albedo, fuzz } => Arc::new(Metal::textured(albedo.build(textures)?, fuzz)),
+               MaterialDesc::Dielectric { ref_idx } => Arc::new(Dielectric::new(ref_idx)),
+               MaterialDesc::DiffuseLight { ref emit } => Arc::new(DiffuseLight::textured(emit.build(textures)?)),
+               MaterialDesc::Isotropic { ref albedo } => Arc::new(Isotropic::textured(albedo.build(textures)?)),
+               MaterialDesc::HenyeyGreenstein { ref albedo, g } => {
+                   Arc::new(HenyeyGreenstein::textured(albedo.build(textures)?, g))
+               }
+           })
+    }
+}
+
+const INTENSITY_OR_POWER: &str = "give either `intensity` or `power`";
+
+impl LightDesc {
+    pub fn build(&self) -> Result<Box<dyn Light>, String> {
+        Ok(match *self {
+               LightDesc::Point { position, intensity, power } => {
+                   match (intensity, power) {
+                       (Some(intensity), None) => Box::new(PointLight::new(vec3(position), vec3(intensity))),
+                       (None, Some(power)) => Box::new(PointLight::from_power(vec3(position), vec3(power))),
+                       _ => return Err(INTENSITY_OR_POWER.to_string()),
+                   }
+               }
+               LightDesc::Spot { position, direction, angle, falloff_start, intensity, power } => {
+                   if direction == [0., 0., 0.] {
+                       return Err("`direction` must not be zero".to_string());
+                   }
+                   if !(angle > 0. && angle <= 180.) {
+                       return Err("`angle` must be above 0 and at most 180 degrees".to_string());
+                   }
+                   if falloff_start.is_some_and(|f| !f.is_finite()) {
+                       return Err("`falloff_start` must be a finite number of degrees".to_string());
+                   }
+                   let (position, direction) = (vec3(position), vec3(direction));
+                   let falloff_start = falloff_start.unwrap_or(angle);
+                   match (intensity, power) {
+                       (Some(intensity), None) => {
+                           Box::new(SpotLight::new(position, direction, vec3(intensity), angle, falloff_start))
+                       }
+                       (None, Some(power)) => {
+                           let spot = SpotLight::from_power(position, direction, vec3(power), angle, falloff_start);
+                           Box::new(spot.ok_or_else(|| "`angle` is too narrow to send out any power".to_string())?)
+                       }
+                       _ => return Err(INTENSITY_OR_POWER.to_string()),
+                   }
+               }
+               LightDesc::Directional { direction, irradiance } => {
+                   if direction == [0., 0., 0.] {
+                       return Err("`direction` must not be zero".to_string());
+                   }
+                   Box::new(DirectionalLight::new(vec3(direction), vec3(irradiance)))
+               }
+           })
+    }
+}
+
+impl CameraDesc {
+    pub fn build(&self, default_aspect: f32) -> Camera {
+        let lookfrom = vec3(self.lookfrom);
+        let lookat = vec3(self.lookat);
+        let focus_dist = self.focus_dist
+                             .unwrap_or_else(|| (lookfrom.clone() - lookat.clone()).len());
+        let cam = Camera::with_focus(&lookfrom,
+                                     &lookat,
+                                     &vec3(self.vup),
+                                     self.vfov,
+                                     self.aspect.unwrap_or(default_aspect),
+                                     self.aperture,
+                                     focus_dist);
+        match self.shutter {
+            Some([open, close]) => cam.with_shutter(open, close),
+            None => cam,
+        }
+    }
+}
+
+impl SceneFile {
+    pub fn from_toml(s: &str) -> Result<Self, SceneError> {
+        let de = toml::Deserializer::new(s);
+        let scene: Self = serde_path_to_error::deserialize(de).map_err(parse_error)?;
+        scene.validate()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, SceneError> {
+        let mut de = serde_json::Deserializer::from_str(s);
+        let scene: Self = serde_path_to_error::deserialize(&mut de).map_err(parse_error)?;
+        de.end().map_err(|e| SceneError::Parse(e.to_string()))?;
+        scene.validate()
+    }
+
+    /// Rejects settings that would leave nothing to render.
+    fn validate(self) -> Result<Self, SceneError> {
+        let render = &self.render;
+        for (field, value) in [("width", render.width), ("height", render.height), ("samples", render.samples)] {
+            if value == 0 {
+                return Err(SceneError::Invalid(format!("in `render.{}`: {} must be positive", field, field)));
+            }
+        }
+        // each of these would leave the camera without a direction to look in
+        let camera = &self.camera;
+        let view = vec3(camera.lookat) - vec3(camera.lookfrom);
+        if view.squared_len() == 0. {
+            return Err(SceneError::Invalid("in `camera.lookat`: lookat must differ from lookfrom".to_string()));
+        }
+        if cross(&vec3(camera.vup), &view).squared_len() == 0. {
+            return Err(SceneError::Invalid("in `camera.vup`: vup must not be zero or along the view direction"
+                                               .to_string()));
+        }
+        if !(camera.vfov > 0. && camera.vfov < 180.) {
+            return Err(SceneError::Invalid("in `camera.vfov`: vfov must be above 0 and below 180 degrees".to_string()));
+        }
+        Ok(self)
+    }
+
+    /// Reads a scene file, choosing the format from the `.toml` or `.json` extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let s = fs::read_to_string(path).map_err(|e| SceneError::Io(name.clone(), e))?;
+        let scene = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&s),
+            Some("json") => Self::from_json(&s),
+            _ => return Err(SceneError::Invalid(format!("{}: scene files must end in .toml or .json", name))),
+        };
+        let mut scene = scene.map_err(|e| match e {
+                                   SceneError::Invalid(msg) => SceneError::Invalid(format!("{}: {}", name, msg)),
+                                   e => SceneError::Parse(format!("{}: {}", name, e)),
+                               })?;
+        scene.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scene)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            width: self.render.width,
+            height: self.render.height,
+            samples: self.render.samples,
+            max_depth: self.render.max_depth,
+            min_depth: self.render.min_depth.unwrap_or(self.render.max_depth),
+            seed: self.render.seed,
+            integrator: self.render.integrator,
+            background: match self.render.background {
+                Some(c) => Background::Solid(vec3(c)),
+                None => Background::Sky,
+            },
+            ..RenderSettings::default()
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.camera.build(self.render.width as f32 / self.render.height as f32)
+    }
+
+    /// Builds every object, sharing one instance of each named texture and material between them.
+    pub fn world(&self) -> Result<HitableList, SceneError> {
+        self.world_and_lights().map(|(world, _)| world)
+    }
+
+    /// Like `world`, but also returns the spheres, quads and boxes made of a `DiffuseLight`
+    /// material, on their own or in instances, shared with the world, for `Integrator::NextEvent`
+    /// to sample.
+    pub fn world_and_lights(&self) -> Result<(HitableList, HitableList), SceneError> {
+        let mut textures: BTreeMap<&str, Arc<dyn Texture>> = BTreeMap::new();
+        for (name, t) in self.textures.iter() {
+            let texture = match t.build(&self.base_dir) {
+                Err(SceneError::Invalid(msg)) => {
+                    return Err(SceneError::Invalid(format!("in `textures.{}`: {}", name, msg)));
+                }
+                texture => texture?,
+            };
+            textures.insert(name.as_str(), texture);
+        }
+        let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+        for (name, m) in self.materials.iter() {
+            let mat = m.build(&textures)
+                       .map_err(|e| SceneError::Invalid(format!("in `materials.{}`: {}", name, e)))?;
+            materials.insert(name.as_str(), mat);
+        }
+        let mut list = HitableList::new(vec![]);
+        let mut lights = HitableList::new(vec![]);
+        for (i, obj) in self.objects.iter().enumerate() {
+            let built = self.object(obj, &format!("objects[{}]", i), &materials)?;
+            if self.is_light(obj) {
+                let shared: Arc<dyn Hitable> = Arc::from(built);
+                lights.list.push(Box::new(shared.clone()));
+                list.list.push(Box::new(shared));
+            } else {
+                list.list.push(built);
+            }
+        }
+        Ok((list, lights))
+    }
+
+    /// Builds the entries of `[[lights]]`, which the integrators send shadow rays to at every hit.
+    pub fn delta_lights(&self) -> Result<Vec<Box<dyn Light>>, SceneError> {
+        self.lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| light.build().map_err(|e| SceneError::Invalid(format!("in `lights[{}]`: {}", i, e))))
+            .collect()
+    }
+
+    /// Whether `obj` emits light and its shape can be sampled as a light.
+    fn is_light(&self, obj: &ObjectDesc) -> bool {
+        if let ObjectDesc::Instance { ref object, .. } = *obj {
+            return self.is_light(object);
+        }
+        let samplable = matches!(*obj, ObjectDesc::Sphere { .. } | ObjectDesc::Quad { .. } | ObjectDesc::Box { .. });
+        let material = obj.material().and_then(|name| self.materials.get(name));
+        samplable && matches!(material, Some(&MaterialDesc::DiffuseLight { .. }))
+    }
+
+    /// Builds one object; `at` is its path in the file, for error messages.
+    fn object(&self,
+              obj: &ObjectDesc,
+              at: &str,
+              materials: &BTreeMap<&str, Arc<dyn Material>>)
+              -> Result<Box<dyn Hitable>, SceneError> {
+        self.shape(obj, at, materials, false)
+    }
+
+    /// Builds `obj`, which may leave out its material if it is the `boundary` of a medium, where
+    /// the material is never seen.
+    fn shape(&self,
+             obj: &ObjectDesc,
+             at: &str,
+             materials: &BTreeMap<&str, Arc<dyn Material>>,
+             boundary: bool)
+             -> Result<Box<dyn Hitable>, SceneError> {
+        let mat = match obj.material() {
+            Some(name) => materials.get(name).cloned().ok_or_else(|| {
+                SceneError::Invalid(format!("in `{}.material`: unknown material `{}`", at, name))
+            })?,
+            None if obj.needs_material() && !boundary => {
+                return Err(SceneError::Invalid(format!("in `{}`: missing `material`", at)));
+            }
+            None => Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73))),
+        };
+        Ok(match *obj {
+               ObjectDesc::Sphere { center, radius, .. } => Box::new(Sphere::new(vec3(center), radius, mat)),
+               ObjectDesc::MovingSphere { center0, center1, time0, time1, radius, .. } => {
+                   Box::new(MovingSphere::new(vec3(center0), vec3(center1), time0, time1, radius, mat))
+               }
+               ObjectDesc::Quad { q, u, v, .. } => {
+                   let quad = Quad::new(vec3(q), vec3(u), vec3(v), mat).ok_or_else(|| {
+                       SceneError::Invalid(format!("in `{}`: `u` and `v` must not be parallel or zero", at))
+                   })?;
+                   Box::new(quad)
+               }
+               ObjectDesc::Box { min, max, .. } => Box::new(BoxShape::new(vec3(min), vec3(max), mat)),
+               ObjectDesc::Plane { point, normal, .. } => {
+                   if normal == [0., 0., 0.] {
+                       return Err(SceneError::Invalid(format!("in `{}.normal`: normal must not be zero", at)));
+                   }
+                   Box::new(Plane::new(vec3(point), vec3(normal), mat))
+               }
+               ObjectDesc::Mesh { ref path, vertex_colors, .. } => {
+                   let path = self.base_dir.join(path);
+                   let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+                   let mesh = match ext.as_deref() {
+                       Some("obj") => obj::load(&path, mat),
+                       Some("ply") => ply::load(&path, mat, vertex_colors),
+                       Some("stl") => stl::load(&path, mat),
+                       Some("gltf") | Some("glb") => gltf::load(&path, mat).map(|scene| scene.mesh),
+                       _ => {
+                           let msg = format!("in `{}.path`: meshes must be .obj, .ply, .stl, .gltf or .glb", at);
+                           return Err(SceneError::Invalid(msg));
+                       }
+                   };
+                   Box::new(mesh.map_err(|e| SceneError::Io(path.display().to_string(), e))?)
+               }
+               ObjectDesc::Instance { ref object, translate, rotate, scale } => {
+                   if scale.contains(&0.) {
+                       return Err(SceneError::Invalid(format!("in `{}.scale`: scale must not be zero", at)));
+                   }
+                   let rotation = Transform::rotate_z(rotate[2]) * Transform::rotate_y(rotate[1]) *
+                                  Transform::rotate_x(rotate[0]);
+                   let transform = Transform::translate(&vec3(translate)) * rotation * Transform::scale(&vec3(scale));
+                   let inner = self.shape(object, &format!("{}.object", at), materials, boundary)?;
+                   let inner: Arc<dyn Hitable> = Arc::from(inner);
+                   Box::new(Instance::new(inner, transform))
+               }
+               ObjectDesc::GridMedium { ref path, density, albedo, emission, g } => {
+                   if density < 0. || !density.is_finite() {
+                       let msg = format!("in `{}.density`: density must not be negative", at);
+                       return Err(SceneError::Invalid(msg));
+                   }
+                   let path = self.base_dir.join(path);
+                   let grid = voxel::load(&path).map_err(|e| SceneError::Io(path.display().to_string(), e))?;
+                   let mut medium = GridMedium::new(Arc::new(grid), density).with_anisotropy(g);
+                   if let Some(albedo) = albedo {
+                       medium = medium.with_albedo(vec3(albedo));
+                   }
+                   if let Some(emission) = emission {
+                       medium = medium.with_emission(vec3(emission));
+                   }
+                   Box::new(medium)
+               }
+               ObjectDesc::ConstantMedium { ref boundary, density, ref material } => {
+                   if density <= 0. || !density.is_finite() {
+                       return Err(SceneError::Invalid(format!("in `{}.density`: density must be positive", at)));
+                   }
+                   // surface materials would scatter about the arbitrary normal of a collision
+                   let phase = self.materials.get(material).is_some_and(|m| {
+                       matches!(*m, MaterialDesc::Isotropic { .. } | MaterialDesc::HenyeyGreenstein { .. })
+                   });
+                   if !phase {
+                       let msg = format!("in `{}.material`: `{}` must be an Isotropic or HenyeyGreenstein material",
+                                         at,
+                                         material);
+                       return Err(SceneError::Invalid(msg));
+                   }
+                   let boundary = self.shape(boundary, &format!("{}.boundary", at), materials, true)?;
+                   Box::new(ConstantMedium::new(boundary, density, mat))
+               }
+           })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::SceneFile;
+    use utils::aabb::Aabb;
+    use utils::render::{Background, Integrator};
+    use utils::vec3::Vec3;
+
+    const SCENE: &str = r#"
+[render]
+width = 40
+height = 20
+
+[camera]
+lookfrom = [3, 3, 2]
+lookat = [0, 0, -1]
+vfov = 20
+aperture = 2
+
+[materials.ground]
+type = "Lambertian"
+albedo = [0.8, 0.8, 0.0]
+
+[materials.gold]
+type = "Metal"
+albedo = [0.8, 0.6, 0.2]
+fuzz = 0.1
+
+[[objects]]
+type = "Sphere"
+center = [0, -100.5, -1]
+radius = 100
+material = "ground"
+
+[[objects]]
+type = "Sphere"
+center = [1, 0, -1]
+radius = 0.5
+material = "gold"
+"#;
+
+    #[test]
+    fn loads_toml_scene() {
+        let scene = SceneFile::from_toml(SCENE).unwrap();
+        assert_eq!(scene.render.width, 40);
+        assert_eq!(scene.render.samples, 100);
+        assert_eq!(scene.world().unwrap().list.len(), 2);
+        assert_eq!(scene.settings().background, Background::Sky);
+        // Russian roulette is off unless a minimum depth is given
+        assert_eq!(scene.settings().min_depth, scene.settings().max_depth);
+        let deep = SceneFile::from_toml(&SCENE.replace("height = 20\n", "height = 20\nmax_depth = 80\n")).unwrap();
+        assert_eq!(deep.settings().min_depth, 80);
+        let roulette = SceneFile::from_toml(&SCENE.replace("height = 20\n", "height = 20\nmin_depth = 3\n")).unwrap();
+        assert_eq!(roulette.settings().min_depth, 3);
+    }
+
+    #[test]
+    fn loads_lights_and_background() {
+        let lit = SCENE.replace("height = 20\n", "height = 20\nbackground = [0, 0, 0]\n")
+                       .replace("type = \"Metal\"\nalbedo = [0.8, 0.6, 0.2]\nfuzz = 0.1",
+                                "type = \"DiffuseLight\"\nemit = [4, 4, 4]");
+        let scene = SceneFile::from_toml(&lit.replace("height = 20\n", "height = 20\nintegrator = \"nee\"\n")).unwrap();
+        assert_eq!(scene.settings().background, Background::Solid(Vec3::new(0., 0., 0.)));
+        assert_eq!(scene.settings().integrator, Integrator::NextEvent);
+        let (world, lights) = scene.world_and_lights().unwrap();
+        assert_eq!(world.list.len(), 2);
+        assert_eq!(lights.list.len(), 1);
+
+        // a light moved by an instance is still sampled
+        let moved = lit +
+                    r#"
+[[objects]]
+type = "Instance"
+translate = [0, 3, 0]
+object = { type = "Sphere", center = [0, 0, 0], radius = 0.5, material = "gold" }
+"#;
+        let (world, lights) = SceneFile::from_toml(&moved).unwrap().world_and_lights().unwrap();
+        assert_eq!(world.list.len(), 3);
+        assert_eq!(lights.list.len(), 2);
+    }
+
+    #[test]
+    fn toml_errors_report_line_and_field() {
+        let bad = SCENE.replace("radius = 0.5\n", "");
+        let msg = SceneFile::from_toml(&bad).unwrap_err().to_string();
+        assert!(msg.contains("objects[1]"), "{}", msg);
+        assert!(msg.contains("line"), "{}", msg);
+        assert!(msg.contains("radius"), "{}", msg);
+    }
+
+    #[test]
+    fn json_errors_report_line_and_field() {
+        let bad = r#"{
+            "camera": {"lookfrom": [0, 0, 0], "lookat": [0, 0, -1], "vfov": 90},
+            "objects": [{"type": "Sphere", "center": [0, 0, -1], "radius": "big", "material": "m"}]
+        }"#;
+        let msg = SceneFile::from_json(bad).unwrap_err().to_string();
+        assert!(msg.contains("objects[0]"), "{}", msg);
+        assert!(msg.contains("\"big\""), "{}", msg);
+        assert!(msg.contains("line 3"), "{}", msg);
+    }
+
+    #[test]
+    fn materials_use_named_textures() {
+        let textured = SCENE.replace("albedo = [0.8, 0.8, 0.0]", "albedo = \"tiles\"")
+                            .replace("albedo = [0.8, 0.6, 0.2]", "albedo = \"marble\"") +
+                       "\n[textures.tiles]\ntype = \"Checker\"\nodd = [0, 0, 0]\neven = [1, 1, 1]\n" +
+                       "\n[textures.marble]\ntype = \"Marble\"\nseed = 3\n";
+        let scene = SceneFile::from_toml(&textured).unwrap();
+        assert_eq!(scene.world().unwrap().list.len(), 2);
+
+        let missing = textured.replace("albedo = \"tiles\"", "albedo = \"granite\"");
+        let msg = SceneFile::from_toml(&missing).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("materials.ground"), "{}", msg);
+        assert!(msg.contains("granite"), "{}", msg);
+
+        let flat = textured.replace("even = [1, 1, 1]\n", "even = [1, 1, 1]\nsize = 0\n");
+        let msg = SceneFile::from_toml(&flat).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("textures.tiles") && msg.contains("size"), "{}", msg);
+    }
+
+    #[test]
+    fn loads_quads_boxes_and_planes() {
+        let shapes = SCENE.to_string() +
+                     r#"
+[[objects]]
+type = "Quad"
+q = [0, 0, 0]
+u = [1, 0, 0]
+v = [0, 1, 0]
+material = "gold"
+
+[[objects]]
+type = "Box"
+min = [0, 0, 0]
+max = [1, 2, 3]
+material = "ground"
+
+[[objects]]
+type = "Plane"
+point = [0, -1, 0]
+normal = [0, 1, 0]
+material = "ground"
+"#;
+        let scene = SceneFile::from_toml(&shapes).unwrap();
+        assert_eq!(scene.world().unwrap().list.len(), 5);
+        let flat = shapes.replace("normal = [0, 1, 0]", "normal = [0, 0, 0]");
+        let msg = SceneFile::from_toml(&flat).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[4].normal"), "{}", msg);
+        let sliver = shapes.replace("v = [0, 1, 0]", "v = [2, 0, 0]");
+        let msg = SceneFile::from_toml(&sliver).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[2]") && msg.contains("parallel"), "{}", msg);
+    }
+
+    #[test]
+    fn unknown_material_is_reported() {
+        let bad = SCENE.replace("material = \"gold\"", "material = \"silver\"");
+        let msg = SceneFile::from_toml(&bad).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[1].material"), "{}", msg);
+        assert!(msg.contains("silver"), "{}", msg);
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        let bad = [("width", SCENE.replace("width = 40", "width = 0")),
+                   ("height", SCENE.replace("height = 20", "height = 0")),
+                   ("samples", SCENE.replace("height = 20\n", "height = 20\nsamples = 0\n"))];
+        for &(field, ref scene) in bad.iter() {
+            let msg = SceneFile::from_toml(scene).err().unwrap().to_string();
+            assert!(msg.contains(&format!("render.{}", field)), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn blind_cameras_are_rejected() {
+        let bad = [("lookat", SCENE.replace("lookat = [0, 0, -1]", "lookat = [3, 3, 2]")),
+                   ("vup", SCENE.replace("vfov = 20", "vfov = 20\nvup = [0, 0, 0]")),
+                   ("vup", SCENE.replace("vfov = 20", "vfov = 20\nvup = [-3, -3, -3]")),
+                   ("vfov", SCENE.replace("vfov = 20", "vfov = 0")),
+                   ("vfov", SCENE.replace("vfov = 20", "vfov = 180")),
+                   ("vfov", SCENE.replace("vfov = 20", "vfov = nan"))];
+        for &(field, ref scene) in bad.iter() {
+            let msg = SceneFile::from_toml(scene).err().unwrap().to_string();
+            assert!(msg.contains(&format!("camera.{}", field)), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn missing_mesh_file_is_reported() {
+        let meshed = SCENE.to_string() + "\n[[objects]]\ntype = \"Mesh\"\npath = \"no-such-mesh.obj\"\n";
+        let msg = SceneFile::from_toml(&meshed).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("no-such-mesh.obj"), "{}", msg);
+        let msg = SceneFile::from_toml(&meshed.replace(".obj", ".3ds")).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[2].path"), "{}", msg);
+    }
+
+    #[test]
+    fn instances_transform_their_object() {
+        let placed = SCENE.to_string() +
+                     r#"
+[[objects]]
+type = "Instance"
+translate = [5, 0, 0]
+rotate = [0, 45, 0]
+object = { type = "Box", min = [0, 0, 0], max = [1, 1, 1], material = "gold" }
+"#;
+        let world = SceneFile::from_toml(&placed).unwrap().world().unwrap();
+        let mut bbox = Aabb::default();
+        assert!(world.list[2].bounding_box(0., 1., &mut bbox));
+        assert!(bbox.min.x() > 4.9 && bbox.max.x() > 6.4, "{:?}", bbox);
+
+        let bad = placed.replace("material = \"gold\" }", "material = \"brass\" }");
+        let msg = SceneFile::from_toml(&bad).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[2].object.material"), "{}", msg);
+    }
+
+    #[test]
+    fn moving_spheres_and_shutter() {
+        let moving = SCENE.replace("aperture = 2\n", "aperture = 2\nshutter = [0, 0.5]\n") +
+                     r#"
+[[objects]]
+type = "MovingSphere"
+center0 = [0, 0, 0]
+center1 = [0, 2, 0]
+radius = 0.5
+material = "gold"
+"#;
+        let scene = SceneFile::from_toml(&moving).unwrap();
+        let cam = scene.camera();
+        assert_eq!((cam.time0, cam.time1), (0., 0.5));
+        let world = scene.world().unwrap();
+        let mut bbox = Aabb::default();
+        assert!(world.list[2].bounding_box(cam.time0, cam.time1, &mut bbox));
+        assert_eq!((bbox.min.y(), bbox.max.y()), (-0.5, 1.5));
+    }
+
+    #[test]
+    fn media_fill_their_boundary() {
+        let foggy = SCENE.to_string() +
+                    r#"
+[materials.smoke]
+type = "HenyeyGreenstein"
+albedo = [0.9, 0.9, 0.9]
+g = 0.3
+
+[[objects]]
+type = "ConstantMedium"
+density = 0.5
+material = "smoke"
+boundary = { type = "Sphere", center = [0, 0, 0], radius = 2 }
+"#;
+        let world = SceneFile::from_toml(&foggy).unwrap().world().unwrap();
+        let mut bbox = Aabb::default();
+        assert!(world.list[2].bounding_box(0., 1., &mut bbox));
+        assert_eq!(bbox.max, Vec3::new(2., 2., 2.));
+
+        let clouds = foggy.replace("type = \"ConstantMedium\"", "type = \"GridMedium\"\npath = \"no-such.grid\"")
+                          .replace("material = \"smoke\"\nboundary = { type = \"Sphere\", center = [0, 0, 0], radius = 2 }\n",
+                                   "g = 0.2\n");
+        let msg = SceneFile::from_toml(&clouds).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("no-such.grid"), "{}", msg);
+
+        // only a medium's boundary may leave out its material
+        let bare = foggy.clone() +
+                   r#"
+[[objects]]
+type = "Sphere"
+center = [0, 0, 5]
+radius = 1
+"#;
+        let msg = SceneFile::from_toml(&bare).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[3]") && msg.contains("missing `material`"), "{}", msg);
+
+        let thin = foggy.replace("density = 0.5", "density = 0");
+        let msg = SceneFile::from_toml(&thin).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[2].density"), "{}", msg);
+
+        // a surface material has no direction to scatter about inside a medium
+        let shiny = foggy.replace("material = \"smoke\"", "material = \"gold\"");
+        let msg = SceneFile::from_toml(&shiny).unwrap().world().err().unwrap().to_string();
+        assert!(msg.contains("objects[2].material") && msg.contains("`gold`"), "{}", msg);
+    }
+
+    #[test]
+    fn loads_point_spot_and_directional_lights() {
+        let lit = SCENE.to_string() +
+                  r#"
+[[lights]]
+type = "Point"
+position = [0, 2, 0]
+power = [100, 100, 100]
+
+[[lights]]
+type = "Spot"
+position = [0, 3, -1]
+direction = [0, -1, 0]
+angle = 30
+falloff_start = 20
+intensity = [50, 50, 50]
+
+[[lights]]
+type = "Directional"
+direction = [1, -1, 0]
+irradiance = [2, 2, 2]
+"#;
+        let scene = SceneFile::from_toml(&lit).unwrap();
+        let lights = scene.delta_lights().unwrap();
+        assert_eq!(lights.len(), 3);
+        let bulb = lights[0].illuminate(&Vec3::new(0., 0., 0.)).unwrap();
+        assert!((bulb.irradiance.x() - 100. / (4. * PI) / 4.).abs() < 1e-5);
+        let spot = lights[1].illuminate(&Vec3::new(0., 0., -1.)).unwrap();
+        assert!((spot.irradiance.y() - 50. / 9.).abs() < 1e-5);
+        assert!(lights[1].illuminate(&Vec3::new(3., 0., -1.)).is_none());
+        assert_eq!(lights[2].illuminate(&Vec3::new(0., 0., 0.)).unwrap().distance, f32::MAX);
+        // They have no shape, so the world is unchanged.
+        assert_eq!(scene.world().unwrap().list.len(), 2);
+
+        let both = lit.replace("power = [100, 100, 100]", "power = [100, 100, 100]\nintensity = [1, 1, 1]");
+        let msg = SceneFile::from_toml(&both).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[0]") && msg.contains("`power`"), "{}", msg);
+        let wide = lit.replace("angle = 30", "angle = 200");
+        let msg = SceneFile::from_toml(&wide).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[1]") && msg.contains("angle"), "{}", msg);
+        let unknown = lit.replace("angle = 30", "angle = nan");
+        let msg = SceneFile::from_toml(&unknown).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[1]") && msg.contains("angle"), "{}", msg);
+        let pinhole = lit.replace("angle = 30\nfalloff_start = 20\nintensity = [50, 50, 50]",
+                                  "angle = 0.0001\npower = [50, 50, 50]");
+        let msg = SceneFile::from_toml(&pinhole).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[1]") && msg.contains("too narrow"), "{}", msg);
+        let unknown = lit.replace("falloff_start = 20", "falloff_start = nan");
+        let msg = SceneFile::from_toml(&unknown).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[1]") && msg.contains("falloff_start"), "{}", msg);
+        let nowhere = lit.replace("direction = [1, -1, 0]", "direction = [0, 0, 0]");
+        let msg = SceneFile::from_toml(&nowhere).unwrap().delta_lights().err().unwrap().to_string();
+        assert!(msg.contains("lights[2]") && msg.contains("direction"), "{}", msg);
+        let msg = SceneFile::from_toml(&lit.replace("irradiance", "power")).err().unwrap().to_string();
+        assert!(msg.contains("lights[2]"), "{}", msg);
+    }
+}
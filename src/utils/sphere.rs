@@ -1,19 +1,25 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
 use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::Aabb;
 use utils::ray::Ray;
 use utils::vec3::{dot, Vec3};
-use utils::random::drand48;
+use utils::random::Sampler;
+use utils::sampling::random_around;
 use utils::material::Material;
 
+pub use utils::sampling::random_in_unit_sphere;
+
 #[allow(dead_code)]
 pub struct Sphere {
     pub center: Vec3,
     radius: f32,
-    mat: Box<Material>,
+    mat: Arc<dyn Material>,
 }
 
 #[allow(dead_code)]
 impl Sphere {
-    pub fn new(cen: Vec3, r: f32, m: Box<Material>) -> Self {
+    pub fn new(cen: Vec3, r: f32, m: Arc<dyn Material>) -> Self {
         Self {
             center: cen,
             radius: r,
@@ -23,39 +29,114 @@ impl Sphere {
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        let oc: Vec3 = r.origin().clone() - self.center.clone();
-        let a: f32 = dot(r.direction(), r.direction());
-        let b: f32 = dot(&oc, r.direction());
-        let c: f32 = dot(&oc, &oc) - self.radius * self.radius;
-        let discriminaun = b * b - a * c;
-        if discriminaun > 0. {
-            let mut temp: f32 = (-b - discriminaun.sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.point_at_parameter(rec.t);
-                rec.normal = (rec.p.clone() - self.center.clone()) / self.radius;
-                rec.mat = self.mat.clone();
-                return true;
-            }
-            temp = (-b + discriminaun.sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.point_at_parameter(rec.t);
-                rec.normal = (rec.p.clone() - self.center.clone()) / self.radius;
-                rec.mat = self.mat.clone();
-                return true;
-            }
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        hit_sphere(&self.center, self.radius, &self.mat, r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        *output_box = Aabb::new(self.center.clone() - Vec3::new(r, r, r),
+                                self.center.clone() + Vec3::new(r, r, r));
+        true
+    }
+
+    /// Uniform over the cone of directions that see the sphere; zero from inside it.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new(self.mat.clone());
+        // surfaces draw no random numbers while hitting, so any sampler does
+        if !self.hit(&Ray::new(origin, direction), 0.001, f32::MAX, &mut rec, &mut Sampler::new(0)) {
+            return 0.;
         }
-        return false;
+        match cone_cos_max(&self.center, self.radius, origin) {
+            Some(cos_max) => 1. / (2. * PI * (1. - cos_max)),
+            None => 0.,
+        }
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let cos_max = cone_cos_max(&self.center, self.radius, origin).unwrap_or(-1.);
+        let cos_theta = 1. + sampler.drand48() * (cos_max - 1.);
+        random_around(&(self.center.clone() - origin.clone()), cos_theta, sampler)
+    }
+}
+
+/// Cosine of the half angle of the cone a sphere fills as seen from `origin`, or `None` from
+/// inside it.
+fn cone_cos_max(center: &Vec3, radius: f32, origin: &Vec3) -> Option<f32> {
+    let dist2 = (center.clone() - origin.clone()).squared_len();
+    if dist2 <= radius * radius {
+        return None;
     }
+    Some((1. - radius * radius / dist2).sqrt())
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::new(drand48(), drand48(), drand48()) * 2.0 - Vec3::new(1., 1., 1.);
-        if p.squared_len() >= 1.0 {
-            return p;
+/// Ray-sphere intersection shared by every sphere shape. A negative `radius` flips the normal
+/// inwards, which makes hollow glass spheres.
+pub fn hit_sphere(center: &Vec3,
+                  radius: f32,
+                  mat: &Arc<dyn Material>,
+                  r: &Ray,
+                  t_min: f32,
+                  t_max: f32,
+                  rec: &mut HitRecord)
+                  -> bool {
+    let oc: Vec3 = r.origin().clone() - center.clone();
+    let a: f32 = dot(r.direction(), r.direction());
+    let b: f32 = dot(&oc, r.direction());
+    let c: f32 = dot(&oc, &oc) - radius * radius;
+    let discriminaun = b * b - a * c;
+    if discriminaun <= 0. {
+        return false;
+    }
+    for temp in [(-b - discriminaun.sqrt()) / a, (-b + discriminaun.sqrt()) / a] {
+        if temp < t_max && temp > t_min {
+            rec.t = temp;
+            rec.p = r.point_at_parameter(rec.t);
+            rec.normal = (rec.p.clone() - center.clone()) / radius;
+            let (u, v) = get_sphere_uv(&((rec.p.clone() - center.clone()) / radius.abs()));
+            rec.u = u;
+            rec.v = v;
+            rec.mat = mat.clone();
+            return true;
         }
     }
+    false
+}
+
+/// Maps a point on the unit sphere to `(u, v)`: `u` goes once around the y axis starting at -x and
+/// `v` runs from the south pole (0) to the north pole (1).
+pub fn get_sphere_uv(p: &Vec3) -> (f32, f32) {
+    let phi = p.z().atan2(p.x());
+    let theta = p.y().clamp(-1., 1.).asin();
+    (1. - (phi + PI) / (2. * PI), (theta + PI / 2.) / PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_sphere_uv, Sphere};
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    #[test]
+    fn sphere_uv_covers_poles_and_equator() {
+        let close = |(u, v): (f32, f32), (eu, ev): (f32, f32)| (u - eu).abs() < 1e-6 && (v - ev).abs() < 1e-6;
+        assert!(close(get_sphere_uv(&Vec3::new(1., 0., 0.)), (0.5, 0.5)));
+        assert!(close(get_sphere_uv(&Vec3::new(0., 0., 1.)), (0.25, 0.5)));
+        assert!(close(get_sphere_uv(&Vec3::new(0., 0., -1.)), (0.75, 0.5)));
+        assert!(get_sphere_uv(&Vec3::new(0., 1., 0.)).1 == 1.);
+        assert!(get_sphere_uv(&Vec3::new(0., -1., 0.)).1 == 0.);
+    }
+
+    #[test]
+    fn hit_records_uv() {
+        let mut sampler = Sampler::new(0);
+        let sphere = Sphere::new(Vec3::new(0., 0., -3.), 2., dummy());
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0., 0., 0.), &Vec3::new(0., 0., -1.));
+        assert!(sphere.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.5).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,168 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+use utils::vec3::Vec3;
+use utils::material::Material;
+use utils::mesh::{Face, MeshData, TriangleMesh};
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Adds a triangle with its own three vertices; STL does not share them between facets.
+fn push_triangle(data: &mut MeshData, corners: &[Vec3]) {
+    let first = data.positions.len();
+    data.positions.extend_from_slice(corners);
+    data.faces.push(Face {
+                        positions: [first, first + 1, first + 2],
+                        ..Face::default()
+                    });
+}
+
+fn parse_binary(bytes: &[u8], data: &mut MeshData) {
+    for facet in bytes[84..].chunks(50) {
+        let f = |i: usize| f32::from_le_bytes([facet[i], facet[i + 1], facet[i + 2], facet[i + 3]]);
+        // the first 12 bytes are the facet normal, which we derive from the winding instead
+        let corners: Vec<Vec3> = (0..3).map(|k| 12 + 12 * k).map(|o| Vec3::new(f(o), f(o + 4), f(o + 8))).collect();
+        push_triangle(data, &corners);
+    }
+}
+
+fn parse_ascii(text: &str, data: &mut MeshData) -> io::Result<()> {
+    let mut corners = vec![];
+    let mut in_facet = false;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().cloned() {
+            Some("facet") => {
+                if in_facet {
+                    return Err(invalid(format!("line {}: `facet` inside another facet", line_no)));
+                }
+                in_facet = true;
+                corners.clear();
+            }
+            Some("vertex") => {
+                if !in_facet {
+                    return Err(invalid(format!("line {}: `vertex` outside a facet", line_no)));
+                }
+                if words.len() != 4 {
+                    return Err(invalid(format!("line {}: expected 3 numbers", line_no)));
+                }
+                let mut p = [0.; 3];
+                for (k, w) in words[1..].iter().enumerate() {
+                    p[k] = w.parse().map_err(|_| invalid(format!("line {}: invalid number `{}`", line_no, w)))?;
+                }
+                corners.push(Vec3::new(p[0], p[1], p[2]));
+            }
+            Some("endfacet") => {
+                if corners.len() != 3 {
+                    return Err(invalid(format!("line {}: facet has {} vertices instead of 3", line_no, corners.len())));
+                }
+                push_triangle(data, &corners);
+                in_facet = false;
+            }
+            _ => {}
+        }
+    }
+    if in_facet {
+        return Err(invalid("unexpected end of file inside a facet".to_string()));
+    }
+    Ok(())
+}
+
+/// Parses an ASCII or binary STL file. A file whose size matches the triangle count of a binary
+/// header is read as binary even if it starts with `solid`, as many exporters write that there.
+pub fn parse_stl(bytes: &[u8], mat: Arc<dyn Material>) -> io::Result<MeshData> {
+    let mut data = MeshData {
+        materials: vec![mat],
+        ..MeshData::default()
+    };
+    let count = if bytes.len() >= 84 {
+        Some(u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize)
+    } else {
+        None
+    };
+    if count.is_some_and(|n| bytes.len() == 84 + 50 * n) {
+        parse_binary(bytes, &mut data);
+    } else if bytes.trim_ascii_start().starts_with(b"solid") {
+        let text = str::from_utf8(bytes).map_err(|_| invalid("ASCII STL is not text".to_string()))?;
+        parse_ascii(text, &mut data)?;
+    } else {
+        return Err(invalid(match count {
+                               Some(n) => {
+                                   format!("binary STL declares {} triangles ({} bytes) but is {} bytes long",
+                                           n,
+                                           84 + 50 * n,
+                                           bytes.len())
+                               }
+                               None => "file is too short to be an STL file".to_string(),
+                           }));
+    }
+    Ok(data)
+}
+
+/// Loads an STL file as a mesh made of `mat`.
+pub fn load<P: AsRef<Path>>(path: P, mat: Arc<dyn Material>) -> io::Result<TriangleMesh> {
+    let bytes = fs::read(path)?;
+    let data = parse_stl(&bytes, mat)?;
+    TriangleMesh::new(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_stl;
+    use utils::material::dummy;
+    use utils::vec3::Vec3;
+
+    const ASCII: &str = "solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid tri
+";
+
+    #[test]
+    fn reads_ascii_and_binary() {
+        let ascii = parse_stl(ASCII.as_bytes(), dummy()).unwrap();
+        assert_eq!(ascii.faces.len(), 1);
+        assert_eq!(ascii.positions[1], Vec3::new(1., 0., 0.));
+
+        // a header starting with "solid" must not fool the reader
+        let mut binary = b"solid exported by some CAD tool".to_vec();
+        binary.resize(80, b' ');
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for z in [0.0f32, 2.].iter() {
+            for v in [0., 0., 1., 0., 0., *z, 1., 0., *z, 0., 1., *z].iter() {
+                binary.extend_from_slice(&v.to_le_bytes());
+            }
+            binary.extend_from_slice(&[0, 0]);
+        }
+        let data = parse_stl(&binary, dummy()).unwrap();
+        assert_eq!(data.faces.len(), 2);
+        assert_eq!(data.faces[1].positions, [3, 4, 5]);
+        assert_eq!(data.positions[5], Vec3::new(0., 1., 2.));
+    }
+
+    #[test]
+    fn malformed_files_are_reported() {
+        let short = ASCII.replace("      vertex 0 1 0\n", "");
+        let msg = parse_stl(short.as_bytes(), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("line 7") && msg.contains("2 vertices"), "{}", msg);
+        let bad = ASCII.replace("vertex 1 0 0", "vertex 1 zero 0");
+        let msg = parse_stl(bad.as_bytes(), dummy()).err().unwrap().to_string();
+        assert!(msg.contains("line 5") && msg.contains("zero"), "{}", msg);
+
+        let mut truncated = vec![0u8; 80];
+        truncated.extend_from_slice(&3u32.to_le_bytes());
+        truncated.extend_from_slice(&[0; 100]);
+        let msg = parse_stl(&truncated, dummy()).err().unwrap().to_string();
+        assert!(msg.contains("3 triangles"), "{}", msg);
+    }
+}
@@ -0,0 +1,417 @@
+extern crate png;
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use utils::vec3::Vec3;
+use utils::tonemap::srgb_eotf;
+use utils::noise::Perlin;
+
+/// A color that varies over a surface, looked up by surface coordinates `(u, v)` or by the hit
+/// point `p`.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3;
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct ConstantTexture {
+    pub color: Vec3,
+}
+
+#[allow(dead_code)]
+impl ConstantTexture {
+    pub fn new(c: Vec3) -> Self {
+        Self { color: c }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+        self.color.clone()
+    }
+}
+
+/// Solid checkerboard of cubes with edge length `size`, so it does not depend on how a surface is
+/// parameterized.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct CheckerTexture {
+    pub odd: Arc<dyn Texture>,
+    pub even: Arc<dyn Texture>,
+    pub size: f32,
+}
+
+#[allow(dead_code)]
+impl CheckerTexture {
+    pub fn new(odd: Arc<dyn Texture>, even: Arc<dyn Texture>, size: f32) -> Self {
+        Self { odd, even, size }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        let cell = |c: f32| (c / self.size).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Grey fractal turbulence, a cloudy camouflage pattern.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    /// Frequency of the base octave; larger values give a finer pattern.
+    pub scale: f32,
+    pub octaves: u32,
+}
+
+#[allow(dead_code)]
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            octaves: 7,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+        let t = self.noise.turbulence(&(p.clone() * self.scale), self.octaves).min(1.);
+        Vec3::new(t, t, t)
+    }
+}
+
+/// Bands along z whose phase is disturbed by turbulence, giving marble veins.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    pub scale: f32,
+    pub base: Vec3,
+    pub vein: Vec3,
+}
+
+#[allow(dead_code)]
+impl MarbleTexture {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            base: Vec3::new(0.9, 0.9, 0.88),
+            vein: Vec3::new(0.25, 0.25, 0.3),
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+        let phase = self.scale * p.z() + 10. * self.noise.turbulence(p, 7);
+        lerp(&self.vein, &self.base, 0.5 * (1. + phase.sin()))
+    }
+}
+
+/// Concentric rings around the y axis, warped by low frequency noise.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct WoodTexture {
+    pub noise: Perlin,
+    /// Rings per unit of distance from the axis.
+    pub scale: f32,
+    pub light: Vec3,
+    pub dark: Vec3,
+}
+
+#[allow(dead_code)]
+impl WoodTexture {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            light: Vec3::new(0.75, 0.52, 0.3),
+            dark: Vec3::new(0.4, 0.22, 0.1),
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+        let r = (p.x() * p.x() + p.z() * p.z()).sqrt() + 0.2 * self.noise.fbm(p, 4);
+        let ring = (r * self.scale).fract().abs();
+        // sharpen the late wood at the end of each ring
+        lerp(&self.light, &self.dark, ring * ring * ring)
+    }
+}
+
+/// Fine speckles from high frequency turbulence.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct GraniteTexture {
+    pub noise: Perlin,
+    pub scale: f32,
+    pub light: Vec3,
+    pub dark: Vec3,
+}
+
+#[allow(dead_code)]
+impl GraniteTexture {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            light: Vec3::new(0.8, 0.78, 0.75),
+            dark: Vec3::new(0.12, 0.1, 0.1),
+        }
+    }
+}
+
+impl Texture for GraniteTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+        let t = (1.5 * self.noise.turbulence(&(p.clone() * self.scale), 8)).min(1.);
+        lerp(&self.dark, &self.light, t.sqrt())
+    }
+}
+
+fn lerp(a: &Vec3, b: &Vec3, t: f32) -> Vec3 {
+    a.clone() * (1. - t) + b.clone() * t
+}
+
+/// An RGB image mapped over `[0, 1]²`, with `v = 1` at the top row. Pixels are stored linear.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    data: Vec<Vec3>,
+}
+
+#[allow(dead_code)]
+impl ImageTexture {
+    /// `data` holds `width * height` linear colors, top row first.
+    pub fn new(width: usize, height: usize, data: Vec<Vec3>) -> Self {
+        assert_eq!(data.len(), width * height, "image texture data does not match its size");
+        Self { width, height, data }
+    }
+
+    /// Loads an sRGB encoded PNG or PPM (P3 or P6) image, picked by the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match ext.as_deref() {
+            Some("png") => Self::from_png(BufReader::new(File::open(path)?)),
+            Some("ppm") => {
+                let mut data = vec![];
+                File::open(path)?.read_to_end(&mut data)?;
+                Self::from_ppm(&data)
+            }
+            _ => Err(invalid("image textures must be .png or .ppm files".to_string())),
+        }
+    }
+
+    pub fn from_png<R: Read>(r: R) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(r);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| invalid(e.to_string()))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(|e| invalid(e.to_string()))?;
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => return Err(invalid("unexpanded indexed png".to_string())),
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        let data = buf[..info.buffer_size()].chunks(channels)
+                                            .map(|px| if channels < 3 {
+                                                     decode(px[0], px[0], px[0], 255)
+                                                 } else {
+                                                     decode(px[0], px[1], px[2], 255)
+                                                 })
+                                            .collect();
+        Ok(Self::new(width, height, data))
+    }
+
+    pub fn from_ppm(bytes: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let magic = ppm_token(bytes, &mut pos)?;
+        let width = ppm_number(bytes, &mut pos)?;
+        let height = ppm_number(bytes, &mut pos)?;
+        let max = ppm_number(bytes, &mut pos)?;
+        if max == 0 || max > 255 {
+            return Err(invalid(format!("unsupported ppm maximum value: {}", max)));
+        }
+        let n = width.checked_mul(height)
+                     .and_then(|n| n.checked_mul(3))
+                     .ok_or_else(|| invalid(format!("ppm size out of range: {}x{}", width, height)))?;
+        let samples: Vec<u8> = match magic.as_str() {
+            "P3" => {
+                (0..n).map(|_| ppm_number(bytes, &mut pos).and_then(|v| ppm_sample(v, max)))
+                      .collect::<io::Result<_>>()?
+            }
+            "P6" => {
+                // a single whitespace byte separates the header from the raster
+                pos += 1;
+                if bytes.len().saturating_sub(pos) < n {
+                    return Err(invalid("truncated ppm".to_string()));
+                }
+                bytes[pos..pos + n].iter().map(|&v| ppm_sample(v as usize, max)).collect::<io::Result<_>>()?
+            }
+            _ => return Err(invalid(format!("unsupported ppm type: {}", magic))),
+        };
+        let data = samples.chunks(3).map(|px| decode(px[0], px[1], px[2], max as u8)).collect();
+        Ok(Self::new(width, height, data))
+    }
+
+    /// Multiplies every pixel by `c`, such as a material's color factor.
+    pub fn tinted(mut self, c: &Vec3) -> Self {
+        for px in self.data.iter_mut() {
+            *px = px.clone() * c.clone();
+        }
+        self
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vec3) -> Vec3 {
+        if self.data.is_empty() {
+            return Vec3::new(0., 1., 1.);
+        }
+        let u = u.clamp(0., 1.);
+        let v = 1. - v.clamp(0., 1.);
+        let i = ((u * self.width as f32) as usize).min(self.width - 1);
+        let j = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.data[j * self.width + i].clone()
+    }
+}
+
+fn decode(r: u8, g: u8, b: u8, max: u8) -> Vec3 {
+    let c = |v: u8| srgb_eotf(v as f32 / max as f32);
+    Vec3::new(c(r), c(g), c(b))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Next whitespace separated header token, skipping `#` comments.
+fn ppm_token(bytes: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(invalid("truncated ppm".to_string()));
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+fn ppm_number(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = ppm_token(bytes, pos)?;
+    token.parse().map_err(|_| invalid(format!("invalid number in ppm: {}", token)))
+}
+
+fn ppm_sample(value: usize, max: usize) -> io::Result<u8> {
+    if value > max {
+        return Err(invalid(format!("ppm sample {} is above the maximum value {}", value, max)));
+    }
+    Ok(value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{CheckerTexture, ConstantTexture, GraniteTexture, ImageTexture, MarbleTexture, NoiseTexture, Texture,
+                WoodTexture};
+    use utils::film::Film;
+    use utils::tonemap::DisplayTransform;
+    use utils::vec3::Vec3;
+
+    #[test]
+    fn checker_alternates_between_cells() {
+        let checker = CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(1., 1., 1.))),
+                                          Arc::new(ConstantTexture::new(Vec3::new(0., 0., 0.))),
+                                          0.5);
+        let at = |x, y, z| checker.value(0., 0., &Vec3::new(x, y, z)).x();
+        assert_eq!(at(0.1, 0.1, 0.1), 0.);
+        assert_eq!(at(0.6, 0.1, 0.1), 1.);
+        assert_eq!(at(-0.1, 0.1, 0.1), 1.);
+        assert_eq!(at(0.6, 0.6, 0.1), 0.);
+    }
+
+    #[test]
+    fn procedural_textures_are_seeded_colors() {
+        let textures: Vec<(Box<dyn Texture>, Box<dyn Texture>)> =
+            vec![(Box::new(NoiseTexture::new(3, 4.)), Box::new(NoiseTexture::new(3, 4.))),
+                 (Box::new(MarbleTexture::new(3, 4.)), Box::new(MarbleTexture::new(3, 4.))),
+                 (Box::new(WoodTexture::new(3, 4.)), Box::new(WoodTexture::new(3, 4.))),
+                 (Box::new(GraniteTexture::new(3, 4.)), Box::new(GraniteTexture::new(3, 4.)))];
+        for (a, b) in textures.iter() {
+            for i in 0..100 {
+                let p = Vec3::new(i as f32 * 0.13, (i as f32 * 0.7).cos(), -(i as f32) * 0.05);
+                let col = a.value(0., 0., &p);
+                assert_eq!(col, b.value(0., 0., &p));
+                for c in [col.x(), col.y(), col.z()].iter() {
+                    assert!((0. ..=1.).contains(c), "{:?}", col);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_ppm_with_comments() {
+        let ppm = b"P3\n# made by hand\n2 2\n255\n255 0 0  0 255 0\n0 0 255  255 255 255\n";
+        let image = ImageTexture::from_ppm(ppm).unwrap();
+        let p = Vec3::new(0., 0., 0.);
+        // v = 1 is the top row
+        assert_eq!(image.value(0.1, 0.9, &p).e, (1., 0., 0.));
+        assert_eq!(image.value(0.9, 0.9, &p).e, (0., 1., 0.));
+        assert_eq!(image.value(0.1, 0.1, &p).e, (0., 0., 1.));
+        assert!(ImageTexture::from_ppm(b"P3\n2 2\n255\n1 2 3").is_err());
+        assert!(ImageTexture::from_ppm(b"P3\n1 1\n100\n50 101 0\n").is_err());
+        assert!(ImageTexture::from_ppm(b"P6\n1 1\n100\n\x32\xff\x00").is_err());
+        assert!(ImageTexture::from_ppm(b"P6\n4294967296 4294967296\n255\n").is_err());
+        assert!(ImageTexture::from_ppm(b"P3\n99999 99999\n255\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn png_and_binary_ppm_round_trip_through_film() {
+        let mut film = Film::new(3, 2);
+        film.add_sample(1, 0, Vec3::new(0.5, 0.2, 0.));
+        film.add_sample(2, 1, Vec3::new(1., 1., 1.));
+        let transform = DisplayTransform::default();
+        for format in ["png", "p6"].iter() {
+            let mut data = vec![];
+            film.write_to(&mut data, format.parse().unwrap(), &transform).unwrap();
+            let image = if *format == "png" {
+                ImageTexture::from_png(&data[..]).unwrap()
+            } else {
+                ImageTexture::from_ppm(&data).unwrap()
+            };
+            assert_eq!((image.width, image.height), (3, 2));
+            let p = Vec3::new(0., 0., 0.);
+            let col = image.value(0.5, 0.75, &p);
+            assert!((col.x() - 0.5).abs() < 0.01 && (col.y() - 0.2).abs() < 0.01, "{:?}", col);
+            assert_eq!(image.value(0.9, 0.1, &p).e, (1., 1., 1.));
+        }
+    }
+}
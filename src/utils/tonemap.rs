@@ -0,0 +1,228 @@
+use std::str::FromStr;
+
+/// Curve that compresses linear scene radiance into `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// Clips everything above 1.
+    Clamp,
+    /// `c / (1 + c)` per channel.
+    Reinhard,
+    /// Reinhard that maps `white` to 1 instead of infinity.
+    ExtendedReinhard { white: f32 },
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// Fitted AgX base curve, which desaturates bright colors towards white instead of skewing hue.
+    Agx,
+}
+
+impl ToneMap {
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        match *self {
+            ToneMap::Clamp => rgb,
+            ToneMap::Reinhard => rgb.map(|c| c / (1. + c)),
+            ToneMap::ExtendedReinhard { white } => rgb.map(|c| c * (1. + c / (white * white)) / (1. + c)),
+            ToneMap::Aces => aces(rgb),
+            ToneMap::Agx => agx(rgb),
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    /// `extended-reinhard` takes an optional white point, e.g. `extended-reinhard:4`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let arg = parts.next();
+        match (name, arg) {
+            ("clamp", None) => Ok(ToneMap::Clamp),
+            ("reinhard", None) => Ok(ToneMap::Reinhard),
+            ("extended-reinhard", None) => Ok(ToneMap::ExtendedReinhard { white: 4. }),
+            ("extended-reinhard", Some(white)) => {
+                match white.parse::<f32>() {
+                    Ok(white) if white > 0. => Ok(ToneMap::ExtendedReinhard { white }),
+                    _ => Err(format!("invalid white point: {}", white)),
+                }
+            }
+            ("aces", None) => Ok(ToneMap::Aces),
+            ("agx", None) => Ok(ToneMap::Agx),
+            _ => Err(format!("unknown tone map: {}", s)),
+        }
+    }
+}
+
+/// Encoding from display-linear `[0, 1]` to the stored code values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Stores values as they are, e.g. for normal visualizations.
+    Linear,
+    /// The piecewise sRGB OETF from IEC 61966-2-1.
+    Srgb,
+}
+
+impl Encoding {
+    pub fn encode(&self, c: f32) -> f32 {
+        match *self {
+            Encoding::Linear => c,
+            Encoding::Srgb => srgb_oetf(c),
+        }
+    }
+}
+
+pub fn srgb_oetf(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `srgb_oetf`, for decoding 8 bit images.
+pub fn srgb_eotf(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Everything between the linear film and an 8 or 16 bit image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayTransform {
+    /// Scales the input by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    pub encoding: Encoding,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.,
+            tone_map: ToneMap::Clamp,
+            encoding: Encoding::Srgb,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// Maps a linear color to encoded values in `[0, 1]`.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let scale = 2f32.powf(self.exposure);
+        let rgb = rgb.map(|c| if c.is_nan() { 0. } else { c.max(0.) * scale });
+        self.tone_map
+            .apply(rgb)
+            .map(|c| self.encoding.encode(c.clamp(0., 1.)))
+    }
+}
+
+fn mul(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    let row = |r: &[f32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+    [row(&m[0]), row(&m[1]), row(&m[2])]
+}
+
+fn aces(rgb: [f32; 3]) -> [f32; 3] {
+    // sRGB => AP1 with the RRT's saturation tweak folded in, and back
+    const INPUT: [[f32; 3]; 3] = [[0.59719, 0.35458, 0.04823], [0.07600, 0.90834, 0.01566], [0.02840, 0.13383, 0.83777]];
+    const OUTPUT: [[f32; 3]; 3] = [[1.60475, -0.53108, -0.07367],
+                                   [-0.10208, 1.10813, -0.00605],
+                                   [-0.00327, -0.07276, 1.07602]];
+    let v = mul(&INPUT, rgb).map(|c| {
+                                     let a = c * (c + 0.024_578_6) - 0.000_090_537;
+                                     let b = c * (0.983_729 * c + 0.432_951) + 0.238_081;
+                                     a / b
+                                 });
+    mul(&OUTPUT, v)
+}
+
+fn agx(rgb: [f32; 3]) -> [f32; 3] {
+    const INSET: [[f32; 3]; 3] = [[0.842_479, 0.078_433_6, 0.079_223_7],
+                                  [0.042_328_2, 0.878_469, 0.079_166_1],
+                                  [0.042_375_7, 0.078_433_6, 0.879_143]];
+    const OUTSET: [[f32; 3]; 3] = [[1.196_879, -0.098_020_9, -0.099_029_7],
+                                   [-0.052_896_9, 1.151_903, -0.098_961_2],
+                                   [-0.052_971_6, -0.098_043_5, 1.151_074]];
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+    let v = mul(&INSET, rgb).map(|c| {
+                                     let ev = c.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+                                     let x = (ev - MIN_EV) / (MAX_EV - MIN_EV);
+                                     let x2 = x * x;
+                                     let x4 = x2 * x2;
+                                     15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 +
+                                     0.1191 * x - 0.002_32
+                                 });
+    // the curve produces display-encoded values; undo its 2.2 gamma so the OETF can be applied
+    mul(&OUTSET, v).map(|c| c.max(0.).powf(2.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{srgb_eotf, srgb_oetf, DisplayTransform, Encoding, ToneMap};
+
+    #[test]
+    fn srgb_oetf_matches_reference_points() {
+        assert_eq!(srgb_oetf(0.), 0.);
+        assert!((srgb_oetf(1.) - 1.).abs() < 1e-6);
+        assert!((srgb_oetf(0.002) - 0.02584).abs() < 1e-6);
+        assert!((srgb_oetf(0.18) - 0.461_356).abs() < 1e-4);
+        // both pieces meet at the threshold
+        assert!((srgb_oetf(0.003_130_8) - srgb_oetf(0.003_130_81)).abs() < 1e-6);
+        for i in 0..=20 {
+            let c = i as f32 / 20.;
+            assert!((srgb_eotf(srgb_oetf(c)) - c).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn curves_stay_in_range_and_are_monotonic() {
+        let curves = [ToneMap::Clamp,
+                      ToneMap::Reinhard,
+                      ToneMap::ExtendedReinhard { white: 4. },
+                      ToneMap::Aces,
+                      ToneMap::Agx];
+        for tone_map in curves.iter() {
+            let transform = DisplayTransform {
+                exposure: 0.,
+                tone_map: *tone_map,
+                encoding: Encoding::Srgb,
+            };
+            let mut last = -1.;
+            for i in 0..200 {
+                let v = i as f32 * 0.1;
+                let out = transform.apply([v, v, v]);
+                for c in out.iter() {
+                    assert!(*c >= 0. && *c <= 1., "{:?}({}) = {}", tone_map, v, c);
+                }
+                assert!(out[1] >= last - 1e-4, "{:?} decreases at {}", tone_map, v);
+                last = out[1];
+            }
+        }
+    }
+
+    #[test]
+    fn extended_reinhard_maps_white_to_one() {
+        let out = ToneMap::ExtendedReinhard { white: 4. }.apply([4., 2., 0.]);
+        assert!((out[0] - 1.).abs() < 1e-6);
+        assert!(out[1] < 1.);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let transform = DisplayTransform {
+            exposure: 1.,
+            tone_map: ToneMap::Clamp,
+            encoding: Encoding::Linear,
+        };
+        assert_eq!(transform.apply([0.25, 0.5, 2.]), [0.5, 1., 1.]);
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!("aces".parse(), Ok(ToneMap::Aces));
+        assert_eq!("extended-reinhard:2".parse(), Ok(ToneMap::ExtendedReinhard { white: 2. }));
+        assert!("extended-reinhard:-1".parse::<ToneMap>().is_err());
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+}
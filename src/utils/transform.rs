@@ -0,0 +1,228 @@
+use std::ops::Mul;
+use utils::vec3::Vec3;
+use utils::ray::Ray;
+use utils::aabb::Aabb;
+
+/// Row-major 4x4 matrix.
+pub type Matrix = [[f32; 4]; 4];
+
+pub const IDENTITY: Matrix = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+/// An affine transform, kept together with its inverse so both directions are cheap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub m: Matrix,
+    pub inv: Matrix,
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    /// Wraps an affine matrix (the bottom row is ignored), or returns `None` if it is singular.
+    pub fn from_matrix(m: Matrix) -> Option<Self> {
+        let c = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+        let det = m[0][0] * c(1, 2, 1, 2) - m[0][1] * c(1, 2, 0, 2) + m[0][2] * c(1, 2, 0, 1);
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        // the inverse of the linear part is its adjugate over the determinant
+        let a = [[c(1, 2, 1, 2), -c(0, 2, 1, 2), c(0, 1, 1, 2)],
+                 [-c(1, 2, 0, 2), c(0, 2, 0, 2), -c(0, 1, 0, 2)],
+                 [c(1, 2, 0, 1), -c(0, 2, 0, 1), c(0, 1, 0, 1)]];
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                inv[i][j] = a[i][j] / det;
+            }
+            inv[i][3] = -(0..3).map(|k| inv[i][k] * m[k][3]).sum::<f32>();
+        }
+        let mut m = m;
+        m[3] = IDENTITY[3];
+        Some(Self { m, inv })
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for a in 0..3 {
+            m[a][3] = offset[a];
+            inv[a][3] = -offset[a];
+        }
+        Self { m, inv }
+    }
+
+    /// Scales along each axis; none of the factors may be zero.
+    pub fn scale(factors: &Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for a in 0..3 {
+            m[a][a] = factors[a];
+            inv[a][a] = 1. / factors[a];
+        }
+        Self { m, inv }
+    }
+
+    /// Counter-clockwise rotation by `degrees` about `axis`, seen from the tip of the axis.
+    pub fn rotate(axis: &Vec3, degrees: f32) -> Self {
+        let len = axis.len();
+        let (x, y, z) = (axis.x() / len, axis.y() / len, axis.z() / len);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1. - cos;
+        let m = [[t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.],
+                 [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.],
+                 [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.],
+                 [0., 0., 0., 1.]];
+        // rotations are orthogonal, so the inverse is the transpose
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                inv[i][j] = m[j][i];
+            }
+        }
+        Self { m, inv }
+    }
+
+    pub fn rotate_x(degrees: f32) -> Self {
+        Self::rotate(&Vec3::new(1., 0., 0.), degrees)
+    }
+
+    pub fn rotate_y(degrees: f32) -> Self {
+        Self::rotate(&Vec3::new(0., 1., 0.), degrees)
+    }
+
+    pub fn rotate_z(degrees: f32) -> Self {
+        Self::rotate(&Vec3::new(0., 0., 1.), degrees)
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    /// Determinant of the linear part; negative if the transform mirrors.
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    /// Normals go through the inverse transpose so they stay perpendicular to transformed
+    /// surfaces. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let inv = &self.inv;
+        let col = |j: usize| inv[0][j] * n.x() + inv[1][j] * n.y() + inv[2][j] * n.z();
+        Vec3::new(col(0), col(1), col(2))
+    }
+
+    /// The direction is not normalized, so ray parameters stay the same on both sides.
+    pub fn ray(&self, r: &Ray) -> Ray {
+        Ray::with_time(&self.point(r.origin()), &self.vector(r.direction()), r.time())
+    }
+
+    /// Box around all eight transformed corners of `b`.
+    pub fn bounding_box(&self, b: &Aabb) -> Aabb {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for corner in 0..8 {
+            let pick = |a: usize| if corner & (1 << a) == 0 { b.min[a] } else { b.max[a] };
+            let p = self.point(&Vec3::new(pick(0), pick(1), pick(2)));
+            for a in 0..3 {
+                min[a] = min[a].min(p[a]);
+                max[a] = max[a].max(p[a]);
+            }
+        }
+        Aabb::new(Vec3::new(min[0], min[1], min[2]), Vec3::new(max[0], max[1], max[2]))
+    }
+}
+
+/// `a * b` applies `b` first, then `a`.
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform {
+            m: mul(&self.m, &other.m),
+            inv: mul(&other.inv, &self.inv),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Transform, IDENTITY};
+    use utils::aabb::Aabb;
+    use utils::vec3::{dot, Vec3};
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a.clone() - b.clone()).len() < 1e-5
+    }
+
+    #[test]
+    fn composes_and_inverts() {
+        let t = Transform::translate(&Vec3::new(1., 2., 3.)) * Transform::rotate_z(90.) *
+                Transform::scale(&Vec3::new(2., 2., 2.));
+        let p = Vec3::new(1., 0., 0.);
+        assert!(close(&t.point(&p), &Vec3::new(1., 4., 3.)), "{:?}", t.point(&p));
+        assert!(close(&t.inverse().point(&t.point(&p)), &p));
+        assert!(close(&t.vector(&p), &Vec3::new(0., 2., 0.)));
+
+        let general = Transform::from_matrix(t.m).unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((general.inv[i][j] - t.inv[i][j]).abs() < 1e-5, "{:?} vs {:?}", general.inv, t.inv);
+            }
+        }
+        let mut flat = IDENTITY;
+        flat[1][1] = 0.;
+        assert!(Transform::from_matrix(flat).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::rotate(&Vec3::new(1., 1., 0.), 30.) * Transform::scale(&Vec3::new(1., 4., 0.5));
+        let (tangent, normal) = (Vec3::new(1., -1., 2.), Vec3::new(1., 1., 0.));
+        assert!(dot(&tangent, &normal).abs() < 1e-6);
+        assert!(dot(&t.vector(&tangent), &t.normal(&normal)).abs() < 1e-5);
+        assert!(Transform::scale(&Vec3::new(-1., 1., 1.)).determinant() < 0.);
+    }
+
+    #[test]
+    fn rotated_boxes_are_bounded() {
+        let b = Aabb::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        let rotated = Transform::rotate_y(45.).bounding_box(&b);
+        let r = 2f32.sqrt();
+        assert!(close(&rotated.min, &Vec3::new(-r, -1., -r)) && close(&rotated.max, &Vec3::new(r, 1., r)),
+                "{:?}",
+                rotated);
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use utils::hitable::{Hitable, HitRecord};
+use utils::aabb::Aabb;
+use utils::ray::Ray;
+use utils::random::Sampler;
+use utils::vec3::{cross, dot, unit_vector, Vec3};
+use utils::material::Material;
+
+/// Möller–Trumbore ray/triangle test. Returns `(t, b1, b2)` where `b1` and `b2` are the
+/// barycentric weights of `p1` and `p2`.
+pub fn intersect(r: &Ray, p0: &Vec3, p1: &Vec3, p2: &Vec3, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+    let e1 = p1.clone() - p0.clone();
+    let e2 = p2.clone() - p0.clone();
+    let pvec = cross(r.direction(), &e2);
+    let det = dot(&e1, &pvec);
+    // relative to the edge lengths so that tiny triangles are not rejected
+    if det.abs() < 1e-9 * dot(&e1, &e1).max(dot(&e2, &e2)) {
+        return None;
+    }
+    let inv_det = 1. / det;
+    let tvec = r.origin().clone() - p0.clone();
+    let b1 = dot(&tvec, &pvec) * inv_det;
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+    let qvec = cross(&tvec, &e1);
+    let b2 = dot(r.direction(), &qvec) * inv_det;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+    let t = dot(&e2, &qvec) * inv_det;
+    if t > t_min && t < t_max {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+/// Bounds of three points, padded along axes the triangle is flat in.
+pub fn triangle_box(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> Aabb {
+    let mut min = [0.; 3];
+    let mut max = [0.; 3];
+    for a in 0..3 {
+        min[a] = p0[a].min(p1[a]).min(p2[a]);
+        max[a] = p0[a].max(p1[a]).max(p2[a]);
+        if max[a] - min[a] < 1e-4 {
+            min[a] -= 1e-4;
+            max[a] += 1e-4;
+        }
+    }
+    Aabb::new(Vec3::new(min[0], min[1], min[2]), Vec3::new(max[0], max[1], max[2]))
+}
+
+/// A single flat triangle facing `(v1 - v0) × (v2 - v0)`, i.e. counter-clockwise vertices face the
+/// viewer. `(u, v)` on a hit are the barycentric weights of `v1` and `v2`.
+#[allow(dead_code)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    normal: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+#[allow(dead_code)]
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, m: Arc<dyn Material>) -> Self {
+        let normal = unit_vector(cross(&(v1.clone() - v0.clone()), &(v2.clone() - v0.clone())));
+        Self {
+            v0,
+            v1,
+            v2,
+            normal,
+            mat: m,
+        }
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord, _sampler: &mut Sampler) -> bool {
+        match intersect(r, &self.v0, &self.v1, &self.v2, t_min, t_max) {
+            Some((t, b1, b2)) => {
+                rec.t = t;
+                rec.u = b1;
+                rec.v = b2;
+                rec.p = r.point_at_parameter(t);
+                rec.normal = self.normal.clone();
+                rec.mat = self.mat.clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32, output_box: &mut Aabb) -> bool {
+        *output_box = triangle_box(&self.v0, &self.v1, &self.v2);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Triangle;
+    use utils::hitable::{Hitable, HitRecord};
+    use utils::material::dummy;
+    use utils::ray::Ray;
+    use utils::vec3::Vec3;
+    use utils::random::Sampler;
+
+    fn tri() -> Triangle {
+        Triangle::new(Vec3::new(0., 0., -1.), Vec3::new(1., 0., -1.), Vec3::new(0., 1., -1.), dummy())
+    }
+
+    #[test]
+    fn hits_inside_and_reports_barycentrics() {
+        let mut sampler = Sampler::new(0);
+        let mut rec = HitRecord::new(dummy());
+        let r = Ray::new(&Vec3::new(0.25, 0.5, 0.), &Vec3::new(0., 0., -1.));
+        assert!(tri().hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler));
+        assert!((rec.t - 1.).abs() < 1e-6);
+        assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.5).abs() < 1e-6);
+        assert_eq!(rec.normal.e, (0., 0., 1.));
+        // hit from behind as well
+        let back = Ray::new(&Vec3::new(0.25, 0.5, -2.), &Vec3::new(0., 0., 1.));
+        assert!(tri().hit(&back, 0.001, f32::MAX, &mut rec, &mut sampler));
+    }
+
+    #[test]
+    fn misses_outside_parallel_and_out_of_range() {
+        let mut sampler = Sampler::new(0);
+        let mut rec = HitRecord::new(dummy());
+        let outside = Ray::new(&Vec3::new(0.6, 0.6, 0.), &Vec3::new(0., 0., -1.));
+        assert!(!tri().hit(&outside, 0.001, f32::MAX, &mut rec, &mut sampler));
+        let parallel = Ray::new(&Vec3::new(-1., 0.2, -1.), &Vec3::new(1., 0., 0.));
+        assert!(!tri().hit(&parallel, 0.001, f32::MAX, &mut rec, &mut sampler));
+        let short = Ray::new(&Vec3::new(0.2, 0.2, 0.), &Vec3::new(0., 0., -1.));
+        assert!(!tri().hit(&short, 0.001, 0.5, &mut rec, &mut sampler));
+    }
+
+    #[test]
+    fn shared_edges_are_watertight() {
+        let mut sampler = Sampler::new(0);
+        // two triangles of a square, shot at along their shared diagonal
+        let a = Triangle::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(1., 1., 0.), dummy());
+        let b = Triangle::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 0.), Vec3::new(0., 1., 0.), dummy());
+        for i in 1..100 {
+            let s = i as f32 / 100.;
+            let r = Ray::new(&Vec3::new(s, s, 1.), &Vec3::new(0., 0., -1.));
+            let mut rec = HitRecord::new(dummy());
+            let hit = a.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler) ||
+                      b.hit(&r, 0.001, f32::MAX, &mut rec, &mut sampler);
+            assert!(hit, "{}", s);
+        }
+    }
+}
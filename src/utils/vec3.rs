@@ -1,6 +1,6 @@
-use std::ops::{Add, Div, Mul, Sub};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, PartialEq)]
 pub struct Vec3 {
     pub e: (f32, f32, f32),
 }
@@ -46,6 +46,14 @@ impl Sub for Vec3 {
     }
 }
 
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.e.0, -self.e.1, -self.e.2)
+    }
+}
+
 impl Mul<Vec3> for Vec3 {
     type Output = Vec3;
 
@@ -78,6 +86,19 @@ impl Div<f32> for Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.e.0,
+            1 => &self.e.1,
+            2 => &self.e.2,
+            _ => panic!("Vec3 index out of range: {}", i),
+        }
+    }
+}
+
 pub fn unit_vector(v: Vec3) -> Vec3 {
     let l = v.len();
     v / l
@@ -90,6 +111,6 @@ pub fn dot(v1: &Vec3, v2: &Vec3) -> f32 {
 #[allow(dead_code)]
 pub fn cross(v1: &Vec3, v2: &Vec3) -> Vec3 {
     Vec3::new(v1.e.1 * v2.e.2 - v1.e.2 * v2.e.1,
-              (v1.e.0 * v2.e.2 - v1.e.2 * v2.e.0) * -1.,
+              -(v1.e.0 * v2.e.2 - v1.e.2 * v2.e.0),
               v1.e.0 * v2.e.1 - v1.e.1 * v2.e.0)
 }
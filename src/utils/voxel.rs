@@ -0,0 +1,409 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use utils::vec3::Vec3;
+use utils::aabb::Aabb;
+
+/// A dense grid of voxels spanning `bounds`, stored with x varying fastest, then y, then z.
+/// Density is always present; albedo and emission are optional per-voxel colors.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    pub dims: [usize; 3],
+    pub bounds: Aabb,
+    pub density: Vec<f32>,
+    pub albedo: Option<Vec<Vec3>>,
+    pub emission: Option<Vec<Vec3>>,
+}
+
+/// Voxels a sparse grid may span. Dense grids are bounded by the size of their file, but the
+/// blocks of a sparse one can leave most of it empty, so the header alone would set the size.
+pub const MAX_SPARSE_VOXELS: usize = 1 << 28;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[allow(dead_code)]
+impl VoxelGrid {
+    pub fn new(dims: [usize; 3], bounds: Aabb, density: Vec<f32>) -> Self {
+        assert_eq!(density.len(), dims[0] * dims[1] * dims[2]);
+        Self {
+            dims,
+            bounds,
+            density,
+            albedo: None,
+            emission: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.density.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.density.is_empty()
+    }
+
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.dims[1] + y) * self.dims[0] + x
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.density.iter().cloned().fold(0., f32::max)
+    }
+
+    /// The eight voxels around `p` with their trilinear weights. Voxel values sit at voxel centers
+    /// and are held constant out to the faces of `bounds`.
+    fn corners(&self, p: &Vec3) -> [(usize, f32); 8] {
+        let mut lo = [0; 3];
+        let mut hi = [0; 3];
+        let mut frac = [0.; 3];
+        for a in 0..3 {
+            let n = self.dims[a];
+            let extent = self.bounds.max[a] - self.bounds.min[a];
+            let x = ((p[a] - self.bounds.min[a]) / extent * n as f32 - 0.5).max(0.).min((n - 1) as f32);
+            lo[a] = x as usize;
+            hi[a] = (lo[a] + 1).min(n - 1);
+            frac[a] = x - lo[a] as f32;
+        }
+        let mut out = [(0, 0.); 8];
+        for (corner, o) in out.iter_mut().enumerate() {
+            let pick = |a: usize| if corner & (1 << a) == 0 { (lo[a], 1. - frac[a]) } else { (hi[a], frac[a]) };
+            let ((x, wx), (y, wy), (z, wz)) = (pick(0), pick(1), pick(2));
+            *o = (self.index(x, y, z), wx * wy * wz);
+        }
+        out
+    }
+
+    /// Trilinearly interpolated density at `p`, which should lie inside `bounds`.
+    pub fn density_at(&self, p: &Vec3) -> f32 {
+        self.corners(p).iter().map(|&(i, w)| self.density[i] * w).sum()
+    }
+
+    fn color_at(&self, channel: &[Vec3], p: &Vec3) -> Vec3 {
+        self.corners(p).iter().fold(Vec3::new(0., 0., 0.), |sum, &(i, w)| sum + channel[i].clone() * w)
+    }
+
+    /// Interpolated per-voxel albedo, or `None` if the grid does not store one.
+    pub fn albedo_at(&self, p: &Vec3) -> Option<Vec3> {
+        self.albedo.as_ref().map(|c| self.color_at(c, p))
+    }
+
+    pub fn emission_at(&self, p: &Vec3) -> Option<Vec3> {
+        self.emission.as_ref().map(|c| self.color_at(c, p))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Channel {
+    Density,
+    Albedo,
+    Emission,
+}
+
+/// Little-endian reader over the binary part of a grid file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize, what: &str) -> io::Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(invalid(format!("{}: unexpected end of file", what)));
+        }
+        let out = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u32(&mut self, what: &str) -> io::Result<u32> {
+        let b = self.take(4, what)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self, what: &str) -> io::Result<f32> {
+        self.u32(what).map(f32::from_bits)
+    }
+}
+
+/// Parses a voxel grid: an ASCII header followed by little-endian `f32` data.
+///
+/// ```text
+/// VOXELS 1
+/// dims 64 32 64
+/// bounds -1 0 -1 1 1 1
+/// channels density albedo emission
+/// blocks 8
+/// end
+/// ```
+///
+/// `channels` lists the stored channels in data order and must include `density`; albedo and
+/// emission take three floats per voxel. Without `blocks` each channel follows as a dense array.
+/// With `blocks B` the data is sparse: a `u32` block count, then per block its `u32` x, y and z
+/// block coordinates and every channel for its B³ voxels. Voxels in no block are empty.
+pub fn parse_grid(bytes: &[u8]) -> io::Result<VoxelGrid> {
+    // the header is everything up to and including the `end` line
+    let mut dims = None;
+    let mut bounds = None;
+    let mut channels = vec![];
+    let mut block = None;
+    let mut pos = 0;
+    let mut line_no = 0;
+    loop {
+        let len = bytes[pos..].iter()
+                              .position(|&b| b == b'\n')
+                              .ok_or_else(|| invalid("header: missing `end` line".to_string()))?;
+        let line = str::from_utf8(&bytes[pos..pos + len]).map_err(|_| {
+                                                             invalid(format!("line {}: header is not text", line_no + 1))
+                                                         })?;
+        pos += len + 1;
+        line_no += 1;
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers = |count: usize| -> io::Result<Vec<f32>> {
+            if words.len() != count + 1 {
+                return Err(invalid(format!("line {}: expected {} numbers", line_no, count)));
+            }
+            words[1..].iter()
+                      .map(|w| w.parse().map_err(|_| invalid(format!("line {}: invalid number `{}`", line_no, w))))
+                      .collect()
+        };
+        match words.first().cloned() {
+            None => continue,
+            Some("VOXELS") if line_no == 1 => {
+                if words.get(1) != Some(&"1") {
+                    return Err(invalid(format!("line 1: unsupported version {:?}", words.get(1))));
+                }
+            }
+            _ if line_no == 1 => return Err(invalid("not a voxel grid, expected `VOXELS 1`".to_string())),
+            Some("dims") => {
+                let n = numbers(3)?;
+                if n.iter().any(|&d| d < 1. || d.fract() != 0.) {
+                    return Err(invalid(format!("line {}: dims must be positive integers", line_no)));
+                }
+                dims = Some([n[0] as usize, n[1] as usize, n[2] as usize]);
+            }
+            Some("bounds") => {
+                let n = numbers(6)?;
+                if n.iter().any(|v| !v.is_finite()) || (0..3).any(|a| n[a] >= n[a + 3]) {
+                    return Err(invalid(format!("line {}: bounds must have min below max", line_no)));
+                }
+                bounds = Some(Aabb::new(Vec3::new(n[0], n[1], n[2]), Vec3::new(n[3], n[4], n[5])));
+            }
+            Some("channels") => {
+                for w in &words[1..] {
+                    let c = match *w {
+                        "density" => Channel::Density,
+                        "albedo" => Channel::Albedo,
+                        "emission" => Channel::Emission,
+                        _ => return Err(invalid(format!("line {}: unknown channel `{}`", line_no, w))),
+                    };
+                    if channels.contains(&c) {
+                        return Err(invalid(format!("line {}: channel `{}` listed twice", line_no, w)));
+                    }
+                    channels.push(c);
+                }
+            }
+            Some("blocks") => {
+                let n = numbers(1)?;
+                if n[0] < 1. || n[0].fract() != 0. {
+                    return Err(invalid(format!("line {}: block size must be a positive integer", line_no)));
+                }
+                block = Some(n[0] as usize);
+            }
+            Some("end") => break,
+            Some(w) => return Err(invalid(format!("line {}: unknown header field `{}`", line_no, w))),
+        }
+    }
+    let dims = dims.ok_or_else(|| invalid("header: missing `dims`".to_string()))?;
+    let bounds = bounds.ok_or_else(|| invalid("header: missing `bounds`".to_string()))?;
+    if !channels.contains(&Channel::Density) {
+        return Err(invalid("header: `channels` must include density".to_string()));
+    }
+
+    // bytes each voxel takes up in the file, and in memory once loaded
+    let voxel_bytes = channels.iter().map(|&c| if c == Channel::Density { 4 } else { 12 }).sum::<usize>();
+    let n = dims[0].checked_mul(dims[1]).and_then(|n| n.checked_mul(dims[2]));
+    let (n, size) = match n.and_then(|n| n.checked_mul(voxel_bytes).map(|size| (n, size))) {
+        Some((n, size)) if size <= isize::MAX as usize => (n, size),
+        _ => return Err(invalid("header: too many voxels".to_string())),
+    };
+    if block.is_some() && n > MAX_SPARSE_VOXELS {
+        return Err(invalid(format!("header: {} voxels, a sparse grid may have at most {}", n, MAX_SPARSE_VOXELS)));
+    }
+    let rest = bytes.len() - pos;
+    if block.is_none() && size > rest {
+        return Err(invalid(format!("voxel data: unexpected end of file, {} voxels do not fit in {} bytes", n, rest)));
+    }
+    let mut grid = VoxelGrid::new(dims, bounds, vec![0.; n]);
+    let black = Vec3::new(0., 0., 0.);
+    if channels.contains(&Channel::Albedo) {
+        grid.albedo = Some(vec![black.clone(); n]);
+    }
+    if channels.contains(&Channel::Emission) {
+        grid.emission = Some(vec![black; n]);
+    }
+    let mut r = Reader { bytes, pos };
+    // reads one channel of one voxel; `index` is None for block voxels that fall outside the grid
+    let read_voxel = |r: &mut Reader, grid: &mut VoxelGrid, c: Channel, index: Option<usize>, what: &str| {
+        match c {
+            Channel::Density => {
+                let d = r.f32(what)?;
+                if d < 0. || !d.is_finite() {
+                    return Err(invalid(format!("{}: density {} is not a finite non-negative number", what, d)));
+                }
+                if let Some(i) = index {
+                    grid.density[i] = d;
+                }
+            }
+            Channel::Albedo | Channel::Emission => {
+                let v = Vec3::new(r.f32(what)?, r.f32(what)?, r.f32(what)?);
+                let channel = if c == Channel::Albedo { &mut grid.albedo } else { &mut grid.emission };
+                if let (Some(i), Some(channel)) = (index, channel.as_mut()) {
+                    channel[i] = v;
+                }
+            }
+        }
+        Ok(())
+    };
+    match block {
+        None => {
+            for &c in &channels {
+                for i in 0..n {
+                    read_voxel(&mut r, &mut grid, c, Some(i), "voxel data")?;
+                }
+            }
+        }
+        Some(b) => {
+            let count = r.u32("block count")?;
+            // each block is its origin followed by its voxels
+            let block_bytes = b.checked_mul(b)
+                               .and_then(|v| v.checked_mul(b))
+                               .and_then(|v| v.checked_mul(voxel_bytes))
+                               .and_then(|v| v.checked_add(12));
+            let rest = bytes.len() - r.pos;
+            if block_bytes.and_then(|size| size.checked_mul(count as usize)).is_none_or(|need| need > rest) {
+                return Err(invalid(format!("block data: unexpected end of file, {} blocks do not fit in {} bytes",
+                                           count,
+                                           rest)));
+            }
+            for k in 0..count {
+                let what = format!("block {} of {}", k, count);
+                let mut origin = [0; 3];
+                for a in 0..3 {
+                    origin[a] = match (r.u32(&what)? as usize).checked_mul(b) {
+                        Some(o) if o < dims[a] => o,
+                        _ => return Err(invalid(format!("{}: block lies outside the grid", what))),
+                    };
+                }
+                for &c in &channels {
+                    for v in 0..b * b * b {
+                        let (x, y, z) = (origin[0] + v % b, origin[1] + v / b % b, origin[2] + v / (b * b));
+                        let inside = x < dims[0] && y < dims[1] && z < dims[2];
+                        let index = if inside { Some(grid.index(x, y, z)) } else { None };
+                        read_voxel(&mut r, &mut grid, c, index, &what)?;
+                    }
+                }
+            }
+        }
+    }
+    if r.pos != bytes.len() {
+        return Err(invalid(format!("{} bytes of trailing data", bytes.len() - r.pos)));
+    }
+    Ok(grid)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<VoxelGrid> {
+    parse_grid(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_grid;
+    use utils::vec3::Vec3;
+
+    fn grid_file(header: &str, data: &[f32]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        for v in data {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn dense_and_sparse_grids_agree() {
+        let header = "VOXELS 1\ndims 3 2 1\nbounds 0 0 0 3 2 1\nchannels density albedo\nend\n";
+        let mut data = vec![0., 1., 2., 3., 4., 5.];
+        for i in 0..6 {
+            data.extend_from_slice(&[i as f32 / 10., 0.5, 1.]);
+        }
+        let dense = parse_grid(&grid_file(header, &data)).unwrap();
+        assert_eq!(dense.density[dense.index(2, 1, 0)], 5.);
+        assert_eq!(dense.albedo.as_ref().unwrap()[4], Vec3::new(0.4, 0.5, 1.));
+
+        // two 2³ blocks cover x 0..4 and y 0..2; voxels past the grid are read and dropped
+        let sparse_header = header.replace("end\n", "blocks 2\nend\n");
+        let mut sparse = to_u32_bits(&[2.]);
+        for bx in 0..2 {
+            sparse.extend(to_u32_bits(&[bx as f32, 0., 0.]));
+            let voxels: Vec<(usize, usize, usize)> = (0..8).map(|v| (2 * bx + v % 2, v / 2 % 2, v / 4)).collect();
+            let value = |x: usize, y: usize, z: usize| if x < 3 && z == 0 { (y * 3 + x) as f32 } else { 0. };
+            let densities: Vec<f32> = voxels.iter().map(|&(x, y, z)| value(x, y, z)).collect();
+            sparse.extend(densities.iter().cloned());
+            for &(x, y, z) in &voxels {
+                sparse.extend_from_slice(&[value(x, y, z) / 10., 0.5, 1.]);
+            }
+        }
+        let sparse = parse_grid(&grid_file(&sparse_header, &sparse)).unwrap();
+        assert_eq!(sparse.density, dense.density);
+        for (a, b) in sparse.albedo.unwrap().iter().zip(dense.albedo.unwrap().iter()) {
+            assert!((a.clone() - b.clone()).len() < 1e-6);
+        }
+    }
+
+    /// Block headers are `u32`s; store them as floats with the same bits so one buffer holds both.
+    fn to_u32_bits(values: &[f32]) -> Vec<f32> {
+        values.iter().map(|&v| f32::from_bits(v as u32)).collect()
+    }
+
+    #[test]
+    fn interpolates_between_voxel_centers() {
+        let header = "VOXELS 1\ndims 2 1 1\nbounds 0 0 0 1 1 1\nchannels density\nend\n";
+        let grid = parse_grid(&grid_file(header, &[0., 2.])).unwrap();
+        let at = |x: f32| grid.density_at(&Vec3::new(x, 0.5, 0.5));
+        assert_eq!(at(0.1), 0.);
+        assert!((at(0.5) - 1.).abs() < 1e-6);
+        assert!((at(0.625) - 1.5).abs() < 1e-6);
+        assert_eq!(at(0.9), 2.);
+        assert_eq!(grid.max_density(), 2.);
+    }
+
+    #[test]
+    fn malformed_grids_are_rejected() {
+        let header = "VOXELS 1\ndims 2 1 1\nbounds 0 0 0 1 1 1\nchannels density\nend\n";
+        let msg = |bytes: Vec<u8>| parse_grid(&bytes).err().unwrap().to_string();
+        assert!(msg(grid_file(header, &[0.])).contains("unexpected end of file"));
+        assert!(msg(grid_file(header, &[0., 1., 2.])).contains("trailing"));
+        assert!(msg(grid_file(header, &[0., -1.])).contains("non-negative"));
+        assert!(msg(grid_file(&header.replace("density\n", "density smoke\n"), &[])).contains("line 4"));
+        assert!(msg(grid_file(&header.replace("dims 2 1 1\n", ""), &[])).contains("dims"));
+        assert!(msg(grid_file("PLY\n", &[])).contains("VOXELS"));
+
+        // sizes are checked against the file before anything is allocated
+        let huge = header.replace("dims 2 1 1", "dims 4294967296 4294967296 4294967296");
+        assert!(msg(grid_file(&huge, &[])).contains("too many voxels"));
+        let big = header.replace("dims 2 1 1", "dims 1000 1000 1000");
+        assert!(msg(grid_file(&big, &[0., 1.])).contains("unexpected end of file"));
+        let sparse = header.replace("dims 2 1 1", "dims 100000 100000 100000").replace("end\n", "blocks 8\nend\n");
+        assert!(msg(grid_file(&sparse, &to_u32_bits(&[0.]))).contains("a sparse grid may have at most"));
+        let blocks = header.replace("end\n", "blocks 65536\nend\n");
+        assert!(msg(grid_file(&blocks, &to_u32_bits(&[1., 0., 0., 0.]))).contains("unexpected end of file"));
+        let far = header.replace("end\n", "blocks 1\nend\n");
+        let mut data = to_u32_bits(&[1., 4294967295., 0., 0.]);
+        data.push(1.);
+        assert!(msg(grid_file(&far, &data)).contains("outside the grid"));
+    }
+}